@@ -10,9 +10,11 @@ mod services;
 mod routes;
 mod router;
 
-use ailoom_fs::FsConfig;
+use ailoom_fs::{FileIndex, FsConfig};
+use ailoom_git::GitRepo;
 use ailoom_store::Store;
-use paths::{discover_workspace_root, normalize_path_for_key};
+use paths::{discover_workspace_root, normalize_path_for_key, workspace_identity};
+use services::events::EventBus;
 use state::AppState;
 
 #[derive(Debug, Parser)]
@@ -32,14 +34,25 @@ struct Args {
   /// Port to bind (default: random free port)
   #[arg(long)]
   port: Option<u16>,
+  /// Disable the filesystem watcher (external edits won't trigger annotation verification)
+  #[arg(long, default_value_t = false)]
+  no_watch: bool,
+  /// Run as MCP server over stdio instead of HTTP (logs go to stderr)
+  #[arg(long, default_value_t = false)]
+  mcp: bool,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+  let args = Args::parse();
   let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into());
-  fmt().with_env_filter(filter).init();
+  // stdio 模式下 stdout 专用于 JSON-RPC，日志改写到 stderr
+  if args.mcp {
+    fmt().with_env_filter(filter).with_writer(std::io::stderr).init();
+  } else {
+    fmt().with_env_filter(filter).init();
+  }
 
-  let args = Args::parse();
   // 版本与构建信息（由 build.rs 注入；main=0.0.0 时可通过 tag/sha 辅助定位）
   let app_version = env!("APP_VERSION");
   let app_git_tag = env!("APP_GIT_TAG");
@@ -51,6 +64,8 @@ async fn main() -> anyhow::Result<()> {
   // Discover workspace root (git repo root if found by walking up to first `.git` dir)
   let workspace_root = discover_workspace_root(&root).unwrap_or_else(|| root.clone());
   let workspace_key = normalize_path_for_key(&workspace_root);
+  let git = GitRepo::open(&workspace_root);
+  let identity = workspace_identity(&workspace_root, git.as_ref());
   let fs_cfg = FsConfig::new(root.clone());
 
   // Prepare DB path
@@ -61,18 +76,38 @@ async fn main() -> anyhow::Result<()> {
     home.join("ailoom").join("ailoom.db")
   };
   if let Some(dir) = db_path.parent() { std::fs::create_dir_all(dir).ok(); }
-  let store = match Store::connect_path(&db_path, &workspace_key).await {
+  let store = match Store::connect_path(&db_path, &workspace_key, identity.as_deref()).await {
     Ok(s) => s,
     Err(e) => {
       // fallback to project root .ailoom/ailoom.db
       let fallback = root.join(".ailoom").join("ailoom.db");
       if let Some(dir) = fallback.parent() { std::fs::create_dir_all(dir).ok(); }
       tracing::warn!("failed to open db at {}, fallback to {}: {}", db_path.display(), fallback.display(), e);
-      Store::connect_path(&fallback, &workspace_key).await?
+      Store::connect_path(&fallback, &workspace_key, identity.as_deref()).await?
     }
   };
 
-  let app_state = AppState { fs: fs_cfg.clone(), store, root: root.clone(), workspace_root: workspace_root.clone() };
+  let app_state = AppState { fs: fs_cfg.clone(), store, root: root.clone(), workspace_root: workspace_root.clone(), events: EventBus::new(), files: FileIndex::default(), git, own_writes: Default::default() };
+  // 监听先于索引构建启动，构建期间的增量刷新由索引在换入后重放
+  let watching = !args.no_watch && match services::watcher::spawn_watcher(app_state.clone()) {
+    Ok(()) => true,
+    Err(e) => { tracing::warn!("watcher disabled: {}", e); false }
+  };
+  if watching {
+    // 文件索引后台构建，完成前查找结果可能不完整
+    let (files, fs) = (app_state.files.clone(), app_state.fs.clone());
+    tokio::task::spawn_blocking(move || {
+      files.rebuild(&fs);
+      tracing::info!("file index: {} files", files.len());
+    });
+  } else {
+    // 无监听维护时，快速打开在索引超过 5s 后按需重建（首次查找时构建，并发查找只遍历一次）
+    app_state.files.rebuild_on_demand(std::time::Duration::from_secs(5));
+  }
+  if args.mcp {
+    tracing::info!("mcp: serving over stdio");
+    return services::mcp::run_stdio(app_state).await;
+  }
   let app = router::build_router(app_state, args.web_dist.clone(), args.no_static);
 
   let bind_addr: SocketAddr = match args.port { Some(p) => SocketAddr::from(([127, 0, 0, 1], p)), None => SocketAddr::from(([127, 0, 0, 1], 0)), };
@@ -16,6 +16,19 @@ pub fn discover_workspace_root(start: &StdPath) -> Option<PathBuf> {
   }
 }
 
+/// 显式指定工作区标识的文件（相对 workspace 根）；可随仓库提交，多处检出共享同一标识
+pub const WORKSPACE_ID_FILE: &str = ".ailoom/workspace-id";
+
+/// 与检出路径无关的工作区标识：优先 `.ailoom/workspace-id`（首行），否则取 git remote + 根提交；都没有时为 `None`
+pub fn workspace_identity(workspace_root: &StdPath, git: Option<&ailoom_git::GitRepo>) -> Option<String> {
+  if let Ok(s) = std::fs::read_to_string(workspace_root.join(WORKSPACE_ID_FILE)) {
+    if let Some(id) = s.lines().next().map(str::trim).filter(|l| !l.is_empty()) {
+      return Some(format!("id:{}", id));
+    }
+  }
+  git.and_then(|g| g.identity())
+}
+
 pub fn normalize_path_for_key(p: &StdPath) -> String {
   #[cfg(target_os = "macos")]
   {
@@ -66,22 +79,20 @@ pub fn from_workspace_to_root(state: &AppState, ws_rel: &str) -> String {
   ws_rel.to_string()
 }
 
+/// 当前 root 相对 workspace 根的前缀（root 即 workspace 根时为空串）
+pub fn root_prefix(state: &AppState) -> String {
+  state.root.strip_prefix(&state.workspace_root).map(|p| p.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+/// workspace 相对路径是否位于当前 root 子树内
+pub fn in_root_scope(state: &AppState, ws_rel: &str) -> bool {
+  let prefix_str = root_prefix(state);
+  prefix_str.is_empty() || ws_rel == prefix_str || ws_rel.starts_with(&(prefix_str + "/"))
+}
+
 pub fn map_and_filter_annotations(state: &AppState, v: Vec<Annotation>) -> Vec<Annotation> {
-  let scope_prefix = state.root.strip_prefix(&state.workspace_root).ok();
-  let prefix_str = scope_prefix
-    .map(|p| p.to_string_lossy().to_string())
-    .unwrap_or_default();
-  let want_all = prefix_str.is_empty();
   v.into_iter()
-    .filter(|a| {
-      if want_all {
-        true
-      } else if a.file_path == prefix_str {
-        true
-      } else {
-        a.file_path.starts_with(&(prefix_str.clone() + "/"))
-      }
-    })
+    .filter(|a| in_root_scope(state, &a.file_path))
     .map(|mut a| {
       a.file_path = from_workspace_to_root(state, &a.file_path);
       a
@@ -1,10 +1,17 @@
 use crate::{
   routes::{
-    annotations::{create_annotation, delete_annotation, export_annotations, import_annotations, list_annotations, update_annotation},
+    annotations::{annotation_history, NEXT_CURSOR_HEADER, create_annotation, delete_annotation, export_annotations, import_annotations, list_annotations, reattach_annotation, reopen_annotation, resolve_annotation, restore_revision, search_annotations, update_annotation},
+    comments::{create_comment, delete_comment, list_comments, update_comment},
+    events::api_events,
     files::{api_file, api_file_full, api_file_put},
-    stitch::stitch_endpoint,
+    find::api_files_find,
+    git::{api_git_diff, api_git_uncommitted},
+    mcp::{mcp_get, mcp_post},
+    search::api_search,
+    stitch::{create_template, delete_template, get_template, list_templates, stitch_endpoint, update_template},
     tree::api_tree,
     verify::verify_annotations_endpoint,
+    workspaces::{list_workspaces, prune_workspaces, transfer_annotations},
   },
   state::AppState,
 };
@@ -12,22 +19,41 @@ use axum::{routing::get, Router};
 use tower_http::{cors::{Any, CorsLayer}, services::ServeDir, trace::TraceLayer};
 
 pub fn build_router(state: AppState, web_dist: std::path::PathBuf, no_static: bool) -> Router {
-  let fs_cfg_tree = state.fs.clone();
   let fs_cfg_file = state.fs.clone();
   let fs_cfg_full = state.fs.clone();
+  let fs_cfg_search = state.fs.clone();
   let api = Router::new()
-    .route("/api/tree", get(move |q| api_tree(q, fs_cfg_tree.clone())))
+    .route("/api/tree", get(api_tree))
     .route("/api/file", get(move |q| api_file(q, fs_cfg_file.clone())))
     .route("/api/file/full", get(move |q| api_file_full(q, fs_cfg_full.clone())))
     .route("/api/file", axum::routing::put(api_file_put))
+    .route("/api/files/find", get(api_files_find))
+    .route("/api/git/uncommitted", get(api_git_uncommitted))
+    .route("/api/git/diff", get(api_git_diff))
+    .route("/api/search", get(move |q| api_search(q, fs_cfg_search.clone())))
     .route("/api/annotations", get(list_annotations).post(create_annotation))
     .route("/api/annotations/:id", axum::routing::put(update_annotation).delete(delete_annotation))
+    .route("/api/annotations/:id/reattach", axum::routing::post(reattach_annotation))
+    .route("/api/annotations/:id/resolve", axum::routing::post(resolve_annotation))
+    .route("/api/annotations/:id/reopen", axum::routing::post(reopen_annotation))
+    .route("/api/annotations/search", get(search_annotations))
+    .route("/api/annotations/:id/history", get(annotation_history))
+    .route("/api/annotations/:id/history/:revision_id/restore", axum::routing::post(restore_revision))
+    .route("/api/annotations/:id/comments", get(list_comments).post(create_comment))
+    .route("/api/annotations/:id/comments/:comment_id", axum::routing::put(update_comment).delete(delete_comment))
     .route("/api/annotations/import", axum::routing::post(import_annotations))
     .route("/api/annotations/export", get(export_annotations))
     .route("/api/stitch", axum::routing::post(stitch_endpoint))
+    .route("/api/stitch/templates", get(list_templates).post(create_template))
+    .route("/api/stitch/templates/:name", get(get_template).put(update_template).delete(delete_template))
     .route("/api/annotations/verify", axum::routing::post(verify_annotations_endpoint))
+    .route("/api/workspaces", get(list_workspaces))
+    .route("/api/workspaces/transfer", axum::routing::post(transfer_annotations))
+    .route("/api/workspaces/prune", axum::routing::post(prune_workspaces))
+    .route("/api/events", get(api_events))
+    .route("/mcp", get(mcp_get).post(mcp_post))
     .with_state(state)
-    .layer(CorsLayer::new().allow_origin(Any).allow_methods(Any).allow_headers(Any));
+    .layer(CorsLayer::new().allow_origin(Any).allow_methods(Any).allow_headers(Any).expose_headers([axum::http::HeaderName::from_static(NEXT_CURSOR_HEADER)]));
 
   if no_static {
     Router::new().merge(api).layer(TraceLayer::new_for_http())
@@ -1,59 +1,184 @@
 use crate::{
-  paths::{from_workspace_to_root, map_and_filter_annotations, to_workspace_relative},
+  paths::{from_workspace_to_root, map_and_filter_annotations, root_prefix, to_workspace_relative},
+  services::{self, events::ServerEvent},
   state::AppState,
   web::error,
 };
-use ailoom_core::{Annotation, CreateAnnotation, UpdateAnnotation};
-use axum::{extract::Path, http::StatusCode, response::IntoResponse, Json};
+use ailoom_core::{Annotation, AnnotationStatus, CreateAnnotation, ImportStrategy, BUNDLE_SCHEMA_VERSION, ReattachAnnotation, ResolveAnnotation, RevisionKind, UpdateAnnotation};
+use ailoom_store::{AnnotationFilter, AnnotationSort, StoreError};
+use axum::{
+  extract::{Path, Query},
+  http::StatusCode,
+  response::IntoResponse,
+  Json,
+};
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListQuery {
+  /// 逗号分隔的状态（active/orphaned/resolved）或 `all`；缺省为全部（隐藏 orphaned 需显式传 `active,resolved`）
+  pub status: Option<String>,
+  pub file_path: Option<String>,
+  /// root 相对的路径前缀（字符串前缀；目录请以 `/` 结尾）
+  pub path_prefix: Option<String>,
+  /// 逗号分隔，需同时包含
+  pub tags: Option<String>,
+  /// 逗号分隔，任一匹配
+  pub priority: Option<String>,
+  pub created_after: Option<String>,
+  pub created_before: Option<String>,
+  pub updated_after: Option<String>,
+  pub updated_before: Option<String>,
+  /// comment/selectedText 子串匹配（SQLite `LIKE`：仅 ASCII 字母不区分大小写）
+  pub q: Option<String>,
+  /// createdAt（默认）/updatedAt/filePath/priority
+  pub sort: Option<String>,
+  /// asc/desc；createdAt/updatedAt 默认 desc，其余默认 asc
+  pub order: Option<String>,
+  pub limit: Option<usize>,
+  pub cursor: Option<String>,
+}
+
+const LIST_LIMIT_MAX: usize = 1000;
+
+fn split_csv(v: Option<&str>) -> Vec<String> {
+  v.map(|s| s.split(',').map(str::trim).filter(|p| !p.is_empty()).map(String::from).collect()).unwrap_or_default()
+}
 
-pub async fn list_annotations(axum::extract::State(state): axum::extract::State<AppState>) -> impl IntoResponse {
-  match state.store.list_annotations().await {
-    Ok(v) => Json(map_and_filter_annotations(&state, v)).into_response(),
+fn parse_statuses(v: Option<&str>) -> Result<Vec<AnnotationStatus>, String> {
+  match v.map(str::trim) {
+    None | Some("") | Some("all") => Ok(vec![AnnotationStatus::Active, AnnotationStatus::Orphaned, AnnotationStatus::Resolved]),
+    Some(list) => split_csv(Some(list)).iter().map(|p| AnnotationStatus::parse(p).ok_or_else(|| format!("unknown status: {}", p))).collect(),
+  }
+}
+
+fn list_filter(state: &AppState, q: ListQuery) -> Result<AnnotationFilter, String> {
+  let statuses = parse_statuses(q.status.as_deref())?;
+  let sort = match q.sort.as_deref() {
+    None | Some("") => AnnotationSort::default(),
+    Some(s) => AnnotationSort::parse(s).ok_or_else(|| format!("unknown sort: {}", s))?,
+  };
+  let descending = match q.order.as_deref() {
+    None | Some("") => matches!(sort, AnnotationSort::CreatedAt | AnnotationSort::UpdatedAt),
+    Some("asc") => false,
+    Some("desc") => true,
+    Some(o) => return Err(format!("unknown order: {}", o)),
+  };
+  if q.limit == Some(0) { return Err("limit must be >= 1".into()); }
+  // root 子树之外的批注不可见；用户前缀按 root 映射到 workspace 相对
+  let root = root_prefix(state);
+  let mut path_prefixes = Vec::new();
+  if !root.is_empty() { path_prefixes.push(format!("{}/", root)); }
+  if let Some(p) = q.path_prefix.as_deref().map(|p| p.trim_start_matches("./")).filter(|p| !p.is_empty()) {
+    path_prefixes.push(if root.is_empty() { p.to_string() } else { format!("{}/{}", root, p) });
+  }
+  Ok(AnnotationFilter {
+    statuses,
+    file_path: q.file_path.as_deref().filter(|p| !p.is_empty()).map(|p| to_workspace_relative(state, p)),
+    path_prefixes,
+    tags: split_csv(q.tags.as_deref()),
+    priorities: split_csv(q.priority.as_deref()),
+    created_after: q.created_after,
+    created_before: q.created_before,
+    updated_after: q.updated_after,
+    updated_before: q.updated_before,
+    text: q.q.map(|t| t.trim().to_string()).filter(|t| !t.is_empty()),
+    sort,
+    descending,
+    limit: q.limit.map(|l| l.min(LIST_LIMIT_MAX)),
+    cursor: q.cursor.filter(|c| !c.is_empty()),
+  })
+}
+
+pub async fn list_annotations(
+  axum::extract::State(state): axum::extract::State<AppState>,
+  Query(q): Query<ListQuery>,
+) -> impl IntoResponse {
+  let filter = match list_filter(&state, q) {
+    Ok(f) => f,
+    Err(msg) => return (StatusCode::BAD_REQUEST, Json(error("INVALID", &msg))).into_response(),
+  };
+  match state.store.query_annotations(&filter).await {
+    Ok(page) => {
+      let items = map_and_filter_annotations(&state, page.items);
+      match page.next_cursor {
+        Some(c) => ([(NEXT_CURSOR_HEADER, c)], Json(items)).into_response(),
+        None => Json(items).into_response(),
+      }
+    }
+    Err(StoreError::InvalidCursor) => (StatusCode::BAD_REQUEST, Json(error("INVALID", "invalid cursor"))).into_response(),
     Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
   }
 }
 
-pub async fn create_annotation(
+#[derive(Debug, serde::Deserialize)]
+pub struct SearchQuery {
+  pub q: Option<String>,
+  /// 同列表接口的 `status`
+  pub status: Option<String>,
+  pub limit: Option<usize>,
+}
+
+const SEARCH_LIMIT_DEFAULT: usize = 50;
+const SEARCH_LIMIT_MAX: usize = 200;
+
+pub async fn search_annotations(
   axum::extract::State(state): axum::extract::State<AppState>,
-  Json(body): Json<CreateAnnotation>,
+  Query(q): Query<SearchQuery>,
 ) -> impl IntoResponse {
-  let id = uuid::Uuid::new_v4().to_string();
-  let now = time::OffsetDateTime::now_utc().format(&time::format_description::well_known::Rfc3339).unwrap_or_else(|_| "".into());
-  let ws_rel_path = to_workspace_relative(&state, &body.file_path);
-  let ann = Annotation {
-    id,
-    file_path: ws_rel_path.clone(),
-    start_line: body.start_line,
-    end_line: body.end_line,
-    start_column: body.start_column,
-    end_column: body.end_column,
-    selected_text: body.selected_text,
-    comment: body.comment,
-    pre_context_hash: body.pre_context_hash,
-    post_context_hash: body.post_context_hash,
-    file_digest: body.file_digest,
-    tags: body.tags,
-    priority: Some(body.priority.unwrap_or_else(|| "P1".into())),
-    created_at: now.clone(),
-    updated_at: now,
+  let text = q.q.as_deref().map(str::trim).unwrap_or("");
+  if text.is_empty() { return (StatusCode::BAD_REQUEST, Json(error("INVALID", "q is required"))).into_response(); }
+  let statuses = match parse_statuses(q.status.as_deref()) {
+    Ok(v) => v,
+    Err(msg) => return (StatusCode::BAD_REQUEST, Json(error("INVALID", &msg))).into_response(),
   };
-  match state.store.insert_annotation(&ann).await {
-    Ok(_) => {
-      let mut out = ann.clone();
-      out.file_path = from_workspace_to_root(&state, &ws_rel_path);
-      Json(out).into_response()
+  let root = root_prefix(&state);
+  let scope: Vec<String> = if root.is_empty() { vec![] } else { vec![format!("{}/", root)] };
+  let limit = q.limit.unwrap_or(SEARCH_LIMIT_DEFAULT).clamp(1, SEARCH_LIMIT_MAX);
+  match state.store.search_annotations(text, &statuses, &scope, limit).await {
+    Ok(hits) => {
+      let hits: Vec<_> = hits
+        .into_iter()
+        .map(|mut h| {
+          h.annotation.file_path = from_workspace_to_root(&state, &h.annotation.file_path);
+          h
+        })
+        .collect();
+      Json(hits).into_response()
     }
     Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
   }
 }
 
+/// 分页时下一页游标所在的响应头
+pub const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+pub async fn create_annotation(
+  axum::extract::State(state): axum::extract::State<AppState>,
+  Json(body): Json<CreateAnnotation>,
+) -> impl IntoResponse {
+  if !services::annotations::is_valid_diff_side(body.diff_side.as_deref()) {
+    return (StatusCode::BAD_REQUEST, Json(error("INVALID", "diffSide must be 'old' or 'new'"))).into_response();
+  }
+  match services::annotations::create_annotation(&state, body).await {
+    Ok(out) => Json(out).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
 pub async fn update_annotation(
   axum::extract::State(state): axum::extract::State<AppState>,
   Path(id): Path<String>,
   Json(body): Json<UpdateAnnotation>,
 ) -> impl IntoResponse {
+  if !services::annotations::is_valid_diff_side(body.diff_side.as_deref()) {
+    return (StatusCode::BAD_REQUEST, Json(error("INVALID", "diffSide must be 'old' or 'new'"))).into_response();
+  }
   match state.store.get_annotation(&id).await {
     Ok(Some(mut ex)) => {
+      // 选区位置变化且未显式给出上下文摘要时，按当前文件重新计算
+      let moved = body.file_path.is_some() || body.start_line.is_some() || body.end_line.is_some();
+      let recompute = moved && body.pre_context_hash.is_none() && body.post_context_hash.is_none();
       if let Some(v) = body.file_path { ex.file_path = to_workspace_relative(&state, &v); }
       if let Some(v) = body.start_line { ex.start_line = v; }
       if let Some(v) = body.end_line { ex.end_line = v; }
@@ -66,9 +191,21 @@ pub async fn update_annotation(
       if let Some(v) = body.file_digest { ex.file_digest = Some(v); }
       if let Some(v) = body.tags { ex.tags = Some(v); }
       if let Some(v) = body.priority { ex.priority = Some(v); }
-      ex.updated_at = time::OffsetDateTime::now_utc().format(&time::format_description::well_known::Rfc3339).unwrap_or_else(|_| ex.updated_at);
-      match state.store.update_annotation(&ex).await {
-        Ok(_) => { let mut out = ex.clone(); out.file_path = from_workspace_to_root(&state, &out.file_path); Json(out).into_response() }
+      if let Some(v) = body.diff_side { ex.diff_side = Some(v); }
+      if let Some(v) = body.base_ref { ex.base_ref = Some(v); }
+      if recompute {
+        let root_rel = from_workspace_to_root(&state, &ex.file_path);
+        services::annotations::fill_anchor_context(&state, &root_rel, &mut ex, true);
+      }
+      ex.updated_at = services::annotations::now_rfc3339();
+      match state.store.update_annotation_recorded(&ex, RevisionKind::Edit).await {
+        Ok(false) => (StatusCode::NOT_FOUND, Json(error("NOT_FOUND", "annotation not found"))).into_response(),
+        Ok(true) => {
+          let mut out = ex.clone();
+          out.file_path = from_workspace_to_root(&state, &out.file_path);
+          state.events.publish(ServerEvent::AnnotationUpdated { annotation: out.clone() });
+          Json(out).into_response()
+        }
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
       }
     }
@@ -77,31 +214,130 @@ pub async fn update_annotation(
   }
 }
 
+pub async fn reattach_annotation(
+  axum::extract::State(state): axum::extract::State<AppState>,
+  Path(id): Path<String>,
+  Json(body): Json<ReattachAnnotation>,
+) -> impl IntoResponse {
+  match services::annotations::reattach_annotation(&state, &id, body).await {
+    Ok(Some(out)) => Json(out).into_response(),
+    Ok(None) => (StatusCode::NOT_FOUND, Json(error("NOT_FOUND", "annotation not found"))).into_response(),
+    Err(e) => (StatusCode::BAD_REQUEST, Json(error("INVALID", &e.to_string()))).into_response(),
+  }
+}
+
+/// 解决/重新打开的统一响应
+fn resolution_response(r: anyhow::Result<services::annotations::Resolution>) -> axum::response::Response {
+  use services::annotations::Resolution;
+  match r {
+    Ok(Resolution::Done(out)) => Json(out).into_response(),
+    Ok(Resolution::NotFound) => (StatusCode::NOT_FOUND, Json(error("NOT_FOUND", "annotation not found"))).into_response(),
+    Ok(Resolution::NotResolved(st)) => (
+      StatusCode::CONFLICT,
+      Json(error("CONFLICT", &format!("only resolved annotations can be reopened (status: {})", st.as_str()))),
+    ).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+/// Body 可省略（空 body 视为 `{}`）；给出但不是合法 JSON 时 400
+pub async fn resolve_annotation(
+  axum::extract::State(state): axum::extract::State<AppState>,
+  Path(id): Path<String>,
+  body: axum::body::Bytes,
+) -> impl IntoResponse {
+  let body: ResolveAnnotation = if body.iter().all(u8::is_ascii_whitespace) {
+    ResolveAnnotation::default()
+  } else {
+    match serde_json::from_slice(&body) {
+      Ok(b) => b,
+      Err(e) => return (StatusCode::BAD_REQUEST, Json(error("INVALID", &format!("invalid JSON body: {}", e)))).into_response(),
+    }
+  };
+  resolution_response(services::annotations::set_resolution(&state, &id, Some(body)).await)
+}
+
+pub async fn reopen_annotation(axum::extract::State(state): axum::extract::State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
+  resolution_response(services::annotations::set_resolution(&state, &id, None).await)
+}
+
+pub async fn annotation_history(axum::extract::State(state): axum::extract::State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
+  match services::annotations::annotation_history(&state, &id).await {
+    Ok(Some(revs)) => Json(revs).into_response(),
+    Ok(None) => (StatusCode::NOT_FOUND, Json(error("NOT_FOUND", "annotation not found"))).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+pub async fn restore_revision(
+  axum::extract::State(state): axum::extract::State<AppState>,
+  Path((id, revision_id)): Path<(String, String)>,
+) -> impl IntoResponse {
+  match services::annotations::restore_revision(&state, &id, &revision_id).await {
+    Ok(Some(out)) => Json(out).into_response(),
+    Ok(None) => (StatusCode::NOT_FOUND, Json(error("NOT_FOUND", "annotation or revision not found"))).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
 pub async fn delete_annotation(axum::extract::State(state): axum::extract::State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
   match state.store.delete_annotation(&id).await {
-    Ok(_) => Json(serde_json::json!({"ok": true})).into_response(),
+    Ok(_) => {
+      state.events.publish(ServerEvent::AnnotationDeleted { id });
+      Json(serde_json::json!({"ok": true})).into_response()
+    }
     Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
   }
 }
 
 #[derive(serde::Deserialize)]
-#[serde(rename_all = "camelCase", untagged)]
+#[serde(untagged)]
 pub enum ImportPayload {
-  Bundle { schema_version: String, annotations: Vec<Annotation> },
+  /// `schemaVersion` 接受字符串或数字，以免数字版本号落入 `Direct` 而被忽略
+  Bundle {
+    #[serde(rename = "schemaVersion")]
+    schema_version: serde_json::Value,
+    annotations: Vec<Annotation>,
+  },
   Direct { annotations: Vec<Annotation> },
 }
 
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportQuery {
+  /// skip / overwrite / newer-wins（默认）/ duplicate-with-new-id
+  pub strategy: Option<String>,
+  pub dry_run: Option<bool>,
+  /// 入库前按当前代码重新定位（同 `/api/annotations/verify`）
+  pub reanchor: Option<bool>,
+}
+
 pub async fn import_annotations(
   axum::extract::State(state): axum::extract::State<AppState>,
+  Query(q): Query<ImportQuery>,
   Json(payload): Json<ImportPayload>,
 ) -> impl IntoResponse {
-  let anns_ws: Vec<Annotation> = match payload {
-    ImportPayload::Bundle { schema_version: _sv, annotations } => annotations,
+  let strategy = match q.strategy.as_deref() {
+    None | Some("") => ImportStrategy::default(),
+    Some(s) => match ImportStrategy::parse(s) {
+      Some(v) => v,
+      None => return (StatusCode::BAD_REQUEST, Json(error("INVALID", &format!("unknown strategy: {}", s)))).into_response(),
+    },
+  };
+  let anns = match payload {
+    ImportPayload::Bundle { schema_version, annotations } => {
+      let sv = match &schema_version { serde_json::Value::String(s) => s.clone(), other => other.to_string() };
+      if sv != BUNDLE_SCHEMA_VERSION {
+        let msg = format!("unsupported schemaVersion: {} (expected {})", sv, BUNDLE_SCHEMA_VERSION);
+        return (StatusCode::BAD_REQUEST, Json(error("UNSUPPORTED_SCHEMA", &msg))).into_response();
+      }
+      annotations
+    }
     ImportPayload::Direct { annotations } => annotations
-  }
-    .into_iter().map(|mut a| { a.file_path = to_workspace_relative(&state, &a.file_path); a }).collect();
-  match state.store.import_annotations(&anns_ws).await {
-    Ok((added, updated, skipped)) => Json(serde_json::json!({"added": added, "updated": updated, "skipped": skipped})).into_response(),
+  };
+  let anns_ws: Vec<Annotation> = anns.into_iter().map(|mut a| { a.file_path = to_workspace_relative(&state, &a.file_path); a }).collect();
+  match services::annotations::import_annotations(&state, anns_ws, strategy, q.dry_run.unwrap_or(false), q.reanchor.unwrap_or(false)).await {
+    Ok(out) => Json(out).into_response(),
     Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
   }
 }
@@ -111,8 +347,90 @@ pub async fn export_annotations(axum::extract::State(state): axum::extract::Stat
     Ok(anns_raw) => {
       let exported_at = time::OffsetDateTime::now_utc().format(&time::format_description::well_known::Rfc3339).unwrap_or_else(|_| "".into());
       let anns = map_and_filter_annotations(&state, anns_raw);
-      Json(serde_json::json!({"schemaVersion": "1", "annotations": anns, "exportedAt": exported_at})).into_response()
+      Json(serde_json::json!({"schemaVersion": BUNDLE_SCHEMA_VERSION, "annotations": anns, "exportedAt": exported_at})).into_response()
     }
     Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  async fn call(resp: axum::response::Response) -> (StatusCode, serde_json::Value) {
+    let status = resp.status();
+    let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap())
+  }
+
+  #[tokio::test]
+  async fn resolve_validates_body_and_reopen_requires_resolved() {
+    let dir = std::env::temp_dir().join(format!("ailoom_test_{}_res", uuid::Uuid::new_v4()));
+    let state = AppState::for_test(&dir).await;
+    std::fs::write(dir.join("a.rs"), "fn keep() {}\nfn gone() {}\n").unwrap();
+    for (id, line, text) in [("r1", 1, "fn keep() {}"), ("r2", 2, "fn gone() {}")] {
+      let ann = Annotation { id: id.into(), file_path: "a.rs".into(), start_line: line, end_line: line, selected_text: text.into(), ..Default::default() };
+      state.store.insert_annotation(&ann).await.unwrap();
+    }
+    let st = || axum::extract::State(state.clone());
+    let resolve = |id: &str, body: &'static str| resolve_annotation(st(), Path(id.to_string()), axum::body::Bytes::from_static(body.as_bytes()));
+
+    let (code, v) = call(resolve("r1", "{not json").await.into_response()).await;
+    assert_eq!((code, v["error"]["code"].as_str()), (StatusCode::BAD_REQUEST, Some("INVALID")));
+    // 未解决的批注不可重新打开
+    let (code, _) = call(reopen_annotation(st(), Path("r1".into())).await.into_response()).await;
+    assert_eq!(code, StatusCode::CONFLICT);
+
+    let (code, v) = call(resolve("r1", "").await.into_response()).await;
+    assert_eq!((code, v["status"].as_str(), v["resolvedBy"].as_str()), (StatusCode::OK, Some("resolved"), Some("user")));
+    let (_, v) = call(resolve("r2", r#"{"note": "done"}"#).await.into_response()).await;
+    assert_eq!(v["resolutionNote"], "done");
+
+    // 解决期间代码变动：重新打开时重新定位，找不到则为 orphaned
+    std::fs::write(dir.join("a.rs"), "// header\nfn keep() {}\n").unwrap();
+    let (_, v) = call(reopen_annotation(st(), Path("r1".into())).await.into_response()).await;
+    assert_eq!((v["status"].as_str(), v["startLine"].as_i64()), (Some("active"), Some(2)));
+    let (_, v) = call(reopen_annotation(st(), Path("r2".into())).await.into_response()).await;
+    assert_eq!((v["status"].as_str(), v["resolutionNote"].is_null()), (Some("orphaned"), true));
+    let (code, _) = call(reopen_annotation(st(), Path("r2".into())).await.into_response()).await;
+    assert_eq!(code, StatusCode::CONFLICT);
+  }
+
+  #[tokio::test]
+  async fn list_pages_through_next_cursor_header() {
+    let dir = std::env::temp_dir().join(format!("ailoom_test_{}_page", uuid::Uuid::new_v4()));
+    let state = AppState::for_test(&dir).await;
+    for i in 0..5 {
+      let ann = Annotation {
+        id: format!("p{i}"),
+        file_path: "a.rs".into(),
+        start_line: 1,
+        end_line: 1,
+        selected_text: "x".into(),
+        priority: (i != 2).then(|| format!("P{}", i % 2)),
+        created_at: format!("2024-01-0{}T00:00:00Z", 1 + i % 2),
+        ..Default::default()
+      };
+      state.store.insert_annotation(&ann).await.unwrap();
+    }
+    let list = |query: serde_json::Value| {
+      let q: ListQuery = serde_json::from_value(query).unwrap();
+      list_annotations(axum::extract::State(state.clone()), Query(q))
+    };
+    let mut ids = Vec::new();
+    let mut cursor: Option<String> = None;
+    for _ in 0..3 {
+      let resp = list(serde_json::json!({"sort": "priority", "limit": 2, "cursor": cursor})).await.into_response();
+      cursor = resp.headers().get(NEXT_CURSOR_HEADER).map(|v| v.to_str().unwrap().to_string());
+      let (code, v) = call(resp).await;
+      assert_eq!(code, StatusCode::OK);
+      ids.extend(v.as_array().unwrap().iter().map(|a| a["id"].as_str().unwrap().to_string()));
+    }
+    // 最后一页不再带游标；P0 → P1 → 未设置，同级按 createdAt/id
+    assert_eq!(cursor, None);
+    assert_eq!(ids, ["p0", "p4", "p1", "p3", "p2"]);
+
+    let (code, v) = call(list(serde_json::json!({"cursor": "not-a-cursor"})).await.into_response()).await;
+    assert_eq!((code, v["error"]["code"].as_str()), (StatusCode::BAD_REQUEST, Some("INVALID")));
+  }
+}
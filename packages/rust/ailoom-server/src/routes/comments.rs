@@ -0,0 +1,143 @@
+use crate::{
+  services::{annotations::now_rfc3339, events::ServerEvent},
+  state::AppState,
+  web::error,
+};
+use ailoom_core::{AnnotationComment, CreateComment, UpdateComment};
+use axum::{extract::Path, http::StatusCode, response::IntoResponse, Json};
+
+async fn annotation_exists(state: &AppState, id: &str) -> Result<bool, axum::response::Response> {
+  match state.store.get_annotation(id).await {
+    Ok(v) => Ok(v.is_some()),
+    Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response()),
+  }
+}
+
+pub async fn list_comments(axum::extract::State(state): axum::extract::State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
+  match annotation_exists(&state, &id).await {
+    Ok(true) => {}
+    Ok(false) => return (StatusCode::NOT_FOUND, Json(error("NOT_FOUND", "annotation not found"))).into_response(),
+    Err(resp) => return resp,
+  }
+  match state.store.list_comments(&id).await {
+    Ok(v) => Json(v).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+pub async fn create_comment(
+  axum::extract::State(state): axum::extract::State<AppState>,
+  Path(id): Path<String>,
+  Json(body): Json<CreateComment>,
+) -> impl IntoResponse {
+  if body.body.trim().is_empty() {
+    return (StatusCode::BAD_REQUEST, Json(error("INVALID", "body is required"))).into_response();
+  }
+  match annotation_exists(&state, &id).await {
+    Ok(true) => {}
+    Ok(false) => return (StatusCode::NOT_FOUND, Json(error("NOT_FOUND", "annotation not found"))).into_response(),
+    Err(resp) => return resp,
+  }
+  let now = now_rfc3339();
+  let comment = AnnotationComment {
+    id: uuid::Uuid::new_v4().to_string(),
+    annotation_id: id,
+    author: body.author.map(|a| a.trim().to_string()).filter(|a| !a.is_empty()).unwrap_or_else(|| "user".into()),
+    body: body.body,
+    created_at: now.clone(),
+    updated_at: now,
+  };
+  match state.store.insert_comment(&comment).await {
+    Ok(_) => {
+      state.events.publish(ServerEvent::CommentCreated { comment: comment.clone() });
+      Json(comment).into_response()
+    }
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+pub async fn update_comment(
+  axum::extract::State(state): axum::extract::State<AppState>,
+  Path((id, comment_id)): Path<(String, String)>,
+  Json(body): Json<UpdateComment>,
+) -> impl IntoResponse {
+  if body.body.trim().is_empty() {
+    return (StatusCode::BAD_REQUEST, Json(error("INVALID", "body is required"))).into_response();
+  }
+  match state.store.get_comment(&id, &comment_id).await {
+    Ok(Some(mut c)) => {
+      c.body = body.body;
+      c.updated_at = now_rfc3339();
+      match state.store.update_comment(&c).await {
+        Ok(true) => {
+          state.events.publish(ServerEvent::CommentUpdated { comment: c.clone() });
+          Json(c).into_response()
+        }
+        // 读取后被并发删除
+        Ok(false) => (StatusCode::NOT_FOUND, Json(error("NOT_FOUND", "comment not found"))).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+      }
+    }
+    Ok(None) => (StatusCode::NOT_FOUND, Json(error("NOT_FOUND", "comment not found"))).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+pub async fn delete_comment(
+  axum::extract::State(state): axum::extract::State<AppState>,
+  Path((id, comment_id)): Path<(String, String)>,
+) -> impl IntoResponse {
+  match state.store.delete_comment(&id, &comment_id).await {
+    Ok(true) => {
+      state.events.publish(ServerEvent::CommentDeleted { annotation_id: id, id: comment_id });
+      Json(serde_json::json!({"ok": true})).into_response()
+    }
+    Ok(false) => (StatusCode::NOT_FOUND, Json(error("NOT_FOUND", "comment not found"))).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use ailoom_core::Annotation;
+
+  async fn call(resp: axum::response::Response) -> (StatusCode, serde_json::Value) {
+    let status = resp.status();
+    let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap())
+  }
+
+  #[tokio::test]
+  async fn comment_thread_crud_and_not_found() {
+    let dir = std::env::temp_dir().join(format!("ailoom_test_{}_comments", uuid::Uuid::new_v4()));
+    let state = AppState::for_test(&dir).await;
+    let ann = Annotation { id: "a1".into(), file_path: "a.rs".into(), start_line: 1, end_line: 1, selected_text: "fn a() {}".into(), ..Default::default() };
+    state.store.insert_annotation(&ann).await.unwrap();
+    let st = || axum::extract::State(state.clone());
+    let create = |id: &str, body: &str| {
+      create_comment(st(), Path(id.to_string()), Json(CreateComment { body: body.into(), author: None }))
+    };
+
+    let (code, _) = call(create("a1", "  ").await.into_response()).await;
+    assert_eq!(code, StatusCode::BAD_REQUEST);
+    let (code, _) = call(create("nope", "hi").await.into_response()).await;
+    assert_eq!(code, StatusCode::NOT_FOUND);
+    let (code, v) = call(create("a1", "hi").await.into_response()).await;
+    assert_eq!((code, v["author"].as_str()), (StatusCode::OK, Some("user")));
+    let cid = v["id"].as_str().unwrap().to_string();
+
+    let update = |cid: &str| update_comment(st(), Path(("a1".to_string(), cid.to_string())), Json(UpdateComment { body: "edited".into() }));
+    let (code, v) = call(update(&cid).await.into_response()).await;
+    assert_eq!((code, v["body"].as_str()), (StatusCode::OK, Some("edited")));
+    let (code, _) = call(update("missing").await.into_response()).await;
+    assert_eq!(code, StatusCode::NOT_FOUND);
+
+    let (code, v) = call(list_comments(st(), Path("a1".into())).await.into_response()).await;
+    assert_eq!((code, v.as_array().map(|a| a.len())), (StatusCode::OK, Some(1)));
+    let (code, _) = call(delete_comment(st(), Path(("a1".to_string(), cid.clone()))).await.into_response()).await;
+    assert_eq!(code, StatusCode::OK);
+    let (code, _) = call(delete_comment(st(), Path(("a1".to_string(), cid))).await.into_response()).await;
+    assert_eq!(code, StatusCode::NOT_FOUND);
+  }
+}
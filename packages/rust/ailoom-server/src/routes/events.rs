@@ -0,0 +1,36 @@
+use crate::state::AppState;
+use axum::response::{
+  sse::{Event, KeepAlive, Sse},
+  IntoResponse,
+};
+use std::convert::Infallible;
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+
+// SSE：每个事件以 `event: <type>` + JSON data 推送；落后丢弃的事件直接跳过（客户端可按需全量刷新）
+pub async fn api_events(axum::extract::State(state): axum::extract::State<AppState>) -> impl IntoResponse {
+  let stream = BroadcastStream::new(state.events.subscribe()).filter_map(|r| {
+    let ev = r.ok()?;
+    let data = serde_json::to_string(&ev).ok()?;
+    Some(Ok::<_, Infallible>(Event::default().event(ev.name()).data(data)))
+  });
+  Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::services::events::ServerEvent;
+
+  #[tokio::test]
+  async fn streams_events_as_named_sse_frames() {
+    let dir = std::env::temp_dir().join(format!("ailoom_test_{}_sse", uuid::Uuid::new_v4()));
+    let state = AppState::for_test(&dir).await;
+    let resp = api_events(axum::extract::State(state.clone())).await.into_response();
+    assert_eq!(resp.headers()["content-type"], "text/event-stream");
+    let mut body = resp.into_body().into_data_stream();
+    state.events.publish(ServerEvent::FileChanged { path: "src/a.rs".into(), digest: None });
+    let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), body.next()).await.unwrap().unwrap().unwrap();
+    let frame = String::from_utf8(chunk.to_vec()).unwrap();
+    assert_eq!(frame, "event: file.changed\ndata: {\"type\":\"file.changed\",\"path\":\"src/a.rs\",\"digest\":null}\n\n");
+  }
+}
@@ -1,4 +1,8 @@
-use crate::{services::verification::verify_annotations_for_file, state::AppState, web::error};
+use crate::{
+  services::{events::ServerEvent, verification::verify_annotations_for_file},
+  state::AppState,
+  web::error,
+};
 use ailoom_fs::{read_file_chunk, FsConfig};
 use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
 
@@ -57,6 +61,8 @@ pub async fn api_file_put(
 ) -> impl IntoResponse {
   match ailoom_fs::write_file(&state.fs, &body.path, &body.content, body.base_digest.as_deref()) {
     Ok(new_digest) => {
+      state.own_writes.record(&state.root, &body.path, &new_digest);
+      state.events.publish(ServerEvent::FileChanged { path: body.path.clone(), digest: Some(new_digest.clone()) });
       let st = state.clone();
       let path = body.path.clone();
       tokio::spawn(async move {
@@ -0,0 +1,20 @@
+use crate::state::AppState;
+use axum::{extract::Query, response::IntoResponse, Json};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct FindQuery {
+  pub q: Option<String>,
+  pub limit: Option<usize>,
+}
+
+pub async fn api_files_find(axum::extract::State(state): axum::extract::State<AppState>, Query(q): Query<FindQuery>) -> impl IntoResponse {
+  let limit = q.limit.unwrap_or(50).clamp(1, 500);
+  let (files, fs) = (state.files.clone(), state.fs.clone());
+  let query = q.q.unwrap_or_default();
+  // 大仓库全量打分较重，放到阻塞线程池；未监听时先按需重建
+  let hits = tokio::task::spawn_blocking(move || {
+    files.refresh_if_stale(&fs);
+    files.find(&query, limit)
+  }).await.unwrap_or_default();
+  Json(hits).into_response()
+}
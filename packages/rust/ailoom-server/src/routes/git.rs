@@ -0,0 +1,52 @@
+use crate::{
+  services::git::{diff_in_root, uncommitted_in_root},
+  state::AppState,
+  web::error,
+};
+use ailoom_git::GitError;
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct UncommittedQuery {
+  /// 逗号分隔的 root 相对路径（pathspec），缺省为整个 root
+  pub paths: Option<String>,
+}
+
+pub async fn api_git_uncommitted(
+  axum::extract::State(state): axum::extract::State<AppState>,
+  Query(q): Query<UncommittedQuery>,
+) -> impl IntoResponse {
+  let paths: Vec<String> = q
+    .paths
+    .as_deref()
+    .map(|s| s.split(',').map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect())
+    .unwrap_or_default();
+  match uncommitted_in_root(&state, &paths).await {
+    Ok(files) => Json(serde_json::json!({ "isRepo": state.git.is_some(), "files": files })).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("GIT_FAILED", &e.to_string()))).into_response(),
+  }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DiffQuery {
+  pub path: String,
+  /// 基线 ref（分支/标签/commit），缺省 HEAD
+  pub base: Option<String>,
+}
+
+pub async fn api_git_diff(
+  axum::extract::State(state): axum::extract::State<AppState>,
+  Query(q): Query<DiffQuery>,
+) -> impl IntoResponse {
+  let rel = std::path::Path::new(&q.path);
+  if q.path.is_empty() || rel.is_absolute() || rel.components().any(|c| matches!(c, std::path::Component::ParentDir)) {
+    return (StatusCode::BAD_REQUEST, Json(error("INVALID_PATH", "path must be relative to root"))).into_response();
+  }
+  let base = q.base.unwrap_or_else(|| "HEAD".into());
+  match diff_in_root(&state, &q.path, &base).await {
+    Ok(Some(diff)) => Json(diff).into_response(),
+    Ok(None) => (StatusCode::NOT_FOUND, Json(error("NOT_A_REPO", "root is not inside a git repository"))).into_response(),
+    Err(GitError::InvalidRef(r)) => (StatusCode::BAD_REQUEST, Json(error("INVALID_REF", &format!("unknown ref: {}", r)))).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("GIT_FAILED", &e.to_string()))).into_response(),
+  }
+}
@@ -0,0 +1,30 @@
+use crate::{services::mcp::{handle_message, parse_error}, state::AppState};
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::Value;
+
+// Streamable HTTP 传输：POST 单条或批量 JSON-RPC，统一以 application/json 响应（不开启 SSE 流）
+pub async fn mcp_post(axum::extract::State(state): axum::extract::State<AppState>, body: String) -> impl IntoResponse {
+  let msg: Value = match serde_json::from_str(&body) {
+    Ok(v) => v,
+    Err(_) => return (StatusCode::BAD_REQUEST, Json(parse_error())).into_response(),
+  };
+  let out = match msg {
+    Value::Array(batch) => {
+      let mut resps = Vec::new();
+      for m in batch {
+        if let Some(r) = handle_message(&state, m).await { resps.push(r); }
+      }
+      if resps.is_empty() { None } else { Some(Value::Array(resps)) }
+    }
+    msg => handle_message(&state, msg).await,
+  };
+  match out {
+    Some(v) => Json(v).into_response(),
+    // 仅包含通知/响应时按规范返回 202 且无响应体
+    None => StatusCode::ACCEPTED.into_response(),
+  }
+}
+
+pub async fn mcp_get() -> impl IntoResponse {
+  StatusCode::METHOD_NOT_ALLOWED
+}
@@ -1,6 +1,12 @@
 pub mod tree;
 pub mod files;
 pub mod annotations;
+pub mod comments;
 pub mod stitch;
 pub mod verify;
-
+pub mod mcp;
+pub mod events;
+pub mod search;
+pub mod find;
+pub mod git;
+pub mod workspaces;
@@ -0,0 +1,62 @@
+use crate::web::error;
+use ailoom_fs::{FsConfig, SearchOptions, Searcher};
+use axum::{
+  body::Body,
+  extract::Query,
+  http::{header, StatusCode},
+  response::IntoResponse,
+  Json,
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchQuery {
+  pub q: String,
+  pub regex: Option<bool>,
+  pub glob: Option<String>,
+  pub case_sensitive: Option<bool>,
+  pub context_lines: Option<usize>,
+  pub max_results: Option<usize>,
+}
+
+// NDJSON 流式返回：每行 `{ type: 'match', ...SearchMatch }`，最后一行 `{ type: 'done', ...SearchSummary }`
+pub async fn api_search(Query(q): Query<SearchQuery>, fs: FsConfig) -> impl IntoResponse {
+  if q.q.is_empty() {
+    return (StatusCode::BAD_REQUEST, Json(error("INVALID", "q is required"))).into_response();
+  }
+  let opts = SearchOptions {
+    query: q.q,
+    regex: q.regex.unwrap_or(false),
+    case_sensitive: q.case_sensitive.unwrap_or(false),
+    glob: q.glob,
+    context_lines: q.context_lines.unwrap_or(2).min(10),
+    max_results: q.max_results.unwrap_or(1000).clamp(1, 10_000),
+  };
+  let searcher = match Searcher::new(&fs, &opts) {
+    Ok(s) => s,
+    Err(e) => {
+      let msg = e.to_string();
+      return (StatusCode::BAD_REQUEST, Json(error(&msg, &msg))).into_response();
+    }
+  };
+
+  let (tx, rx) = tokio::sync::mpsc::channel::<Result<String, std::io::Error>>(64);
+  tokio::task::spawn_blocking(move || {
+    let line = |v: serde_json::Value| format!("{}\n", v);
+    let summary = searcher.run(|m| {
+      let mut v = serde_json::to_value(&m).unwrap_or_default();
+      v["type"] = "match".into();
+      // 发送失败说明客户端已断开，终止遍历
+      tx.blocking_send(Ok(line(v))).is_ok()
+    });
+    let mut v = serde_json::to_value(&summary).unwrap_or_default();
+    v["type"] = "done".into();
+    let _ = tx.blocking_send(Ok(line(v)));
+  });
+  (
+    [(header::CONTENT_TYPE, "application/x-ndjson")],
+    Body::from_stream(ReceiverStream::new(rx)),
+  )
+    .into_response()
+}
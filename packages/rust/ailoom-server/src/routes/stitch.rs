@@ -1,11 +1,14 @@
-use crate::{paths::map_and_filter_annotations, state::AppState, web::error};
-use ailoom_core::Annotation;
-use ailoom_stitch as stitch;
-use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+use crate::{
+  services::stitch::{self as svc, run_stitch, stats_json, StitchError, StitchParams, TemplateSource},
+  state::AppState,
+  web::error,
+};
+use ailoom_stitch::CustomTemplate;
+use axum::{extract::{Path, Query}, http::StatusCode, response::IntoResponse, Json};
 
 #[derive(serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct StitchQuery { pub template_id: Option<String>, pub max_chars: Option<usize> }
+pub struct StitchQuery { pub template_id: Option<String>, pub max_chars: Option<usize>, pub max_tokens: Option<usize>, pub tokenizer: Option<String>, pub include_threads: Option<bool>, pub include_resolved: Option<bool>, pub context_lines: Option<usize>, pub expand_block: Option<bool>, pub format: Option<String> }
 #[derive(serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct StitchBody { pub annotation_ids: Option<Vec<String>> }
@@ -15,12 +18,172 @@ pub async fn stitch_endpoint(
   Query(q): Query<StitchQuery>,
   Json(body): Json<StitchBody>,
 ) -> impl IntoResponse {
-  let ids = body.annotation_ids.unwrap_or_default();
-  let anns_raw = match state.store.list_annotations_by_ids(&ids).await { Ok(v) => v, Err(e) => { return (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response() } };
-  let anns: Vec<Annotation> = map_and_filter_annotations(&state, anns_raw);
-  let tpl = stitch::TemplateId::parse(q.template_id.as_deref().unwrap_or("concise"));
-  let max_chars = q.max_chars.unwrap_or(4000).max(200).min(200_000);
-  let r = stitch::generate_prompt(tpl, max_chars, anns);
-  Json(serde_json::json!({"prompt": r.prompt, "stats": {"total": r.stats.total, "used": r.stats.used, "truncated": r.stats.truncated, "chars": r.stats.chars} })).into_response()
+  let params = StitchParams {
+    template_id: q.template_id,
+    annotation_ids: body.annotation_ids.unwrap_or_default(),
+    max_chars: q.max_chars,
+    max_tokens: q.max_tokens,
+    tokenizer: q.tokenizer,
+    include_threads: q.include_threads.unwrap_or(false),
+    include_resolved: q.include_resolved.unwrap_or(false),
+    context_lines: q.context_lines,
+    expand_block: q.expand_block.unwrap_or(false),
+    format: q.format,
+  };
+  match run_stitch(&state, params).await {
+    Ok(r) => Json(serde_json::json!({"prompt": r.prompt, "stats": stats_json(&r.stats) })).into_response(),
+    Err(e) => stitch_error(e),
+  }
 }
 
+fn stitch_error(e: StitchError) -> axum::response::Response {
+  match e {
+    StitchError::Invalid(m) => (StatusCode::BAD_REQUEST, Json(error("INVALID", &m))).into_response(),
+    StitchError::TemplateNotFound(_) => (StatusCode::NOT_FOUND, Json(error("NOT_FOUND", &e.to_string()))).into_response(),
+    StitchError::Template(t) => (StatusCode::BAD_REQUEST, Json(error("INVALID_TEMPLATE", &t.message))).into_response(),
+    StitchError::Internal(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateTemplateBody { pub name: String, pub body: String, pub description: Option<String> }
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateTemplateBody { pub body: String, pub description: Option<String> }
+
+pub async fn list_templates(axum::extract::State(state): axum::extract::State<AppState>) -> impl IntoResponse {
+  match svc::list_templates(&state).await {
+    Ok(v) => Json(v).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+pub async fn get_template(axum::extract::State(state): axum::extract::State<AppState>, Path(name): Path<String>) -> impl IntoResponse {
+  match svc::find_template(&state, &name).await {
+    Ok(Some(t)) => Json(t).into_response(),
+    Ok(None) => (StatusCode::NOT_FOUND, Json(error("NOT_FOUND", "template not found"))).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+pub async fn create_template(
+  axum::extract::State(state): axum::extract::State<AppState>,
+  Json(body): Json<CreateTemplateBody>,
+) -> impl IntoResponse {
+  if let Err(m) = svc::validate_template_name(&body.name) {
+    return (StatusCode::BAD_REQUEST, Json(error("INVALID", &m))).into_response();
+  }
+  if let Err(e) = CustomTemplate::compile(&body.name, &body.body) {
+    return (StatusCode::BAD_REQUEST, Json(error("INVALID_TEMPLATE", &e.message))).into_response();
+  }
+  match state.store.get_stitch_template(&body.name).await {
+    Ok(None) => {}
+    Ok(Some(_)) => return (StatusCode::CONFLICT, Json(error("CONFLICT", "template already exists"))).into_response(),
+    Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+  match svc::create_template(&state, body.name, body.body, body.description).await {
+    Ok(t) => Json(svc::TemplateOut::from(t)).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+pub async fn update_template(
+  axum::extract::State(state): axum::extract::State<AppState>,
+  Path(name): Path<String>,
+  Json(body): Json<UpdateTemplateBody>,
+) -> impl IntoResponse {
+  if let Err(e) = CustomTemplate::compile(&name, &body.body) {
+    return (StatusCode::BAD_REQUEST, Json(error("INVALID_TEMPLATE", &e.message))).into_response();
+  }
+  match svc::update_template(&state, &name, body.body, body.description).await {
+    Ok(Some(t)) => Json(svc::TemplateOut::from(t)).into_response(),
+    Ok(None) => not_editable(&state, &name).await,
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+pub async fn delete_template(axum::extract::State(state): axum::extract::State<AppState>, Path(name): Path<String>) -> impl IntoResponse {
+  match state.store.delete_stitch_template(&name).await {
+    Ok(true) => Json(serde_json::json!({"ok": true})).into_response(),
+    Ok(false) => not_editable(&state, &name).await,
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+/// 数据库中没有该模板时：内置/文件模板为只读（400），否则 404
+async fn not_editable(state: &AppState, name: &str) -> axum::response::Response {
+  match svc::find_template(state, name).await {
+    Ok(Some(t)) => {
+      let msg = if t.source == TemplateSource::File { "file templates are edited in .ailoom/templates" } else { "built-in templates are read-only" };
+      (StatusCode::BAD_REQUEST, Json(error("INVALID", msg))).into_response()
+    }
+    Ok(None) => (StatusCode::NOT_FOUND, Json(error("NOT_FOUND", "template not found"))).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SRC: &str = "{% macro item(item) %}\n{{ item.comment }}\n{% endmacro %}\n";
+
+  async fn call(resp: axum::response::Response) -> (StatusCode, serde_json::Value) {
+    let status = resp.status();
+    let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap())
+  }
+
+  #[tokio::test]
+  async fn template_crud_guards_builtin_and_file_templates() {
+    let dir = std::env::temp_dir().join(format!("ailoom_test_{}_templates", uuid::Uuid::new_v4()));
+    let state = AppState::for_test(&dir).await;
+    std::fs::create_dir_all(dir.join(svc::TEMPLATES_DIR)).unwrap();
+    std::fs::write(dir.join(svc::TEMPLATES_DIR).join("shared.jinja"), SRC).unwrap();
+    let st = || axum::extract::State(state.clone());
+    let create = |name: &str, body: &str| create_template(st(), Json(CreateTemplateBody { name: name.into(), body: body.into(), description: None }));
+    let update = |name: &str| update_template(st(), Path(name.to_string()), Json(UpdateTemplateBody { body: SRC.into(), description: Some("d".into()) }));
+
+    let (code, v) = call(create("team", SRC).await.into_response()).await;
+    assert_eq!((code, v["source"].as_str()), (StatusCode::OK, Some("db")));
+    let (code, v) = call(create("team", SRC).await.into_response()).await;
+    assert_eq!((code, v["error"]["code"].as_str()), (StatusCode::CONFLICT, Some("CONFLICT")));
+    let (code, _) = call(create("concise", SRC).await.into_response()).await;
+    assert_eq!(code, StatusCode::BAD_REQUEST);
+    let (code, v) = call(create("broken", "{{ oops").await.into_response()).await;
+    assert_eq!((code, v["error"]["code"].as_str()), (StatusCode::BAD_REQUEST, Some("INVALID_TEMPLATE")));
+
+    let (code, v) = call(update("team").await.into_response()).await;
+    assert_eq!((code, v["description"].as_str()), (StatusCode::OK, Some("d")));
+    for name in ["concise", "shared"] {
+      let (code, _) = call(update(name).await.into_response()).await;
+      assert_eq!(code, StatusCode::BAD_REQUEST, "PUT {name}");
+      let (code, _) = call(delete_template(st(), Path(name.into())).await.into_response()).await;
+      assert_eq!(code, StatusCode::BAD_REQUEST, "DELETE {name}");
+    }
+    let (code, _) = call(update("nope").await.into_response()).await;
+    assert_eq!(code, StatusCode::NOT_FOUND);
+    let (code, _) = call(get_template(st(), Path("nope".into())).await.into_response()).await;
+    assert_eq!(code, StatusCode::NOT_FOUND);
+
+    // 拼接接口的错误映射
+    let stitch = |q: &str| {
+      let Query(q) = Query::<StitchQuery>::try_from_uri(&format!("/api/stitch?{q}").parse().unwrap()).unwrap();
+      stitch_endpoint(st(), Query(q), Json(StitchBody { annotation_ids: None }))
+    };
+    let (code, _) = call(stitch("tokenizer=bpe").await.into_response()).await;
+    assert_eq!(code, StatusCode::BAD_REQUEST);
+    let (code, _) = call(stitch("templateId=team&format=json").await.into_response()).await;
+    assert_eq!(code, StatusCode::BAD_REQUEST);
+    let (code, _) = call(stitch("templateId=nope").await.into_response()).await;
+    assert_eq!(code, StatusCode::NOT_FOUND);
+    let (code, v) = call(stitch("templateId=team").await.into_response()).await;
+    assert_eq!((code, v["stats"]["total"].as_u64()), (StatusCode::OK, Some(0)));
+
+    let (code, _) = call(delete_template(st(), Path("team".into())).await.into_response()).await;
+    assert_eq!(code, StatusCode::OK);
+    let (code, _) = call(delete_template(st(), Path("team".into())).await.into_response()).await;
+    assert_eq!(code, StatusCode::NOT_FOUND);
+  }
+}
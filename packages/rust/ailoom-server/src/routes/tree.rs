@@ -1,17 +1,36 @@
-use crate::web::error;
-use ailoom_fs::{list_dir, FsConfig};
+use crate::{services::git::uncommitted_in_root, state::AppState, web::error};
+use ailoom_core::{EntryType, GitStatus};
+use ailoom_fs::list_dir;
 use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
 
 #[derive(Debug, serde::Deserialize)]
 pub struct TreeQuery {
   pub dir: Option<String>,
+  /// 为 true 时附带 `gitStatus`（目录：其下存在任意改动即标记为 modified）
+  pub git: Option<bool>,
 }
 
-pub async fn api_tree(Query(q): Query<TreeQuery>, fs: FsConfig) -> impl IntoResponse {
+pub async fn api_tree(axum::extract::State(state): axum::extract::State<AppState>, Query(q): Query<TreeQuery>) -> impl IntoResponse {
   let dir = q.dir.unwrap_or_else(|| ".".into());
-  match list_dir(&fs, &dir) {
-    Ok(entries) => Json(entries).into_response(),
-    Err(e) => (StatusCode::BAD_REQUEST, Json(error("INVALID_PATH", &e.to_string()))).into_response(),
+  let mut entries = match list_dir(&state.fs, &dir) {
+    Ok(entries) => entries,
+    Err(e) => return (StatusCode::BAD_REQUEST, Json(error("INVALID_PATH", &e.to_string()))).into_response(),
+  };
+  if q.git.unwrap_or(false) {
+    match uncommitted_in_root(&state, &[dir]).await {
+      Ok(changed) => {
+        for ent in entries.iter_mut() {
+          ent.git_status = match ent.r#type {
+            EntryType::File => changed.iter().find(|f| f.path == ent.path).map(|f| f.status),
+            EntryType::Dir => {
+              let prefix = format!("{}/", ent.path);
+              changed.iter().any(|f| f.path.starts_with(&prefix)).then_some(GitStatus::Modified)
+            }
+          };
+        }
+      }
+      Err(e) => tracing::warn!("tree: git status failed: {}", e),
+    }
   }
+  Json(entries).into_response()
 }
-
@@ -8,7 +8,9 @@ pub struct VerifyBody {
   pub ids: Option<Vec<String>>, // 预留
   pub window: Option<usize>,
   pub full_limit_bytes: Option<usize>,
-  pub remove_broken: Option<bool>,
+  /// 无法重新定位时是否标记为 orphaned（不会删除）；`removeBroken` 为旧名，保留一个版本
+  #[serde(alias = "removeBroken")]
+  pub mark_orphaned: Option<bool>,
 }
 
 pub async fn verify_annotations_endpoint(
@@ -21,11 +23,43 @@ pub async fn verify_annotations_endpoint(
     Some(p) => p.to_string(),
     None => { return (StatusCode::BAD_REQUEST, Json(error("INVALID", "filePath is required"))).into_response(); }
   };
-  let window = body.window.unwrap_or(40).max(1).min(2000);
+  let window = body.window.unwrap_or(40).clamp(1, 2000);
   let full_limit = body.full_limit_bytes.unwrap_or(5 * 1024 * 1024);
-  let remove_broken = body.remove_broken.unwrap_or(true);
-  match verify_annotations_for_file(&state, &file, Some(window), Some(full_limit), remove_broken).await {
+  let mark_orphaned = body.mark_orphaned.unwrap_or(true);
+  match verify_annotations_for_file(&state, &file, Some(window), Some(full_limit), mark_orphaned).await {
     Ok(v) => Json::<VerifyResultOut>(v).into_response(),
     Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use ailoom_core::{Annotation, AnnotationStatus};
+
+  // 旧参数名 removeBroken 仍生效；响应同时带新旧字段名
+  #[tokio::test]
+  async fn accepts_legacy_param_and_returns_legacy_fields() {
+    let dir = std::env::temp_dir().join(format!("ailoom_test_{}_vr", uuid::Uuid::new_v4()));
+    let state = AppState::for_test(&dir).await;
+    std::fs::write(dir.join("a.txt"), "one\n").unwrap();
+    let ann = Annotation { id: "v1".into(), file_path: "a.txt".into(), start_line: 1, end_line: 1, selected_text: "fn removed() {}".into(), ..Default::default() };
+    state.store.insert_annotation(&ann).await.unwrap();
+
+    let call = |body: serde_json::Value| {
+      let state = state.clone();
+      async move {
+        let resp = verify_annotations_endpoint(axum::extract::State(state), Json(serde_json::from_value(body).unwrap())).await.into_response();
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+      }
+    };
+    let v = call(serde_json::json!({"filePath": "a.txt", "removeBroken": false})).await;
+    assert_eq!((v["checked"].as_u64(), v["orphaned"].as_u64()), (Some(1), Some(0)));
+    assert_eq!(state.store.get_annotation("v1").await.unwrap().unwrap().status, AnnotationStatus::Active);
+
+    let v = call(serde_json::json!({"filePath": "a.txt"})).await;
+    assert_eq!(v["orphanedIds"], serde_json::json!(["v1"]));
+    assert_eq!((&v["deleted"], &v["deletedIds"]), (&v["orphaned"], &v["orphanedIds"]));
+  }
+}
@@ -0,0 +1,99 @@
+use crate::{services, state::AppState, web::error};
+use ailoom_core::TransferMode;
+use axum::{http::StatusCode, response::IntoResponse, Json};
+
+pub async fn list_workspaces(axum::extract::State(state): axum::extract::State<AppState>) -> impl IntoResponse {
+  match services::workspaces::list_workspaces(&state).await {
+    Ok(v) => Json(v).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferBody {
+  pub from: String,
+  pub to: String,
+  pub mode: TransferMode,
+  /// 缺省或为空表示源工作区的全部批注
+  pub annotation_ids: Option<Vec<String>>,
+}
+
+pub async fn transfer_annotations(
+  axum::extract::State(state): axum::extract::State<AppState>,
+  Json(body): Json<TransferBody>,
+) -> impl IntoResponse {
+  if body.from == body.to { return (StatusCode::BAD_REQUEST, Json(error("INVALID", "from and to must differ"))).into_response(); }
+  for id in [&body.from, &body.to] {
+    match state.store.get_workspace(id).await {
+      Ok(Some(_)) => {}
+      Ok(None) => return (StatusCode::NOT_FOUND, Json(error("NOT_FOUND", &format!("workspace not found: {}", id)))).into_response(),
+      Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+    }
+  }
+  let ids = body.annotation_ids.unwrap_or_default();
+  match services::workspaces::transfer_annotations(&state, &body.from, &body.to, &ids, body.mode).await {
+    Ok(out) => Json(out).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PruneBody {
+  /// 缺省为 `true`：只有显式传 `false` 才真正删除
+  pub dry_run: Option<bool>,
+}
+
+pub async fn prune_workspaces(
+  axum::extract::State(state): axum::extract::State<AppState>,
+  Json(body): Json<PruneBody>,
+) -> impl IntoResponse {
+  match services::workspaces::prune_workspaces(&state, body.dry_run.unwrap_or(true)).await {
+    Ok(out) => Json(out).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error("INTERNAL", &e.to_string()))).into_response(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use axum::extract::FromRequest;
+
+  async fn prune(state: &AppState, content_type: Option<&str>, body: &'static str) -> (StatusCode, serde_json::Value) {
+    let mut req = axum::http::Request::post("/api/workspaces/prune");
+    if let Some(ct) = content_type { req = req.header("content-type", ct); }
+    let req = req.body(axum::body::Body::from(body)).unwrap();
+    let resp = match Json::<PruneBody>::from_request(req, &()).await {
+      Ok(json) => prune_workspaces(axum::extract::State(state.clone()), json).await.into_response(),
+      Err(rejection) => rejection.into_response(),
+    };
+    let status = resp.status();
+    let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or_default())
+  }
+
+  #[tokio::test]
+  async fn prune_is_a_dry_run_unless_explicitly_disabled() {
+    let dir = std::env::temp_dir().join(format!("ailoom_test_{}_prune", uuid::Uuid::new_v4()));
+    let state = AppState::for_test(&dir).await;
+    let gone = ailoom_store::Store::connect_path(&dir.join("ailoom.db"), &dir.join("gone").to_string_lossy(), None).await.unwrap();
+    let gone_id = gone.workspace_id().to_string();
+    let ws_count = || async { state.store.list_workspaces().await.unwrap().len() };
+
+    // 空 body / 缺 Content-Type / 非法 JSON 一律拒绝，不删除
+    assert!(prune(&state, Some("application/json"), "").await.0.is_client_error());
+    assert_eq!(prune(&state, None, r#"{"dryRun": false}"#).await.0, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert!(prune(&state, Some("application/json"), "{dryRun:").await.0.is_client_error());
+    assert_eq!(ws_count().await, 2);
+
+    let (code, v) = prune(&state, Some("application/json"), "{}").await;
+    assert_eq!((code, v["dryRun"].as_bool()), (StatusCode::OK, Some(true)));
+    assert_eq!(v["pruned"][0]["id"].as_str(), Some(gone_id.as_str()));
+    assert_eq!(ws_count().await, 2);
+
+    let (_, v) = prune(&state, Some("application/json"), r#"{"dryRun": false}"#).await;
+    assert_eq!(v["dryRun"].as_bool(), Some(false));
+    assert_eq!(ws_count().await, 1);
+  }
+}
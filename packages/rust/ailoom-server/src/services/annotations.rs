@@ -0,0 +1,238 @@
+use crate::{
+  paths::{from_workspace_to_root, in_root_scope, map_and_filter_annotations, to_workspace_relative},
+  services::{events::ServerEvent, verification::{anchor_annotation, context_hashes, span_text, Anchoring, FileView, Relocation}},
+  state::AppState,
+};
+use ailoom_core::{Annotation, AnnotationRevision, AnnotationStatus, CreateAnnotation, ImportReport, ImportStrategy, ReattachAnnotation, ResolveAnnotation, RevisionKind};
+use std::collections::{HashMap, HashSet};
+use anyhow::Result;
+
+pub use ailoom_core::now_rfc3339;
+
+/// diff 批注的侧别仅允许 `old`（基线）/ `new`（工作区）
+pub fn is_valid_diff_side(side: Option<&str>) -> bool {
+  matches!(side, None | Some("old") | Some("new"))
+}
+
+/// 按当前文件内容补齐选区前/后上下文摘要与 fileDigest（供校验时给候选位置打分）；
+/// `force` 为 false 时保留调用方已提供的值。文件不可全文读取（非文本/超限）时保持原样。
+pub fn fill_anchor_context(state: &AppState, root_rel_path: &str, ann: &mut Annotation, force: bool) {
+  let Ok(ff) = ailoom_fs::read_file_full(&state.fs, root_rel_path) else { return };
+  let lines: Vec<&str> = ff.content.split('\n').collect();
+  let (pre, post) = context_hashes(&lines, ann.start_line.max(1) as usize, ann.end_line.max(1) as usize);
+  if force || ann.pre_context_hash.is_none() { ann.pre_context_hash = Some(pre); }
+  if force || ann.post_context_hash.is_none() { ann.post_context_hash = Some(post); }
+  if force || ann.file_digest.is_none() { ann.file_digest = Some(ff.digest); }
+}
+
+/// 创建批注：入参路径为 root 相对，入库为 workspace 相对，返回值再映射回 root 相对（REST 与 MCP 共用）
+pub async fn create_annotation(state: &AppState, body: CreateAnnotation) -> Result<Annotation> {
+  anyhow::ensure!(is_valid_diff_side(body.diff_side.as_deref()), "diffSide must be 'old' or 'new'");
+  let id = uuid::Uuid::new_v4().to_string();
+  let now = now_rfc3339();
+  let ws_rel_path = to_workspace_relative(state, &body.file_path);
+  let mut ann = Annotation {
+    id,
+    file_path: ws_rel_path.clone(),
+    start_line: body.start_line,
+    end_line: body.end_line,
+    start_column: body.start_column,
+    end_column: body.end_column,
+    selected_text: body.selected_text,
+    comment: body.comment,
+    pre_context_hash: body.pre_context_hash,
+    post_context_hash: body.post_context_hash,
+    file_digest: body.file_digest,
+    tags: body.tags,
+    priority: Some(body.priority.unwrap_or_else(|| "P1".into())),
+    diff_side: body.diff_side,
+    base_ref: body.base_ref,
+    status: AnnotationStatus::Active,
+    resolved_at: None,
+    resolved_by: None,
+    resolution_note: None,
+    created_at: now.clone(),
+    updated_at: now,
+  };
+  fill_anchor_context(state, &body.file_path, &mut ann, false);
+  state.store.insert_annotation_recorded(&ann, RevisionKind::Create).await?;
+  let mut out = ann;
+  out.file_path = from_workspace_to_root(state, &ws_rel_path);
+  state.events.publish(ServerEvent::AnnotationCreated { annotation: out.clone() });
+  Ok(out)
+}
+
+/// 重新挂接：把批注移到给定位置并恢复为 `active`（常用于 orphaned）；`selectedText` 缺省时从当前文件按新位置截取。
+/// 批注不存在返回 `None`；新位置超出文件或截取为空时报错。
+pub async fn reattach_annotation(state: &AppState, id: &str, body: ReattachAnnotation) -> Result<Option<Annotation>> {
+  let Some(mut ann) = state.store.get_annotation(id).await? else { return Ok(None) };
+  anyhow::ensure!(body.start_line >= 1 && body.end_line >= body.start_line, "invalid span");
+  if let Some(p) = body.file_path { ann.file_path = to_workspace_relative(state, &p); }
+  let root_rel = from_workspace_to_root(state, &ann.file_path);
+  ann.start_line = body.start_line;
+  ann.end_line = body.end_line;
+  ann.start_column = body.start_column;
+  ann.end_column = body.end_column;
+  ann.selected_text = match body.selected_text {
+    Some(t) => t,
+    None => {
+      let ff = ailoom_fs::read_file_full(&state.fs, &root_rel)?;
+      let lines: Vec<&str> = ff.content.split('\n').collect();
+      anyhow::ensure!(body.end_line as usize <= lines.len(), "span is out of file range");
+      span_text(
+        &lines,
+        body.start_line as usize,
+        body.end_line as usize,
+        body.start_column.unwrap_or(1).max(1) as usize,
+        body.end_column.map(|c| c as usize).unwrap_or(usize::MAX),
+      )
+    }
+  };
+  anyhow::ensure!(!ann.selected_text.trim().is_empty(), "selected text is empty");
+  fill_anchor_context(state, &root_rel, &mut ann, true);
+  ann.status = AnnotationStatus::Active;
+  ann.updated_at = now_rfc3339();
+  if !state.store.update_annotation_recorded(&ann, RevisionKind::Reattach).await? { return Ok(None); }
+  let mut out = ann;
+  out.file_path = root_rel;
+  state.events.publish(ServerEvent::AnnotationUpdated { annotation: out.clone() });
+  Ok(Some(out))
+}
+
+/// 解决/重新打开的结果
+pub enum Resolution {
+  Done(Box<Annotation>),
+  NotFound,
+  /// 只有 `resolved` 可以重新打开；携带当前状态
+  NotResolved(AnnotationStatus),
+}
+
+/// 标记解决（`Some`）或重新打开（`None`）。重新打开时按当前文件重新定位（resolved 期间不参与校验，位置可能已过时），
+/// 找不到则以 `orphaned` 打开而非 `active`
+pub async fn set_resolution(state: &AppState, id: &str, resolve: Option<ResolveAnnotation>) -> Result<Resolution> {
+  let Some(mut ann) = state.store.get_annotation(id).await? else { return Ok(Resolution::NotFound) };
+  let now = now_rfc3339();
+  let kind = if resolve.is_some() { RevisionKind::Resolve } else { RevisionKind::Reopen };
+  match resolve {
+    Some(r) => {
+      ann.status = AnnotationStatus::Resolved;
+      ann.resolved_at = Some(now.clone());
+      ann.resolved_by = Some(r.resolved_by.map(|b| b.trim().to_string()).filter(|b| !b.is_empty()).unwrap_or_else(|| "user".into()));
+      ann.resolution_note = r.note.filter(|n| !n.trim().is_empty());
+    }
+    None => {
+      if ann.status != AnnotationStatus::Resolved { return Ok(Resolution::NotResolved(ann.status)); }
+      let mut file = FileView::load(state, &from_workspace_to_root(state, &ann.file_path), None, None);
+      ann.status = match anchor_annotation(state, &mut file, &mut ann).await {
+        Anchoring::Lost => AnnotationStatus::Orphaned,
+        _ => AnnotationStatus::Active,
+      };
+      ann.resolved_at = None;
+      ann.resolved_by = None;
+      ann.resolution_note = None;
+    }
+  }
+  ann.updated_at = now;
+  if !state.store.update_annotation_recorded(&ann, kind).await? { return Ok(Resolution::NotFound); }
+  let mut out = ann;
+  out.file_path = from_workspace_to_root(state, &out.file_path);
+  state.events.publish(ServerEvent::AnnotationUpdated { annotation: out.clone() });
+  Ok(Resolution::Done(Box::new(out)))
+}
+
+/// 修订历史（新→旧），快照路径映射为 root 相对；批注不存在返回 `None`
+pub async fn annotation_history(state: &AppState, id: &str) -> Result<Option<Vec<AnnotationRevision>>> {
+  if state.store.get_annotation(id).await?.is_none() { return Ok(None); }
+  let revs = state.store.list_revisions(id).await?;
+  Ok(Some(
+    revs
+      .into_iter()
+      .map(|mut r| {
+        r.snapshot.file_path = from_workspace_to_root(state, &r.snapshot.file_path);
+        r
+      })
+      .collect(),
+  ))
+}
+
+/// 恢复到某次修订的快照（保留 id/createdAt，刷新 updatedAt），并记录一次 `restore` 修订；批注或修订不存在返回 `None`
+pub async fn restore_revision(state: &AppState, id: &str, revision_id: &str) -> Result<Option<Annotation>> {
+  let Some(current) = state.store.get_annotation(id).await? else { return Ok(None) };
+  let Some(rev) = state.store.get_revision(id, revision_id).await? else { return Ok(None) };
+  let mut ann = rev.snapshot;
+  ann.id = current.id;
+  ann.created_at = current.created_at;
+  ann.updated_at = now_rfc3339();
+  if !state.store.update_annotation_recorded(&ann, RevisionKind::Restore).await? { return Ok(None); }
+  let mut out = ann;
+  out.file_path = from_workspace_to_root(state, &out.file_path);
+  state.events.publish(ServerEvent::AnnotationUpdated { annotation: out.clone() });
+  Ok(Some(out))
+}
+
+/// 导入结果：在 `ImportReport` 之上附带重锚结论（仅含实际新增/更新的批注，id 为入库后的 id）
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportResultOut {
+  #[serde(flatten)]
+  pub report: ImportReport,
+  pub relocations: Vec<Relocation>,
+  pub orphaned_ids: Vec<String>,
+}
+
+/// 导入后逐条推送新增/更新事件（以入库后的内容为准）
+async fn publish_imported(state: &AppState, report: &ImportReport) -> Result<()> {
+  let added: HashSet<&String> = report.added_ids.iter().collect();
+  let ids: Vec<String> = report.added_ids.iter().chain(report.updated_ids.iter()).cloned().collect();
+  if ids.is_empty() { return Ok(()); }
+  for annotation in map_and_filter_annotations(state, state.store.list_annotations_by_ids(&ids).await?) {
+    if added.contains(&annotation.id) {
+      state.events.publish(ServerEvent::AnnotationCreated { annotation });
+    } else {
+      state.events.publish(ServerEvent::AnnotationUpdated { annotation });
+    }
+  }
+  Ok(())
+}
+
+/// 导入（入参为 workspace 相对路径）。`reanchor` 时先按当前代码对每条 active 批注重新定位：
+/// 命中则改写位置后入库，找不到则以 `orphaned` 入库；`updatedAt` 保持 bundle 中的值，以免影响合并策略
+pub async fn import_annotations(
+  state: &AppState,
+  mut anns: Vec<Annotation>,
+  strategy: ImportStrategy,
+  dry_run: bool,
+  reanchor: bool,
+) -> Result<ImportResultOut> {
+  let mut relocations: Vec<Relocation> = Vec::new();
+  let mut orphaned: Vec<String> = Vec::new();
+  if reanchor {
+    let mut files: HashMap<String, FileView> = HashMap::new();
+    for ann in anns.iter_mut() {
+      if ann.status != AnnotationStatus::Active || !in_root_scope(state, &ann.file_path) { continue; }
+      let file = files
+        .entry(ann.file_path.clone())
+        .or_insert_with(|| FileView::load(state, &from_workspace_to_root(state, &ann.file_path), None, None));
+      match anchor_annotation(state, file, ann).await {
+        Anchoring::Relocated(rel) => relocations.push(rel),
+        Anchoring::Lost => {
+          ann.status = AnnotationStatus::Orphaned;
+          orphaned.push(ann.id.clone());
+        }
+        Anchoring::Unchanged | Anchoring::Skipped => {}
+      }
+    }
+  }
+  let report = state.store.import_annotations(&anns, strategy, dry_run).await?;
+  if !dry_run { publish_imported(state, &report).await?; }
+  // 跳过的批注未入库，不报告；另存为新 id 的按新 id 报告
+  let stored: HashSet<&String> = report.added_ids.iter().chain(report.updated_ids.iter()).collect();
+  let final_id = |id: &str| report.id_map.get(id).cloned().unwrap_or_else(|| id.to_string());
+  let relocations = relocations
+    .into_iter()
+    .map(|mut r| { r.id = final_id(&r.id); r })
+    .filter(|r| stored.contains(&r.id))
+    .collect();
+  let orphaned_ids = orphaned.iter().map(|id| final_id(id)).filter(|id| stored.contains(id)).collect();
+  Ok(ImportResultOut { report, relocations, orphaned_ids })
+}
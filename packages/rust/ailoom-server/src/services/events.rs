@@ -0,0 +1,88 @@
+use crate::services::verification::VerifyResultOut;
+use ailoom_core::{Annotation, AnnotationComment};
+use tokio::sync::broadcast;
+
+// 变更事件：路径均为 root 相对（与 REST 返回保持一致）
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "type", rename_all_fields = "camelCase")]
+pub enum ServerEvent {
+  #[serde(rename = "annotation.created")]
+  AnnotationCreated { annotation: Annotation },
+  #[serde(rename = "annotation.updated")]
+  AnnotationUpdated { annotation: Annotation },
+  #[serde(rename = "annotation.deleted")]
+  AnnotationDeleted { id: String },
+  #[serde(rename = "comment.created")]
+  CommentCreated { comment: AnnotationComment },
+  #[serde(rename = "comment.updated")]
+  CommentUpdated { comment: AnnotationComment },
+  #[serde(rename = "comment.deleted")]
+  CommentDeleted { annotation_id: String, id: String },
+  #[serde(rename = "annotations.verified")]
+  Verified { file_path: String, result: VerifyResultOut },
+  #[serde(rename = "file.changed")]
+  FileChanged { path: String, digest: Option<String> },
+}
+
+impl ServerEvent {
+  pub fn name(&self) -> &'static str {
+    match self {
+      ServerEvent::AnnotationCreated { .. } => "annotation.created",
+      ServerEvent::AnnotationUpdated { .. } => "annotation.updated",
+      ServerEvent::AnnotationDeleted { .. } => "annotation.deleted",
+      ServerEvent::CommentCreated { .. } => "comment.created",
+      ServerEvent::CommentUpdated { .. } => "comment.updated",
+      ServerEvent::CommentDeleted { .. } => "comment.deleted",
+      ServerEvent::Verified { .. } => "annotations.verified",
+      ServerEvent::FileChanged { .. } => "file.changed",
+    }
+  }
+}
+
+/// 进程内广播：无订阅者时发送直接丢弃；慢订阅者落后超过容量将跳过旧事件
+#[derive(Clone)]
+pub struct EventBus {
+  tx: broadcast::Sender<ServerEvent>,
+}
+
+impl EventBus {
+  pub fn new() -> Self {
+    let (tx, _) = broadcast::channel(256);
+    Self { tx }
+  }
+
+  pub fn publish(&self, ev: ServerEvent) {
+    let _ = self.tx.send(ev);
+  }
+
+  pub fn subscribe(&self) -> broadcast::Receiver<ServerEvent> {
+    self.tx.subscribe()
+  }
+}
+
+impl Default for EventBus {
+  fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn bus_fans_out_in_order_and_serializes_with_type_tag() {
+    let bus = EventBus::new();
+    // 无订阅者时发送不报错
+    bus.publish(ServerEvent::AnnotationDeleted { id: "x".into() });
+    let (mut a, mut b) = (bus.subscribe(), bus.subscribe());
+    bus.publish(ServerEvent::AnnotationDeleted { id: "a1".into() });
+    bus.publish(ServerEvent::CommentDeleted { annotation_id: "a1".into(), id: "c1".into() });
+    for rx in [&mut a, &mut b] {
+      let ev = rx.try_recv().unwrap();
+      assert_eq!(ev.name(), "annotation.deleted");
+      assert_eq!(serde_json::to_value(&ev).unwrap(), serde_json::json!({"type": "annotation.deleted", "id": "a1"}));
+      let ev = rx.try_recv().unwrap();
+      assert_eq!(serde_json::to_value(&ev).unwrap(), serde_json::json!({"type": "comment.deleted", "annotationId": "a1", "id": "c1"}));
+      assert!(rx.try_recv().is_err());
+    }
+  }
+}
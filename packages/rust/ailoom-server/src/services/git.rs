@@ -0,0 +1,64 @@
+use crate::{
+  paths::{from_workspace_to_root, in_root_scope, to_workspace_relative},
+  state::AppState,
+};
+use ailoom_core::{FileDiff, GitFileStatus};
+use ailoom_git::GitError;
+use anyhow::Result;
+
+/// 当前 root 子树内的未提交文件（路径映射为 root 相对）；非 git 仓库返回空
+pub async fn uncommitted_in_root(state: &AppState, root_rel_paths: &[String]) -> Result<Vec<GitFileStatus>> {
+  let Some(repo) = state.git.clone() else { return Ok(vec![]) };
+  let pathspecs: Vec<String> = root_rel_paths.iter().map(|p| to_workspace_relative(state, p)).collect();
+  let all = tokio::task::spawn_blocking(move || repo.status(&pathspecs)).await??;
+  Ok(
+    all
+      .into_iter()
+      .filter(|f| in_root_scope(state, &f.path))
+      .map(|mut f| {
+        f.path = from_workspace_to_root(state, &f.path);
+        f.orig_path = f.orig_path.map(|p| from_workspace_to_root(state, &p));
+        f
+      })
+      .collect(),
+  )
+}
+
+/// 单文件相对 `base` 的 diff（入参/出参路径均为 root 相对）；非 git 仓库返回 `None`
+pub async fn diff_in_root(state: &AppState, root_rel_path: &str, base: &str) -> Result<Option<FileDiff>, GitError> {
+  let Some(repo) = state.git.clone() else { return Ok(None) };
+  let ws_rel = to_workspace_relative(state, root_rel_path);
+  let base = base.to_string();
+  let mut diff = tokio::task::spawn_blocking(move || repo.diff_file(&ws_rel, &base))
+    .await
+    .map_err(|e| GitError::Command(e.to_string()))??;
+  diff.path = from_workspace_to_root(state, &diff.path);
+  diff.old_path = diff.old_path.map(|p| from_workspace_to_root(state, &p));
+  Ok(Some(diff))
+}
+
+/// 历史中最多回溯的提交数（每个提交一次 `git show`）
+const HISTORY_MAX_COMMITS: usize = 50;
+
+/// 在文件的 git 历史中查找内容摘要等于 `digest` 的版本，返回该版本到当前工作区内容的 diff；
+/// 非仓库/未命中返回 `None`。入参为 workspace 相对路径。
+pub async fn diff_since_digest(state: &AppState, ws_rel_path: &str, digest: &str) -> Option<FileDiff> {
+  let repo = state.git.clone()?;
+  let path = ws_rel_path.to_string();
+  let digest = digest.to_string();
+  let res = tokio::task::spawn_blocking(move || -> Result<Option<FileDiff>, GitError> {
+    for commit in repo.file_commits(&path, HISTORY_MAX_COMMITS)? {
+      let Ok(bytes) = repo.show_file(&commit, &path) else { continue };
+      if ailoom_fs::content_digest(&bytes) == digest {
+        return repo.diff_file(&path, &commit).map(Some);
+      }
+    }
+    Ok(None)
+  })
+  .await;
+  match res {
+    Ok(Ok(d)) => d,
+    Ok(Err(e)) => { tracing::warn!("git history lookup failed for {}: {}", ws_rel_path, e); None }
+    Err(e) => { tracing::warn!("git history lookup panicked for {}: {}", ws_rel_path, e); None }
+  }
+}
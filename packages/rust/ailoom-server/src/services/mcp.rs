@@ -0,0 +1,285 @@
+use crate::{paths::map_and_filter_annotations, services, state::AppState};
+use ailoom_core::{AnnotationStatus, CreateAnnotation};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+// MCP（Model Context Protocol）最小实现：JSON-RPC 2.0，仅提供 tools 能力。
+// 传输层：stdio（逐行 JSON）与 streamable HTTP（POST /mcp，JSON 响应），二者共用 handle_message。
+
+const PROTOCOL_VERSION: &str = "2025-03-26";
+const SERVER_NAME: &str = "ai-loom";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+fn rpc_result(id: Value, result: Value) -> Value {
+  json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn rpc_error(id: Value, code: i64, message: &str) -> Value {
+  json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+pub fn parse_error() -> Value {
+  rpc_error(Value::Null, PARSE_ERROR, "parse error")
+}
+
+/// 处理单条 JSON-RPC 消息；通知（无 id）不产生响应
+pub async fn handle_message(state: &AppState, msg: Value) -> Option<Value> {
+  let id = msg.get("id").cloned();
+  let method = match msg.get("method").and_then(|m| m.as_str()) {
+    Some(m) => m.to_string(),
+    None => return id.map(|id| rpc_error(id, INVALID_REQUEST, "invalid request")),
+  };
+  let params = msg.get("params").cloned().unwrap_or(Value::Null);
+  let id = id?;
+  let resp = match method.as_str() {
+    "initialize" => {
+      let version = params.get("protocolVersion").and_then(|v| v.as_str()).unwrap_or(PROTOCOL_VERSION);
+      rpc_result(id, json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": { "listChanged": false } },
+        "serverInfo": { "name": SERVER_NAME, "version": env!("APP_VERSION") },
+      }))
+    }
+    "ping" => rpc_result(id, json!({})),
+    "tools/list" => rpc_result(id, json!({ "tools": tool_definitions() })),
+    "tools/call" => {
+      let name = match params.get("name").and_then(|v| v.as_str()) {
+        Some(n) => n.to_string(),
+        None => return Some(rpc_error(id, INVALID_PARAMS, "tool name is required")),
+      };
+      let args = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+      match call_tool(state, &name, args).await {
+        Ok(text) => rpc_result(id, json!({ "content": [{ "type": "text", "text": text }], "isError": false })),
+        Err(ToolError::UnknownTool) => rpc_error(id, INVALID_PARAMS, &format!("unknown tool: {name}")),
+        Err(ToolError::Failed(msg)) => rpc_result(id, json!({ "content": [{ "type": "text", "text": msg }], "isError": true })),
+      }
+    }
+    _ => rpc_error(id, METHOD_NOT_FOUND, &format!("method not found: {method}")),
+  };
+  Some(resp)
+}
+
+/// stdio 传输：每行一条 JSON-RPC 消息（或批量数组），响应逐行写回 stdout
+pub async fn run_stdio(state: AppState) -> anyhow::Result<()> {
+  let mut lines = BufReader::new(tokio::io::stdin()).lines();
+  let mut stdout = tokio::io::stdout();
+  while let Some(line) = lines.next_line().await? {
+    if line.trim().is_empty() { continue; }
+    let out = match serde_json::from_str::<Value>(&line) {
+      Ok(Value::Array(batch)) => {
+        let mut resps = Vec::new();
+        for m in batch {
+          if let Some(r) = handle_message(&state, m).await { resps.push(r); }
+        }
+        if resps.is_empty() { None } else { Some(Value::Array(resps)) }
+      }
+      Ok(msg) => handle_message(&state, msg).await,
+      Err(_) => Some(parse_error()),
+    };
+    if let Some(v) = out {
+      stdout.write_all(format!("{}\n", v).as_bytes()).await?;
+      stdout.flush().await?;
+    }
+  }
+  Ok(())
+}
+
+enum ToolError {
+  UnknownTool,
+  Failed(String),
+}
+
+impl<E: std::fmt::Display> From<E> for ToolError {
+  fn from(e: E) -> Self { ToolError::Failed(e.to_string()) }
+}
+
+fn tool_definitions() -> Value {
+  json!([
+    {
+      "name": "list_contexts",
+      "description": "List review annotations in the current root (sorted by createdAt desc).",
+      "inputSchema": {
+        "type": "object",
+        "properties": {
+          "limit": { "type": "integer", "minimum": 1, "maximum": 500 },
+          "offset": { "type": "integer", "minimum": 0 },
+          "status": {
+            "type": "array",
+            "items": { "type": "string", "enum": ["active", "orphaned", "resolved"] },
+            "description": "Statuses to include (default: active)"
+          }
+        }
+      }
+    },
+    {
+      "name": "get_context",
+      "description": "Get a single annotation by id.",
+      "inputSchema": {
+        "type": "object",
+        "properties": { "id": { "type": "string" } },
+        "required": ["id"]
+      }
+    },
+    {
+      "name": "stitch",
+      "description": "Stitch annotations into a prompt (sorted by priority, file and line, within a char and/or token budget).",
+      "inputSchema": {
+        "type": "object",
+        "properties": {
+          "templateId": { "type": "string", "description": "concise (default), detailed, or a custom template name (see GET /api/stitch/templates)" },
+          "annotationIds": { "type": "array", "items": { "type": "string" } },
+          "maxChars": { "type": "integer", "minimum": 200, "maximum": 200000 },
+          "maxTokens": { "type": "integer", "minimum": 50, "maximum": 200000 },
+          "tokenizer": { "type": "string", "enum": ["heuristic", "cl100k", "o200k"], "description": "Tokenizer used for maxTokens and token stats (default heuristic)" },
+          "includeThreads": { "type": "boolean", "description": "Include reply threads under each fully included annotation" },
+          "includeResolved": { "type": "boolean", "description": "Include resolved annotations (excluded by default)" },
+          "contextLines": { "type": "integer", "minimum": 0, "maximum": 100, "description": "Lines of live file context around each span; overlapping spans are merged" },
+          "expandBlock": { "type": "boolean", "description": "Expand each span to its enclosing function or block" },
+          "format": { "type": "string", "enum": ["markdown", "json", "xml"], "description": "Output format for built-in templates (default markdown)" }
+        }
+      }
+    },
+    {
+      "name": "read_file_chunk",
+      "description": "Read a text file by line range (path relative to root).",
+      "inputSchema": {
+        "type": "object",
+        "properties": {
+          "path": { "type": "string" },
+          "startLine": { "type": "integer", "minimum": 1 },
+          "maxLines": { "type": "integer", "minimum": 1, "maximum": 5000 }
+        },
+        "required": ["path"]
+      }
+    },
+    {
+      "name": "create_annotation",
+      "description": "Create an annotation on a file span (path relative to root).",
+      "inputSchema": {
+        "type": "object",
+        "properties": {
+          "filePath": { "type": "string" },
+          "startLine": { "type": "integer", "minimum": 1 },
+          "endLine": { "type": "integer", "minimum": 1 },
+          "startColumn": { "type": "integer", "minimum": 1 },
+          "endColumn": { "type": "integer", "minimum": 1 },
+          "selectedText": { "type": "string" },
+          "comment": { "type": "string" },
+          "tags": { "type": "array", "items": { "type": "string" } },
+          "priority": { "type": "string", "enum": ["P0", "P1", "P2"] },
+          "diffSide": { "type": "string", "enum": ["old", "new"], "description": "Side of the diff the span refers to" },
+          "baseRef": { "type": "string", "description": "Git ref the diff was taken against (e.g. HEAD)" }
+        },
+        "required": ["filePath", "startLine", "endLine", "selectedText", "comment"]
+      }
+    }
+  ])
+}
+
+#[derive(serde::Deserialize)]
+struct ListArgs { limit: Option<usize>, offset: Option<usize>, status: Option<Vec<AnnotationStatus>> }
+
+#[derive(serde::Deserialize)]
+struct GetArgs { id: String }
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StitchArgs { template_id: Option<String>, annotation_ids: Option<Vec<String>>, max_chars: Option<usize>, max_tokens: Option<usize>, tokenizer: Option<String>, include_threads: Option<bool>, include_resolved: Option<bool>, context_lines: Option<usize>, expand_block: Option<bool>, format: Option<String> }
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReadArgs { path: String, start_line: Option<usize>, max_lines: Option<usize> }
+
+async fn call_tool(state: &AppState, name: &str, args: Value) -> Result<String, ToolError> {
+  match name {
+    "list_contexts" => {
+      let a: ListArgs = serde_json::from_value(args)?;
+      let statuses = a.status.unwrap_or_else(|| vec![AnnotationStatus::Active]);
+      let anns = map_and_filter_annotations(state, state.store.list_annotations_with_status(&statuses).await?);
+      let total = anns.len();
+      let limit = a.limit.unwrap_or(50).clamp(1, 500);
+      let page: Vec<_> = anns.into_iter().skip(a.offset.unwrap_or(0)).take(limit).collect();
+      Ok(json!({ "total": total, "annotations": page }).to_string())
+    }
+    "get_context" => {
+      let a: GetArgs = serde_json::from_value(args)?;
+      let found = state.store.get_annotation(&a.id).await?.into_iter().collect();
+      match map_and_filter_annotations(state, found).pop() {
+        Some(ann) => Ok(serde_json::to_string(&ann)?),
+        None => Err(ToolError::Failed(format!("annotation not found: {}", a.id))),
+      }
+    }
+    "stitch" => {
+      let a: StitchArgs = serde_json::from_value(args)?;
+      let params = services::stitch::StitchParams {
+        template_id: a.template_id,
+        annotation_ids: a.annotation_ids.unwrap_or_default(),
+        max_chars: a.max_chars,
+        max_tokens: a.max_tokens,
+        tokenizer: a.tokenizer,
+        include_threads: a.include_threads.unwrap_or(false),
+        include_resolved: a.include_resolved.unwrap_or(false),
+        context_lines: a.context_lines,
+        expand_block: a.expand_block.unwrap_or(false),
+        format: a.format,
+      };
+      let r = services::stitch::run_stitch(state, params).await.map_err(|e| ToolError::Failed(e.to_string()))?;
+      Ok(json!({"prompt": r.prompt, "stats": services::stitch::stats_json(&r.stats) }).to_string())
+    }
+    "read_file_chunk" => {
+      let a: ReadArgs = serde_json::from_value(args)?;
+      let start = a.start_line.unwrap_or(1).max(1);
+      let max = a.max_lines.unwrap_or(2000).min(5000);
+      let chunk = ailoom_fs::read_file_chunk(&state.fs, &a.path, start, max)?;
+      Ok(serde_json::to_string(&chunk)?)
+    }
+    "create_annotation" => {
+      let body: CreateAnnotation = serde_json::from_value(args)?;
+      let ann = services::annotations::create_annotation(state, body).await?;
+      Ok(serde_json::to_string(&ann)?)
+    }
+    _ => Err(ToolError::UnknownTool),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  async fn call(state: &AppState, id: i64, method: &str, params: Value) -> Value {
+    handle_message(state, json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })).await.unwrap()
+  }
+
+  #[tokio::test]
+  async fn dispatches_initialize_list_and_call() {
+    let dir = std::env::temp_dir().join(format!("ailoom_test_{}_mcp", uuid::Uuid::new_v4()));
+    let state = AppState::for_test(&dir).await;
+    std::fs::write(dir.join("a.rs"), "fn main() {}\n").unwrap();
+
+    let r = call(&state, 1, "initialize", json!({ "protocolVersion": "2024-11-05" })).await;
+    assert_eq!((r["id"].clone(), r["result"]["protocolVersion"].clone()), (json!(1), json!("2024-11-05")));
+    assert_eq!(r["result"]["serverInfo"]["name"], SERVER_NAME);
+    // 通知不产生响应
+    assert!(handle_message(&state, json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await.is_none());
+
+    let r = call(&state, 2, "tools/list", json!({})).await;
+    let names: Vec<&str> = r["result"]["tools"].as_array().unwrap().iter().filter_map(|t| t["name"].as_str()).collect();
+    assert!(names.contains(&"stitch") && names.contains(&"create_annotation"));
+
+    let args = json!({ "filePath": "a.rs", "startLine": 1, "endLine": 1, "selectedText": "fn main() {}", "comment": "entry" });
+    let r = call(&state, 3, "tools/call", json!({ "name": "create_annotation", "arguments": args })).await;
+    assert_eq!(r["result"]["isError"], false);
+    let r = call(&state, 4, "tools/call", json!({ "name": "list_contexts", "arguments": {} })).await;
+    let listed: Value = serde_json::from_str(r["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+    assert_eq!((listed["total"].clone(), listed["annotations"][0]["comment"].clone()), (json!(1), json!("entry")));
+
+    let r = call(&state, 5, "tools/call", json!({ "name": "nope" })).await;
+    assert_eq!(r["error"]["code"], INVALID_PARAMS);
+    let r = call(&state, 6, "resources/list", json!({})).await;
+    assert_eq!(r["error"]["code"], METHOD_NOT_FOUND);
+  }
+}
@@ -1,2 +1,8 @@
 pub mod verification;
-
+pub mod annotations;
+pub mod mcp;
+pub mod events;
+pub mod watcher;
+pub mod git;
+pub mod stitch;
+pub mod workspaces;
@@ -0,0 +1,298 @@
+use crate::{paths::map_and_filter_annotations, services::annotations::now_rfc3339, state::AppState};
+use ailoom_core::{Annotation, AnnotationStatus, StitchTemplate};
+use ailoom_stitch::{self as stitch, ContextOptions, CustomTemplate, OutputFormat, StitchOptions, StitchResult, StitchStats, TemplateError, TemplateId};
+use anyhow::Result;
+
+/// 自定义模板文件目录（相对 workspace 根）：`<name>.<ext>`，文件名即模板名
+pub const TEMPLATES_DIR: &str = ".ailoom/templates";
+const TEMPLATE_FILE_EXTS: &[&str] = &["jinja", "j2", "md", "txt"];
+/// 取上下文时单个文件最多读取的行数
+const CONTEXT_FILE_MAX_LINES: usize = 20_000;
+/// `contextLines` 上限
+pub const CONTEXT_LINES_MAX: usize = 100;
+
+/// 组装拼接选项（REST 与 MCP 共用）：`include_threads` 时附带各批注的讨论线程
+pub async fn stitch_options(state: &AppState, anns: &[Annotation], include_threads: bool) -> Result<StitchOptions> {
+  let mut opts = StitchOptions::default();
+  if include_threads {
+    let ids: Vec<String> = anns.iter().map(|a| a.id.clone()).collect();
+    opts.threads = state.store.list_comments_for(&ids).await?;
+  }
+  Ok(opts)
+}
+
+/// 待拼接的批注（root 相对路径）：未指定 ids 时取全部 active；resolved 默认排除（显式 ids 亦然），
+/// `include_resolved` 时一并纳入；orphaned 仅在显式指定 id 时纳入
+pub async fn stitch_candidates(state: &AppState, ids: &[String], include_resolved: bool) -> Result<Vec<Annotation>> {
+  let listed = if ids.is_empty() {
+    let mut statuses = vec![AnnotationStatus::Active];
+    if include_resolved { statuses.push(AnnotationStatus::Resolved); }
+    state.store.list_annotations_with_status(&statuses).await?
+  } else {
+    state.store.list_annotations_by_ids(ids).await?
+  };
+  let anns = listed.into_iter().filter(|a| include_resolved || a.status != AnnotationStatus::Resolved).collect();
+  Ok(map_and_filter_annotations(state, anns))
+}
+
+/// 字符预算：显式 `max_chars` 优先；仅给出 `max_tokens` 时放宽到上限，由 token 预算约束；两者皆无时默认 4000
+pub fn effective_max_chars(max_chars: Option<usize>, max_tokens: Option<usize>) -> usize {
+  let default = if max_tokens.is_some() { 200_000 } else { 4000 };
+  max_chars.unwrap_or(default).clamp(200, 200_000)
+}
+
+/// 拼接统计的 JSON 形式（REST 与 MCP 共用）
+pub fn stats_json(s: &StitchStats) -> serde_json::Value {
+  let items: Vec<serde_json::Value> = s
+    .items
+    .iter()
+    .map(|i| {
+      let mut v = serde_json::json!({"id": i.id, "tokens": i.tokens, "level": i.level.as_str()});
+      if !i.merged_ids.is_empty() { v["mergedIds"] = serde_json::json!(i.merged_ids); }
+      v
+    })
+    .collect();
+  serde_json::json!({"total": s.total, "used": s.used, "truncated": s.truncated, "chars": s.chars, "tokens": s.tokens, "tokenizer": s.tokenizer, "items": items})
+}
+
+/// 一次拼接请求的参数（REST 查询串/请求体与 MCP 参数归一后）
+#[derive(Debug, Clone, Default)]
+pub struct StitchParams {
+  pub template_id: Option<String>,
+  pub annotation_ids: Vec<String>,
+  pub max_chars: Option<usize>,
+  pub max_tokens: Option<usize>,
+  pub tokenizer: Option<String>,
+  pub include_threads: bool,
+  pub include_resolved: bool,
+  /// 选区前后各带的上下文行数
+  pub context_lines: Option<usize>,
+  /// 扩展到包围选区的函数/代码块
+  pub expand_block: bool,
+  /// 输出格式：markdown（默认）/ json / xml；仅内置模板
+  pub format: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum StitchError {
+  /// 参数不合法（如未知分词器）
+  Invalid(String),
+  /// 模板不存在
+  TemplateNotFound(String),
+  /// 自定义模板编译或渲染失败
+  Template(TemplateError),
+  Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for StitchError {
+  fn from(e: anyhow::Error) -> Self { Self::Internal(e) }
+}
+
+impl std::fmt::Display for StitchError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Invalid(m) => f.write_str(m),
+      Self::TemplateNotFound(name) => write!(f, "template not found: {}", name),
+      Self::Template(e) => write!(f, "template error: {}", e),
+      Self::Internal(e) => write!(f, "{}", e),
+    }
+  }
+}
+
+/// 拼接（REST 与 MCP 共用）：解析模板与分词器，取候选批注，按预算生成
+pub async fn run_stitch(state: &AppState, p: StitchParams) -> Result<StitchResult, StitchError> {
+  let tokenizer = stitch::tokenizer_by_name(p.tokenizer.as_deref().unwrap_or("heuristic"))
+    .ok_or_else(|| StitchError::Invalid("tokenizer must be heuristic, cl100k or o200k".into()))?;
+  let format = match p.format.as_deref() {
+    Some(f) => OutputFormat::parse(f).ok_or_else(|| StitchError::Invalid("format must be markdown, json or xml".into()))?,
+    None => OutputFormat::Markdown,
+  };
+  let name = p.template_id.as_deref().unwrap_or("concise");
+  let template = match TemplateId::builtin(name) {
+    Some(t) => Resolved::Builtin(t),
+    None => {
+      let t = find_template(state, name).await?.ok_or_else(|| StitchError::TemplateNotFound(name.to_string()))?;
+      // 自定义模板自行决定输出格式
+      if format != OutputFormat::Markdown {
+        return Err(StitchError::Invalid("format applies to built-in templates only".into()));
+      }
+      Resolved::Custom(Box::new(CustomTemplate::compile(&t.name, t.body.as_deref().unwrap_or_default()).map_err(StitchError::Template)?))
+    }
+  };
+  let anns = stitch_candidates(state, &p.annotation_ids, p.include_resolved).await?;
+  let max_chars = effective_max_chars(p.max_chars, p.max_tokens);
+  let mut opts = stitch_options(state, &anns, p.include_threads).await?;
+  opts.max_tokens = p.max_tokens.map(|t| t.clamp(50, 200_000));
+  opts.tokenizer = tokenizer;
+  opts.format = format;
+  opts.context = ContextOptions { lines: p.context_lines.unwrap_or(0).min(CONTEXT_LINES_MAX), expand_block: p.expand_block };
+  // 自定义模板总能看到 before/after，因此同样需要文件内容
+  if opts.context.is_enabled() || matches!(template, Resolved::Custom(_)) {
+    opts.files = load_files(state, &anns);
+  }
+  match template {
+    Resolved::Builtin(tpl) => stitch::generate_prompt(tpl, max_chars, anns, &opts).map_err(|e| StitchError::Internal(e.into())),
+    Resolved::Custom(tpl) => stitch::generate_custom(&tpl, max_chars, anns, &opts).map_err(StitchError::Template),
+  }
+}
+
+enum Resolved {
+  Builtin(TemplateId),
+  Custom(Box<CustomTemplate>),
+}
+
+/// 批注涉及文件的当前内容（按 root 相对路径，经 `read_file_chunk` 读取）；不可读或非文本的文件略过
+fn load_files(state: &AppState, anns: &[Annotation]) -> std::collections::HashMap<String, String> {
+  let mut out = std::collections::HashMap::new();
+  for a in anns {
+    if out.contains_key(&a.file_path) { continue; }
+    if let Ok(chunk) = ailoom_fs::read_file_chunk(&state.fs, &a.file_path, 1, CONTEXT_FILE_MAX_LINES) {
+      out.insert(a.file_path.clone(), chunk.content);
+    }
+  }
+  out
+}
+
+/// 模板来源：内置、`.ailoom/templates` 文件、数据库；同名时数据库优先于文件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemplateSource {
+  Builtin,
+  File,
+  Db,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateOut {
+  pub name: String,
+  pub source: TemplateSource,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub description: Option<String>,
+  /// 内置模板无源码
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub body: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub created_at: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub updated_at: Option<String>,
+  /// 仅数据库模板可经 API 修改
+  pub read_only: bool,
+}
+
+impl From<StitchTemplate> for TemplateOut {
+  fn from(t: StitchTemplate) -> Self {
+    Self { name: t.name, source: TemplateSource::Db, description: t.description, body: Some(t.body), created_at: Some(t.created_at), updated_at: Some(t.updated_at), read_only: false }
+  }
+}
+
+fn builtin_templates() -> Vec<TemplateOut> {
+  [(TemplateId::Concise, "Compact list: location, comment and a short snippet"), (TemplateId::Detailed, "Full fields, snippet and optional reply threads")]
+    .into_iter()
+    .map(|(t, desc)| TemplateOut { name: t.name().into(), source: TemplateSource::Builtin, description: Some(desc.into()), body: None, created_at: None, updated_at: None, read_only: true })
+    .collect()
+}
+
+/// `.ailoom/templates` 下的模板文件（按名称排序）；与内置模板同名的文件被忽略
+fn file_templates(state: &AppState) -> Vec<TemplateOut> {
+  let Ok(dir) = std::fs::read_dir(state.workspace_root.join(TEMPLATES_DIR)) else { return Vec::new() };
+  let mut out: Vec<TemplateOut> = dir
+    .filter_map(|e| e.ok())
+    .filter_map(|e| {
+      let path = e.path();
+      let ext = path.extension()?.to_str()?;
+      if !TEMPLATE_FILE_EXTS.contains(&ext) || !path.is_file() { return None; }
+      let name = path.file_stem()?.to_str()?.to_string();
+      if TemplateId::builtin(&name).is_some() { return None; }
+      let body = std::fs::read_to_string(&path).ok()?;
+      Some(TemplateOut { name, source: TemplateSource::File, description: None, body: Some(body), created_at: None, updated_at: None, read_only: true })
+    })
+    .collect();
+  out.sort_by(|a, b| a.name.cmp(&b.name));
+  out
+}
+
+/// 全部可用模板：内置、数据库、未被数据库同名覆盖的文件
+pub async fn list_templates(state: &AppState) -> Result<Vec<TemplateOut>> {
+  let db: Vec<TemplateOut> = state.store.list_stitch_templates().await?.into_iter().map(TemplateOut::from).collect();
+  let files = file_templates(state).into_iter().filter(|f| !db.iter().any(|d| d.name == f.name));
+  let mut out = builtin_templates();
+  out.extend(db.iter().cloned());
+  out.extend(files);
+  Ok(out)
+}
+
+/// 按名称解析模板：内置名（大小写不敏感）→ 数据库 → 文件
+pub async fn find_template(state: &AppState, name: &str) -> Result<Option<TemplateOut>> {
+  if let Some(t) = TemplateId::builtin(name) {
+    return Ok(builtin_templates().into_iter().find(|b| b.name == t.name()));
+  }
+  if let Some(t) = state.store.get_stitch_template(name).await? {
+    return Ok(Some(t.into()));
+  }
+  Ok(file_templates(state).into_iter().find(|f| f.name == name))
+}
+
+/// 模板名：1–64 位字母、数字、`-`、`_`，且不可与内置模板同名
+pub fn validate_template_name(name: &str) -> Result<(), String> {
+  if name.is_empty() || name.len() > 64 || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+    return Err("name must be 1-64 characters of letters, digits, '-' or '_'".into());
+  }
+  if TemplateId::builtin(name).is_some() {
+    return Err(format!("'{}' is a built-in template", name));
+  }
+  Ok(())
+}
+
+pub async fn create_template(state: &AppState, name: String, body: String, description: Option<String>) -> Result<StitchTemplate> {
+  let now = now_rfc3339();
+  let t = StitchTemplate { name, description, body, created_at: now.clone(), updated_at: now };
+  state.store.insert_stitch_template(&t).await?;
+  Ok(t)
+}
+
+/// 更新数据库模板；不存在时返回 `None`
+pub async fn update_template(state: &AppState, name: &str, body: String, description: Option<String>) -> Result<Option<StitchTemplate>> {
+  let Some(mut t) = state.store.get_stitch_template(name).await? else { return Ok(None) };
+  t.body = body;
+  if description.is_some() { t.description = description; }
+  t.updated_at = now_rfc3339();
+  state.store.update_stitch_template(&t).await?;
+  Ok(Some(t))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const ITEM: &str = "{% macro item(item) %}\n{{ item.comment }}\n{% endmacro %}\n";
+
+  #[tokio::test]
+  async fn run_stitch_rejects_bad_params_and_resolves_templates() {
+    let dir = std::env::temp_dir().join(format!("ailoom_test_{}_stitch", uuid::Uuid::new_v4()));
+    let state = AppState::for_test(&dir).await;
+    std::fs::write(dir.join("a.rs"), "fn a() {}\n").unwrap();
+    let ann = Annotation { id: "a1".into(), file_path: "a.rs".into(), start_line: 1, end_line: 1, selected_text: "fn a() {}".into(), comment: "rename".into(), ..Default::default() };
+    state.store.insert_annotation(&ann).await.unwrap();
+    let run = |template: &str, tokenizer: Option<&str>, format: Option<&str>| {
+      let p = StitchParams { template_id: Some(template.into()), tokenizer: tokenizer.map(Into::into), format: format.map(Into::into), ..Default::default() };
+      let state = state.clone();
+      async move { run_stitch(&state, p).await }
+    };
+
+    assert!(matches!(run("concise", Some("bpe"), None).await, Err(StitchError::Invalid(_))));
+    assert!(matches!(run("concise", None, Some("yaml")).await, Err(StitchError::Invalid(_))));
+    assert!(matches!(run("nope", None, None).await, Err(StitchError::TemplateNotFound(_))));
+    assert!(run("concise", Some("cl100k"), Some("json")).await.unwrap().prompt.contains("rename"));
+
+    // 文件模板可用；数据库中的同名模板优先
+    std::fs::create_dir_all(dir.join(TEMPLATES_DIR)).unwrap();
+    std::fs::write(dir.join(TEMPLATES_DIR).join("team.jinja"), format!("{ITEM}{{% macro footer(stats) %}}\nfrom file\n{{% endmacro %}}\n")).unwrap();
+    assert_eq!(run("team", None, None).await.unwrap().prompt, "rename\nfrom file\n");
+    assert!(matches!(run("team", None, Some("json")).await, Err(StitchError::Invalid(_))));
+    create_template(&state, "team".into(), format!("{ITEM}{{% macro footer(stats) %}}\nfrom db\n{{% endmacro %}}\n"), None).await.unwrap();
+    assert_eq!(run("team", None, None).await.unwrap().prompt, "rename\nfrom db\n");
+    let listed: Vec<(String, TemplateSource)> = list_templates(&state).await.unwrap().into_iter().map(|t| (t.name, t.source)).collect();
+    assert_eq!(listed.iter().filter(|(n, _)| n == "team").collect::<Vec<_>>(), vec![&("team".to_string(), TemplateSource::Db)]);
+  }
+}
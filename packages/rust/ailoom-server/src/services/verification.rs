@@ -1,259 +1,537 @@
 use crate::{
-  paths::to_workspace_relative,
+  paths::{from_workspace_to_root, to_workspace_relative},
+  services::{annotations::now_rfc3339, events::ServerEvent, git::diff_since_digest},
   state::AppState,
 };
-use ailoom_core::Annotation;
+use ailoom_core::{Annotation, AnnotationStatus, FileDiff, RevisionKind};
 use anyhow::Result;
+use std::collections::HashMap;
 
-#[derive(Debug, serde::Serialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone)]
 pub struct VerifyResultOut {
   pub checked: usize,
   pub updated: usize,
-  pub deleted: usize,
+  pub orphaned: usize,
   pub skipped: usize,
   pub updated_ids: Vec<String>,
-  pub deleted_ids: Vec<String>,
+  pub orphaned_ids: Vec<String>,
   pub skipped_ids: Vec<String>,
+  pub relocations: Vec<Relocation>,
 }
 
-pub async fn verify_annotations_for_file(
-  state: &AppState,
-  root_rel_path: &str,
-  window: Option<usize>,
-  full_limit_bytes: Option<usize>,
-  remove_broken: bool,
-) -> Result<VerifyResultOut> {
-  let ws_rel = to_workspace_relative(state, root_rel_path);
-  let anns_all = state.store.list_annotations().await?;
-  let mut target: Vec<Annotation> = anns_all
-    .into_iter()
-    .filter(|a| a.file_path == ws_rel)
-    .collect();
-  if target.is_empty() {
-    return Ok(VerifyResultOut { checked: 0, updated: 0, deleted: 0, skipped: 0, updated_ids: vec![], deleted_ids: vec![], skipped_ids: vec![] });
-  }
-
-  let win = window.unwrap_or(40);
-  let full_limit = full_limit_bytes.unwrap_or(5 * 1024 * 1024);
-
-  // 尝试读取全文（≤阈值）
-  let mut full_text: Option<String> = None;
-  let mut full_digest: Option<String> = None;
-  match ailoom_fs::read_file_full(&state.fs, root_rel_path) {
-    Ok(ff) => {
-      if (ff.size as usize) <= full_limit {
-        full_digest = Some(ff.digest.clone());
-        full_text = Some(ff.content);
-      }
+/// 对外形态另附旧字段名 `deleted`/`deletedIds`（批注已不再删除，其值同 `orphaned`/`orphanedIds`），保留一个版本后移除
+impl serde::Serialize for VerifyResultOut {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    #[derive(serde::Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Wire<'a> {
+      checked: usize,
+      updated: usize,
+      orphaned: usize,
+      skipped: usize,
+      updated_ids: &'a [String],
+      orphaned_ids: &'a [String],
+      skipped_ids: &'a [String],
+      relocations: &'a [Relocation],
+      deleted: usize,
+      deleted_ids: &'a [String],
     }
-    Err(e) => {
-      let msg = e.to_string();
-      if msg == "OVER_LIMIT" || msg == "NON_TEXT" || msg == "INVALID_PATH" || msg == "NOT_FILE" {
-        // 忽略
-      } else {
-        tracing::warn!("verify: read_file_full failed for {}: {}", root_rel_path, msg);
-      }
+    Wire {
+      checked: self.checked,
+      updated: self.updated,
+      orphaned: self.orphaned,
+      skipped: self.skipped,
+      updated_ids: &self.updated_ids,
+      orphaned_ids: &self.orphaned_ids,
+      skipped_ids: &self.skipped_ids,
+      relocations: &self.relocations,
+      deleted: self.orphaned,
+      deleted_ids: &self.orphaned_ids,
     }
+    .serialize(serializer)
   }
+}
 
-  fn find_all_positions(text: &str, needle: &str) -> Vec<(usize, usize, usize, usize)> {
-    let mut res = Vec::new();
-    if needle.is_empty() || text.is_empty() { return res; }
-    let mut start_idx = 0usize;
-    while let Some(pos) = text[start_idx..].find(needle) {
-      let abs = start_idx + pos;
-      let before = &text[..abs];
-      let s_line = 1 + bytecount::count(before.as_bytes(), b'\n');
-      let last_nl = before.rfind('\n');
-      let s_col = match last_nl {
-        Some(i) => text[i+1..abs].chars().count() + 1,
-        None => before.chars().count() + 1,
-      };
-      let end_abs = abs + needle.len();
-      let before_end = &text[..end_abs];
-      let e_line = 1 + bytecount::count(before_end.as_bytes(), b'\n');
-      let last_nl2 = before_end.rfind('\n');
-      let e_col = match last_nl2 {
-        Some(i) => text[i+1..end_abs].chars().count() + 1,
-        None => before_end.chars().count() + 1,
-      };
-      res.push((s_line, e_line, s_col, e_col));
-      start_idx = end_abs;
+/// 一次重定位：原/新行号、定位方式（`history` | `text` | `fuzzy` | `boundary`）与置信度（0~1）
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Relocation {
+  pub id: String,
+  pub from_start_line: i64,
+  pub from_end_line: i64,
+  pub start_line: i64,
+  pub end_line: i64,
+  pub method: &'static str,
+  pub confidence: f64,
+}
+
+fn slice_by_char_cols(line: &str, start_col_1b: usize, end_col_1b: usize) -> String {
+  if line.is_empty() { return String::new(); }
+  let start_char = start_col_1b.saturating_sub(1);
+  let end_char = end_col_1b.saturating_sub(1);
+  let mut start_byte = None;
+  let mut end_byte = None;
+  for (ci, (bi, _ch)) in line.char_indices().enumerate() {
+    if start_byte.is_none() && ci == start_char { start_byte = Some(bi); }
+    if ci == end_char { end_byte = Some(bi); break; }
+  }
+  let sb = start_byte.unwrap_or(line.len());
+  let eb = end_byte.unwrap_or(line.len());
+  if sb > eb { return String::new(); }
+  line.get(sb..eb).unwrap_or("") .to_string()
+}
+
+/// 上下文摘要覆盖选区前/后的行数
+const CONTEXT_LINES: usize = 3;
+/// 候选位置：起止行与起止列（1 基，结束列不含）
+type Span = (usize, usize, usize, usize);
+/// 模糊候选的最低相似度（归一化编辑距离）
+const FUZZY_MIN_SIMILARITY: f64 = 0.75;
+/// 过短的选区编辑距离区分度太低（如 `one` 与 `gone`），不生成模糊候选
+const FUZZY_MIN_CHARS: usize = 8;
+
+/// 选区前/后各 `CONTEXT_LINES` 行（逐行去首尾空白）的摘要；`lines` 为整文件按 '\n' 切分的行，行号 1 基。
+pub fn context_hashes(lines: &[&str], start_line: usize, end_line: usize) -> (String, String) {
+  let digest = |from: usize, to: usize| {
+    let joined: Vec<&str> = lines.get(from..to.min(lines.len()).max(from)).unwrap_or(&[]).iter().map(|l| l.trim()).collect();
+    ailoom_fs::content_digest(joined.join("\n").as_bytes())
+  };
+  let s0 = start_line.max(1) - 1;
+  let pre = digest(s0.saturating_sub(CONTEXT_LINES), s0);
+  let post = digest(end_line.max(1), end_line.max(1) + CONTEXT_LINES);
+  (pre, post)
+}
+
+/// 按行/字符列（1 基，结束列不含；`usize::MAX` 表示行尾）截取选区文本
+pub(crate) fn span_text(lines: &[&str], s_line: usize, e_line: usize, s_col: usize, e_col: usize) -> String {
+  let mut buf = String::new();
+  for (i, l) in lines.iter().enumerate().take(e_line).skip(s_line.max(1) - 1) {
+    if i + 1 > s_line { buf.push('\n'); }
+    let from = if i + 1 == s_line { s_col } else { 1 };
+    let to = if i + 1 == e_line { e_col } else { usize::MAX };
+    buf.push_str(&slice_by_char_cols(l, from, to));
+  }
+  buf
+}
+
+/// 候选位置置信度（0~1）：前/后上下文摘要一致各 0.25（批注无摘要或全文不可用时按半分计），
+/// 加上候选文本与原 selectedText 的相似度（归一化编辑距离）× 0.5
+fn candidate_confidence(ann: &Annotation, lines: Option<&[&str]>, s_line: usize, e_line: usize, cand_text: &str) -> f64 {
+  let (pre, post) = match lines {
+    Some(ls) => { let (p, q) = context_hashes(ls, s_line, e_line); (Some(p), Some(q)) }
+    None => (None, None),
+  };
+  let agree = |want: &Option<String>, got: &Option<String>| match (want, got) {
+    (Some(w), Some(g)) => if w == g { 0.25 } else { 0.0 },
+    _ => 0.125,
+  };
+  agree(&ann.pre_context_hash, &pre) + agree(&ann.post_context_hash, &post) + similarity(cand_text, &ann.selected_text) * 0.5
+}
+
+fn similarity(a: &str, b: &str) -> f64 {
+  if a == b { return 1.0; }
+  // 编辑距离为 O(n·m)，超长选区只比较前 2000 个字符
+  let cap = |t: &str| t.chars().take(2000).collect::<String>();
+  strsim::normalized_levenshtein(&cap(a), &cap(b))
+}
+
+/// 模糊候选：`[from, to]` 内与选区行数相同的行窗口（沿用原列范围截取），相似度不低于阈值者入选。
+/// `lines[0]` 为第 `first` 行；返回位置与截取的文本
+fn fuzzy_candidates(ann: &Annotation, lines: &[&str], first: usize, from: usize, to: usize) -> Vec<(Span, String)> {
+  let span = ann.selected_text.split('\n').count();
+  let s_col = ann.start_column.unwrap_or(1).max(1) as usize;
+  let e_col = ann.end_column.map(|c| c as usize).unwrap_or(usize::MAX);
+  let want = ann.selected_text.chars().count();
+  let last = first + lines.len() - 1;
+  let mut out = Vec::new();
+  if want < FUZZY_MIN_CHARS { return out; }
+  for s_line in from.max(first)..=to.min(last) {
+    let e_line = s_line + span - 1;
+    if e_line > last { break; }
+    let text = span_text(&lines[s_line - first..=e_line - first], 1, span, s_col, e_col);
+    // 长度差是编辑距离的下界：差距过大直接跳过，免去逐个计算
+    let len = text.chars().count();
+    if (len.abs_diff(want) as f64) > (1.0 - FUZZY_MIN_SIMILARITY) * len.max(want) as f64 { continue; }
+    if similarity(&text, &ann.selected_text) >= FUZZY_MIN_SIMILARITY {
+      let end = if e_col == usize::MAX { lines[e_line - first].chars().count() + 1 } else { e_col };
+      out.push(((s_line, e_line, s_col, end), text));
     }
-    res
   }
+  out
+}
+
+/// 无法重新定位的批注不删除，标记为 orphaned（保留原行号与选中文本，供之后重新挂接）
+async fn orphan_annotation(state: &AppState, ann: &mut Annotation) -> Result<()> {
+  ann.status = AnnotationStatus::Orphaned;
+  ann.updated_at = now_rfc3339();
+  state.store.update_annotation_recorded(ann, RevisionKind::Orphan).await?;
+  Ok(())
+}
+
+/// 单条批注相对当前文件的定位结论
+pub(crate) enum Anchoring {
+  /// 原位置文本一致
+  Unchanged,
+  /// 已改写 `ann` 的位置（及必要时的 selectedText/上下文摘要/fileDigest）
+  Relocated(Relocation),
+  /// 找不到（或 selectedText 为空）
+  Lost,
+  /// 不参与校验（diff 基线侧）
+  Skipped,
+}
+
+/// 一个文件的当前内容视图：全文（≤阈值时）及其摘要，供同一文件上的多条批注共用
+pub(crate) struct FileView {
+  pub root_rel_path: String,
+  pub ws_rel_path: String,
+  pub full_text: Option<String>,
+  pub full_digest: Option<String>,
+  pub window: usize,
+  // 按批注记录的 fileDigest 缓存“历史版本→当前”的 diff（同一版本上的批注共用）
+  history: HashMap<String, Option<FileDiff>>,
+}
 
-  fn slice_by_char_cols(line: &str, start_col_1b: usize, end_col_1b: usize) -> String {
-    if line.is_empty() { return String::new(); }
-    let start_char = start_col_1b.saturating_sub(1);
-    let end_char = end_col_1b.saturating_sub(1);
-    let mut start_byte = None;
-    let mut end_byte = None;
-    for (ci, (bi, _ch)) in line.char_indices().enumerate() {
-      if start_byte.is_none() && ci == start_char { start_byte = Some(bi); }
-      if ci == end_char { end_byte = Some(bi); break; }
+impl FileView {
+  pub fn load(state: &AppState, root_rel_path: &str, window: Option<usize>, full_limit_bytes: Option<usize>) -> Self {
+    let full_limit = full_limit_bytes.unwrap_or(5 * 1024 * 1024);
+    // 尝试读取全文（≤阈值）
+    let mut full_text: Option<String> = None;
+    let mut full_digest: Option<String> = None;
+    match ailoom_fs::read_file_full(&state.fs, root_rel_path) {
+      Ok(ff) => {
+        if (ff.size as usize) <= full_limit {
+          full_digest = Some(ff.digest.clone());
+          full_text = Some(ff.content);
+        }
+      }
+      Err(e) => {
+        let msg = e.to_string();
+        if msg == "OVER_LIMIT" || msg == "NON_TEXT" || msg == "INVALID_PATH" || msg == "NOT_FILE" {
+          // 忽略
+        } else {
+          tracing::warn!("verify: read_file_full failed for {}: {}", root_rel_path, msg);
+        }
+      }
+    }
+    FileView {
+      root_rel_path: root_rel_path.to_string(),
+      ws_rel_path: to_workspace_relative(state, root_rel_path),
+      full_text,
+      full_digest,
+      window: window.unwrap_or(40),
+      history: HashMap::new(),
     }
-    let sb = start_byte.unwrap_or_else(|| line.len());
-    let eb = end_byte.unwrap_or_else(|| line.len());
-    if sb > eb { return String::new(); }
-    line.get(sb..eb).unwrap_or("") .to_string()
   }
+}
 
-  let mut checked = 0usize;
-  let mut updated = 0usize;
-  let mut deleted = 0usize;
-  let mut skipped = 0usize;
-  let mut updated_ids = Vec::new();
-  let mut deleted_ids = Vec::new();
-  let mut skipped_ids = Vec::new();
+fn find_all_positions(text: &str, needle: &str) -> Vec<(usize, usize, usize, usize)> {
+  let mut res = Vec::new();
+  if needle.is_empty() || text.is_empty() { return res; }
+  let mut start_idx = 0usize;
+  while let Some(pos) = text[start_idx..].find(needle) {
+    let abs = start_idx + pos;
+    let before = &text[..abs];
+    let s_line = 1 + bytecount::count(before.as_bytes(), b'\n');
+    let last_nl = before.rfind('\n');
+    let s_col = match last_nl {
+      Some(i) => text[i+1..abs].chars().count() + 1,
+      None => before.chars().count() + 1,
+    };
+    let end_abs = abs + needle.len();
+    let before_end = &text[..end_abs];
+    let e_line = 1 + bytecount::count(before_end.as_bytes(), b'\n');
+    let last_nl2 = before_end.rfind('\n');
+    let e_col = match last_nl2 {
+      Some(i) => text[i+1..end_abs].chars().count() + 1,
+      None => before_end.chars().count() + 1,
+    };
+    res.push((s_line, e_line, s_col, e_col));
+    start_idx = end_abs;
+  }
+  res
+}
 
-  for mut ann in target.drain(..) {
-    checked += 1;
-    if ann.selected_text.trim().is_empty() {
-      if remove_broken {
-        if let Err(e) = state.store.delete_annotation(&ann.id).await {
-          tracing::warn!("verify: delete(empty-selected) failed for {}: {}", ann.id, e);
-        } else { deleted += 1; deleted_ids.push(ann.id.clone()); }
-      } else { skipped += 1; skipped_ids.push(ann.id.clone()); }
-      continue;
+/// 在内存中对单条批注重新定位（不写库、不改 `updatedAt`/`status`，由调用方决定如何落库）
+pub(crate) async fn anchor_annotation(state: &AppState, file: &mut FileView, ann: &mut Annotation) -> Anchoring {
+  if ann.selected_text.trim().is_empty() { return Anchoring::Lost; }
+  // 批注在 diff 基线侧：选中文本本就不在工作区文件中，不参与校验
+  if ann.diff_side.as_deref() == Some("old") { return Anchoring::Skipped; }
+  let root_rel_path = file.root_rel_path.as_str();
+  let win = file.window;
+  let full_lines: Option<Vec<&str>> = file.full_text.as_ref().map(|t| t.split('\n').collect());
+
+  // 快速检查：窗口内按字符切片比较
+  let mut cur_text_same = false;
+  let win_start = if ann.start_line > 1 { (ann.start_line as usize).saturating_sub(1) } else { 1 };
+  let need = (ann.end_line.max(1) as usize).saturating_sub(win_start) + 1;
+  if let Ok(ch) = ailoom_fs::read_file_chunk(&state.fs, root_rel_path, win_start, need) {
+    let lines: Vec<&str> = ch.content.split('\n').collect();
+    let s_rel = (ann.start_line as usize).saturating_sub(ch.start_line).saturating_add(1).max(1);
+    let e_rel = (ann.end_line as usize).saturating_sub(ch.start_line).saturating_add(1).max(1).min(lines.len());
+    if s_rel <= e_rel && s_rel <= lines.len() {
+      let s_col = ann.start_column.unwrap_or(1).max(1) as usize;
+      let e_col = ann.end_column.unwrap_or(usize::MAX as i64) as usize;
+      let cur = if s_rel == e_rel {
+        let l = lines[s_rel - 1];
+        slice_by_char_cols(l, s_col, e_col)
+      } else {
+        let mut buf = String::new();
+        let first = lines[s_rel - 1];
+        buf.push_str(&slice_by_char_cols(first, s_col, usize::MAX));
+        for l in &lines[s_rel..(e_rel - 1)] { buf.push('\n'); buf.push_str(l); }
+        let last = lines[e_rel - 1];
+        buf.push('\n');
+        buf.push_str(&slice_by_char_cols(last, 1, e_col));
+        buf
+      };
+      cur_text_same = cur == ann.selected_text;
     }
+  }
+  if cur_text_same { return Anchoring::Unchanged; }
 
-    // 快速检查：窗口内按字符切片比较
-    let mut cur_text_same = false;
-    let win_start = if ann.start_line > 1 { (ann.start_line as usize).saturating_sub(1) } else { 1 };
-    let need = (ann.end_line - ann.start_line + 1).max(1) as usize;
-    match ailoom_fs::read_file_chunk(&state.fs, root_rel_path, win_start, need) {
-      Ok(ch) => {
-        let lines: Vec<&str> = ch.content.split('\n').collect();
-        let s_rel = (ann.start_line as usize).saturating_sub(ch.start_line).saturating_add(1).max(1);
-        let e_rel = (ann.end_line as usize).saturating_sub(ch.start_line).saturating_add(1).max(1).min(lines.len());
-        if s_rel <= e_rel && s_rel <= lines.len() {
-          let s_col = ann.start_column.unwrap_or(1).max(1) as usize;
-          let e_col = ann.end_column.unwrap_or(usize::MAX as i64) as usize;
-          let cur = if s_rel == e_rel {
-            let l = lines[s_rel - 1];
-            slice_by_char_cols(l, s_col, e_col)
-          } else {
-            let mut buf = String::new();
-            let first = lines[s_rel - 1];
-            buf.push_str(&slice_by_char_cols(first, s_col, usize::MAX));
-            for i in (s_rel)..(e_rel - 1) { buf.push_str("\n"); buf.push_str(lines[i]); }
-            let last = lines[e_rel - 1];
-            buf.push_str("\n");
-            buf.push_str(&slice_by_char_cols(last, 1, e_col));
-            buf
-          };
-          cur_text_same = cur == ann.selected_text;
+  let mut new_pos: Option<(usize, usize, usize, usize)> = None;
+  let mut method = "text";
+
+  // 历史重锚：fileDigest 命中 git 历史中的某个版本时，按该版本→当前内容的 diff 映射行号；
+  // 首/尾行均未被改写才采用（选区内部改动随之刷新 selectedText），否则回落到文本搜索
+  if let (Some(d), Some(cur_digest)) = (ann.file_digest.clone(), file.full_digest.as_deref()) {
+    if d != cur_digest && state.git.is_some() {
+      if !file.history.contains_key(&d) {
+        let diff = diff_since_digest(state, &file.ws_rel_path, &d).await;
+        file.history.insert(d.clone(), diff);
+      }
+      if let Some(Some(diff)) = file.history.get(&d) {
+        let s = ailoom_git::map_old_line(diff, ann.start_line.max(1) as usize);
+        let e = ailoom_git::map_old_line(diff, ann.end_line.max(1) as usize);
+        if let (Some(s), Some(e)) = (s, e) {
+          if e >= s {
+            new_pos = Some((s, e, ann.start_column.unwrap_or(1).max(1) as usize, ann.end_column.unwrap_or(1).max(1) as usize));
+            method = "history";
+          }
         }
       }
-      Err(_) => {}
     }
-    if cur_text_same { continue; }
-
-    // 窗口搜索（±win）
-    let mut new_pos: Option<(usize, usize, usize, usize)> = None;
-    let win_start2 = (ann.start_line as usize).saturating_sub(win);
-    let win_end2 = (ann.end_line as usize + win).max(win_start2 + 1);
-    let max_lines = win_end2.saturating_sub(win_start2) + 1;
-    if let Ok(ch) = ailoom_fs::read_file_chunk(&state.fs, root_rel_path, win_start2.max(1), max_lines) {
-      let content = ch.content.clone();
-      let occs = find_all_positions(&content, &ann.selected_text);
-      if !occs.is_empty() {
-        let anchor = ann.start_line.max(1) as usize;
-        let mut best = occs[0];
-        let mut best_dist = best.0.abs_diff(anchor);
-        for o in occs.into_iter().skip(1) { let d = o.0.abs_diff(anchor); if d < best_dist { best = o; best_dist = d; } }
-        let s_abs = ch.start_line + best.0 - 1;
-        let e_abs = ch.start_line + best.1 - 1;
-        new_pos = Some((s_abs, e_abs, best.2, best.3));
+  }
+
+  // 文本候选：精确出现位置（全文可用时取全部，否则取窗口 ±win 内）+ 窗口内的模糊候选（选区被小幅改写）；
+  // 按置信度择优，同分时优先落在原窗口内者，再取离原位置最近者
+  let win_start2 = (ann.start_line as usize).saturating_sub(win);
+  let win_end2 = (ann.end_line as usize + win).max(win_start2 + 1);
+  let max_lines = win_end2.saturating_sub(win_start2) + 1;
+  if new_pos.is_none() {
+    let chunk = match full_lines {
+      Some(_) => None,
+      None => ailoom_fs::read_file_chunk(&state.fs, root_rel_path, win_start2.max(1), max_lines).ok(),
+    };
+    let (src, first): (Vec<&str>, usize) = match (&full_lines, &chunk) {
+      (Some(ls), _) => (ls.clone(), 1),
+      (None, Some(ch)) => (ch.content.split('\n').collect(), ch.start_line),
+      (None, None) => (Vec::new(), 1),
+    };
+    let exact: Vec<Span> = match (&file.full_text, &chunk) {
+      (Some(all), _) => find_all_positions(all, &ann.selected_text),
+      (None, Some(ch)) => find_all_positions(&ch.content, &ann.selected_text)
+        .into_iter()
+        .map(|o| (ch.start_line + o.0 - 1, ch.start_line + o.1 - 1, o.2, o.3))
+        .collect(),
+      (None, None) => vec![],
+    };
+    // 模糊候选的文本为 Some（精确候选即原 selectedText）
+    let mut cands: Vec<(Span, Option<String>)> = Vec::new();
+    if !src.is_empty() {
+      for (o, text) in fuzzy_candidates(ann, &src, first, win_start2.max(1), win_end2) {
+        if !exact.iter().any(|e| e.0 == o.0) { cands.push((o, Some(text))); }
       }
     }
+    cands.extend(exact.into_iter().map(|o| (o, None)));
+    let anchor = ann.start_line.max(1) as usize;
+    let in_window = |line: usize| line >= win_start2 && line <= win_end2;
+    let mut best: Option<(Span, bool, f64)> = None;
+    for (o, text) in cands {
+      let conf = candidate_confidence(ann, full_lines.as_deref(), o.0, o.1, text.as_deref().unwrap_or(&ann.selected_text));
+      let better = match best {
+        None => true,
+        Some((b, _, bc)) => {
+          conf > bc + 1e-9
+            || ((conf - bc).abs() <= 1e-9
+              && (in_window(o.0), std::cmp::Reverse(o.0.abs_diff(anchor))) > (in_window(b.0), std::cmp::Reverse(b.0.abs_diff(anchor))))
+        }
+      };
+      if better { best = Some((o, text.is_some(), conf)); }
+    }
+    if let Some((o, fuzzy, _)) = best {
+      new_pos = Some(o);
+      if fuzzy { method = "fuzzy"; }
+    }
+  }
 
-    // 边界锚定（多行选区）
-    if new_pos.is_none() {
-      if ann.selected_text.contains('\n') {
-        if let Ok(ch2) = ailoom_fs::read_file_chunk(&state.fs, root_rel_path, win_start2.max(1), max_lines) {
-          let lines_vec: Vec<&str> = ch2.content.split('\n').collect();
-          let head = ann.selected_text.split('\n').next().unwrap_or("").trim();
-          let tail = ann.selected_text.rsplit('\n').next().unwrap_or("").trim();
-          if !head.is_empty() && !tail.is_empty() {
-            let mut best_s: Option<(usize, usize, usize)> = None;
-            let mut best_s_dist = usize::MAX;
-            for (i, ln) in lines_vec.iter().enumerate() {
-              if let Some(byte_pos) = ln.find(head) {
-                let abs_line = ch2.start_line + i;
-                let dist = abs_line.abs_diff(ann.start_line.max(1) as usize);
-                if dist < best_s_dist {
-                  let s_col = ln[..byte_pos].chars().count() + 1;
-                  let e_byte = byte_pos + head.len();
-                  let e_col = ln[..e_byte].chars().count() + 1;
-                  best_s = Some((abs_line, s_col, e_col));
-                  best_s_dist = dist;
-                }
-              }
+  // 边界锚定（多行选区）
+  if new_pos.is_none() && ann.selected_text.contains('\n') {
+    if let Ok(ch2) = ailoom_fs::read_file_chunk(&state.fs, root_rel_path, win_start2.max(1), max_lines) {
+      let lines_vec: Vec<&str> = ch2.content.split('\n').collect();
+      let head = ann.selected_text.split('\n').next().unwrap_or("").trim();
+      let tail = ann.selected_text.rsplit('\n').next().unwrap_or("").trim();
+      if !head.is_empty() && !tail.is_empty() {
+        let mut best_s: Option<(usize, usize, usize)> = None;
+        let mut best_s_dist = usize::MAX;
+        for (i, ln) in lines_vec.iter().enumerate() {
+          if let Some(byte_pos) = ln.find(head) {
+            let abs_line = ch2.start_line + i;
+            let dist = abs_line.abs_diff(ann.start_line.max(1) as usize);
+            if dist < best_s_dist {
+              let s_col = ln[..byte_pos].chars().count() + 1;
+              let e_byte = byte_pos + head.len();
+              let e_col = ln[..e_byte].chars().count() + 1;
+              best_s = Some((abs_line, s_col, e_col));
+              best_s_dist = dist;
             }
-            if let Some((s_abs, s_col1, _)) = best_s {
-              let mut best_e: Option<(usize, usize, usize)> = None;
-              let mut best_e_dist = usize::MAX;
-              for (j, ln) in lines_vec.iter().enumerate().skip(s_abs.saturating_sub(ch2.start_line)) {
-                if let Some(byte_pos) = ln.find(tail) {
-                  let abs_line = ch2.start_line + j;
-                  let dist = abs_line.abs_diff(ann.end_line.max(1) as usize);
-                  if dist < best_e_dist {
-                    let s_col = ln[..byte_pos].chars().count() + 1;
-                    let e_byte = byte_pos + tail.len();
-                    let e_col = ln[..e_byte].chars().count() + 1;
-                    best_e = Some((abs_line, s_col, e_col));
-                    best_e_dist = dist;
-                  }
-                }
-              }
-              if let Some((e_abs, _e_scol, e_col1)) = best_e {
-                if e_abs >= s_abs { new_pos = Some((s_abs, e_abs, s_col1, e_col1)); }
+          }
+        }
+        if let Some((s_abs, s_col1, _)) = best_s {
+          let mut best_e: Option<(usize, usize, usize)> = None;
+          let mut best_e_dist = usize::MAX;
+          for (j, ln) in lines_vec.iter().enumerate().skip(s_abs.saturating_sub(ch2.start_line)) {
+            if let Some(byte_pos) = ln.find(tail) {
+              let abs_line = ch2.start_line + j;
+              let dist = abs_line.abs_diff(ann.end_line.max(1) as usize);
+              if dist < best_e_dist {
+                let s_col = ln[..byte_pos].chars().count() + 1;
+                let e_byte = byte_pos + tail.len();
+                let e_col = ln[..e_byte].chars().count() + 1;
+                best_e = Some((abs_line, s_col, e_col));
+                best_e_dist = dist;
               }
             }
           }
+          if let Some((e_abs, _e_scol, e_col1)) = best_e {
+            if e_abs >= s_abs { new_pos = Some((s_abs, e_abs, s_col1, e_col1)); method = "boundary"; }
+          }
         }
       }
     }
+  }
 
-    // 全文
-    if new_pos.is_none() {
-      if let Some(ref all) = full_text {
-        let occs = find_all_positions(all, &ann.selected_text);
-        if !occs.is_empty() {
-          let anchor = ann.start_line.max(1) as usize;
-          let mut best = occs[0];
-          let mut best_dist = best.0.abs_diff(anchor);
-          for o in occs.into_iter().skip(1) { let d = o.0.abs_diff(anchor); if d < best_dist { best = o; best_dist = d; } }
-          new_pos = Some(best);
-        }
+  let Some((s_line, e_line, s_col, e_col)) = new_pos else { return Anchoring::Lost };
+  let from_start_line = ann.start_line;
+  let from_end_line = ann.end_line;
+  ann.start_line = s_line as i64;
+  ann.end_line = e_line as i64;
+  if ann.start_column.is_some() { ann.start_column = Some(s_col as i64); }
+  if ann.end_column.is_some() { ann.end_column = Some(e_col as i64); }
+  let confidence;
+  if let Some(ref lines) = full_lines {
+    let cur = span_text(
+      lines,
+      s_line,
+      e_line,
+      ann.start_column.unwrap_or(1).max(1) as usize,
+      ann.end_column.map(|c| c as usize).unwrap_or(usize::MAX),
+    );
+    confidence = candidate_confidence(ann, Some(lines), s_line, e_line, &cur);
+    // 历史映射/边界锚定时选区内容可能已变化：以当前内容为准；上下文摘要同步为新位置
+    if method != "text" { ann.selected_text = cur; }
+    let (pre, post) = context_hashes(lines, s_line, e_line);
+    ann.pre_context_hash = Some(pre);
+    ann.post_context_hash = Some(post);
+  } else {
+    // 无全文（超出全文上限）：读取新位置及前后上下文所在的片段计算，行号换算为片段内相对行
+    let first = s_line.saturating_sub(CONTEXT_LINES).max(1);
+    let chunk = ailoom_fs::read_file_chunk(&state.fs, root_rel_path, first, e_line - first + 1 + CONTEXT_LINES).ok();
+    confidence = match chunk {
+      Some(ch) => {
+        let lines: Vec<&str> = ch.content.split('\n').collect();
+        let (s_rel, e_rel) = (s_line - first + 1, e_line - first + 1);
+        let cur = span_text(
+          &lines,
+          s_rel,
+          e_rel,
+          ann.start_column.unwrap_or(1).max(1) as usize,
+          ann.end_column.map(|c| c as usize).unwrap_or(usize::MAX),
+        );
+        candidate_confidence(ann, Some(&lines), s_rel, e_rel, &cur)
       }
-    }
+      None => candidate_confidence(ann, None, s_line, e_line, &ann.selected_text),
+    };
+  }
+  if let Some(ref d) = file.full_digest { ann.file_digest = Some(d.clone()); }
+  Anchoring::Relocated(Relocation {
+    id: ann.id.clone(),
+    from_start_line,
+    from_end_line,
+    start_line: ann.start_line,
+    end_line: ann.end_line,
+    method,
+    confidence: (confidence * 100.0).round() / 100.0,
+  })
+}
 
-    if let Some((s_line, e_line, s_col, e_col)) = new_pos {
-      ann.start_line = s_line as i64;
-      ann.end_line = e_line as i64;
-      if ann.start_column.is_some() { ann.start_column = Some(s_col as i64); }
-      if ann.end_column.is_some() { ann.end_column = Some(e_col as i64); }
-      ann.updated_at = time::OffsetDateTime::now_utc()
-        .format(&time::format_description::well_known::Rfc3339)
-        .unwrap_or_else(|_| ann.updated_at);
-      if let Some(ref d) = full_digest { ann.file_digest = Some(d.clone()); }
-      if let Err(e) = state.store.update_annotation(&ann).await {
-        tracing::warn!("verify: update failed for {}: {}", ann.id, e);
-      } else { updated += 1; updated_ids.push(ann.id.clone()); }
-    } else if remove_broken {
-      if let Err(e) = state.store.delete_annotation(&ann.id).await {
-        tracing::warn!("verify: delete failed for {}: {}", ann.id, e);
-      } else { deleted += 1; deleted_ids.push(ann.id.clone()); }
-    } else {
-      skipped += 1; skipped_ids.push(ann.id.clone());
+/// 逐条通知批注变更（入参为入库形态，推送前映射回 root 相对路径）
+fn publish_updated(state: &AppState, ann: &Annotation) {
+  let mut annotation = ann.clone();
+  annotation.file_path = from_workspace_to_root(state, &annotation.file_path);
+  state.events.publish(ServerEvent::AnnotationUpdated { annotation });
+}
+
+/// 校验一个文件上的 active 批注并落库重定位结果；`mark_orphaned` 为假时找不到的批注保持原样（仅计入 checked）
+pub async fn verify_annotations_for_file(
+  state: &AppState,
+  root_rel_path: &str,
+  window: Option<usize>,
+  full_limit_bytes: Option<usize>,
+  mark_orphaned: bool,
+) -> Result<VerifyResultOut> {
+  let ws_rel = to_workspace_relative(state, root_rel_path);
+  let anns_all = state.store.list_annotations_with_status(&[AnnotationStatus::Active]).await?;
+  let mut target: Vec<Annotation> = anns_all
+    .into_iter()
+    .filter(|a| a.file_path == ws_rel)
+    .collect();
+  if target.is_empty() {
+    return Ok(VerifyResultOut { checked: 0, updated: 0, orphaned: 0, skipped: 0, updated_ids: vec![], orphaned_ids: vec![], skipped_ids: vec![], relocations: vec![] });
+  }
+
+  let mut file = FileView::load(state, root_rel_path, window, full_limit_bytes);
+  let mut checked = 0usize;
+  let mut updated = 0usize;
+  let mut orphaned = 0usize;
+  let mut skipped = 0usize;
+  let mut updated_ids = Vec::new();
+  let mut orphaned_ids = Vec::new();
+  let mut skipped_ids = Vec::new();
+  let mut relocations = Vec::new();
+
+  for mut ann in target.drain(..) {
+    checked += 1;
+    match anchor_annotation(state, &mut file, &mut ann).await {
+      Anchoring::Unchanged => {}
+      Anchoring::Skipped => { skipped += 1; skipped_ids.push(ann.id.clone()); }
+      Anchoring::Relocated(rel) => {
+        ann.updated_at = now_rfc3339();
+        if let Err(e) = state.store.update_annotation_recorded(&ann, RevisionKind::Relocate).await {
+          tracing::warn!("verify: update failed for {}: {}", ann.id, e);
+        } else {
+          publish_updated(state, &ann);
+          updated += 1;
+          updated_ids.push(ann.id.clone());
+          relocations.push(rel);
+        }
+      }
+      Anchoring::Lost if mark_orphaned => {
+        if let Err(e) = orphan_annotation(state, &mut ann).await {
+          tracing::warn!("verify: orphan failed for {}: {}", ann.id, e);
+        } else {
+          publish_updated(state, &ann);
+          orphaned += 1;
+          orphaned_ids.push(ann.id.clone());
+        }
+      }
+      Anchoring::Lost => { skipped += 1; skipped_ids.push(ann.id.clone()); }
     }
   }
 
-  Ok(VerifyResultOut { checked, updated, deleted, skipped, updated_ids, deleted_ids, skipped_ids })
+  let out = VerifyResultOut { checked, updated, orphaned, skipped, updated_ids, orphaned_ids, skipped_ids, relocations };
+  state.events.publish(ServerEvent::Verified { file_path: root_rel_path.to_string(), result: out.clone() });
+  Ok(out)
 }
 
 #[cfg(test)]
@@ -261,20 +539,11 @@ mod tests {
   use super::*;
   use crate::state::AppState;
   use ailoom_core::Annotation;
-  use ailoom_fs::FsConfig;
-  use ailoom_store::Store;
-  use std::{fs, path::PathBuf};
-
-  fn write_file(p: &PathBuf, content: &str) { fs::create_dir_all(p.parent().unwrap()).unwrap(); fs::write(p, content).unwrap(); }
-
-  async fn make_state(tmpdir: &PathBuf) -> AppState {
-    let root = tmpdir.clone();
-    let workspace_root = root.clone();
-    let fs_cfg = FsConfig::new(root.clone());
-    let db = tmpdir.join("ailoom.db");
-    let store = Store::connect_path(&db, &workspace_root.to_string_lossy()).await.unwrap();
-    AppState { fs: fs_cfg, store, root, workspace_root }
-  }
+  use std::{fs, path::Path};
+
+  fn write_file(p: &Path, content: &str) { fs::create_dir_all(p.parent().unwrap()).unwrap(); fs::write(p, content).unwrap(); }
+
+  async fn make_state(tmpdir: &Path) -> AppState { AppState::for_test(tmpdir).await }
 
   fn new_ann(id: &str, file_path_ws_rel: &str, start: i64, end: i64, selected: &str) -> Annotation {
     Annotation {
@@ -291,15 +560,21 @@ mod tests {
       file_digest: None,
       tags: None,
       priority: Some("P1".into()),
+      diff_side: None,
+      base_ref: None,
+      status: AnnotationStatus::Active,
+      resolved_at: None,
+      resolved_by: None,
+      resolution_note: None,
       created_at: "2020-01-01T00:00:00Z".into(),
       updated_at: "2020-01-01T00:00:00Z".into(),
     }
   }
 
-  // 最小关键路径：找不到选区时会删除批注
+  // 最小关键路径：找不到选区时标记为 orphaned（不删除）
 
   #[tokio::test]
-  async fn verify_deletes_when_not_found() {
+  async fn verify_orphans_when_not_found() {
     let tmpdir = std::env::temp_dir().join(format!("ailoom_test_{}_2", uuid::Uuid::new_v4()));
     fs::create_dir_all(&tmpdir).unwrap();
     let state = make_state(&tmpdir).await;
@@ -312,8 +587,201 @@ mod tests {
     // 改成完全不同的内容
     write_file(&fp, "xxx\nyyy\nzzz\n");
 
+    let mut rx = state.events.subscribe();
+    let r = verify_annotations_for_file(&state, file_rel, Some(40), Some(5 * 1024 * 1024), true).await.unwrap();
+    assert_eq!((r.orphaned, r.orphaned_ids.as_slice()), (1, ["b1".to_string()].as_slice()));
+    // 逐条事件在汇总事件之前
+    match rx.try_recv().unwrap() {
+      ServerEvent::AnnotationUpdated { annotation } => assert_eq!((annotation.id.as_str(), annotation.status), ("b1", AnnotationStatus::Orphaned)),
+      ev => panic!("unexpected {}", ev.name()),
+    }
+    assert_eq!(rx.try_recv().unwrap().name(), "annotations.verified");
+    let got = state.store.get_annotation("b1").await.unwrap().unwrap();
+    assert_eq!((got.status, got.start_line, got.selected_text.as_str()), (AnnotationStatus::Orphaned, 1, "one\ntwo"));
+    let orphans = state.store.list_annotations_with_status(&[AnnotationStatus::Orphaned]).await.unwrap();
+    assert_eq!(orphans.len(), 1);
+
+    // 再次校验不处理 orphaned
     let r = verify_annotations_for_file(&state, file_rel, Some(40), Some(5 * 1024 * 1024), true).await.unwrap();
-    assert_eq!(r.deleted, 1);
-    assert!(state.store.get_annotation("b1").await.unwrap().is_none());
+    assert_eq!(r.checked, 0);
+  }
+
+  // fileDigest 命中 git 历史版本时按 diff 映射，而非就近匹配到新插入的同文本
+  #[tokio::test]
+  async fn verify_reanchors_through_git_history() {
+    let tmpdir = std::env::temp_dir().join(format!("ailoom_test_{}_3", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&tmpdir).unwrap();
+    let git = |args: &[&str]| {
+      let ok = std::process::Command::new("git")
+        .arg("-C").arg(&tmpdir)
+        .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+        .args(args)
+        .output().map(|o| o.status.success()).unwrap_or(false);
+      assert!(ok, "git {:?} failed", args);
+    };
+    let file_rel = "dup.txt";
+    let fp = tmpdir.join(file_rel);
+    let old = "dup\na\nb\nc\n";
+    write_file(&fp, old);
+    git(&["init", "-q"]);
+    git(&["add", file_rel]);
+    git(&["commit", "-qm", "init"]);
+    let mut state = make_state(&tmpdir).await;
+    state.git = ailoom_git::GitRepo::open(&tmpdir);
+    let mut ann = new_ann("h1", file_rel, 1, 1, "dup");
+    ann.file_digest = Some(ailoom_fs::content_digest(old.as_bytes()));
+    state.store.insert_annotation(&ann).await.unwrap();
+
+    // 顶部插入新行（含同文本 "dup"），原选区下移到第 5 行
+    write_file(&fp, "x\ndup\ny\nz\ndup\na\nb\nc\n");
+
+    let r = verify_annotations_for_file(&state, file_rel, Some(40), Some(5 * 1024 * 1024), true).await.unwrap();
+    assert_eq!(r.updated, 1);
+    let got = state.store.get_annotation("h1").await.unwrap().unwrap();
+    assert_eq!((got.start_line, got.end_line, got.selected_text.as_str()), (5, 5, "dup"));
+  }
+
+  // 同文本多处出现时，上下文摘要一致的候选优先于离原位置更近的候选
+  #[tokio::test]
+  async fn verify_prefers_candidate_with_matching_context() {
+    let tmpdir = std::env::temp_dir().join(format!("ailoom_test_{}_4", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&tmpdir).unwrap();
+    let state = make_state(&tmpdir).await;
+    let file_rel = "ctx.txt";
+    let fp = tmpdir.join(file_rel);
+    let body = "a1\na2\na3\ndup\nb1\nb2\nb3\nf1\nf2\nf3\nf4\nf5\nc1\nc2\nc3\ndup\nd1\nd2\nd3\n";
+    write_file(&fp, body);
+    let lines: Vec<&str> = body.split('\n').collect();
+    let (pre, post) = context_hashes(&lines, 16, 16);
+    let mut ann = new_ann("c1", file_rel, 16, 16, "dup");
+    ann.pre_context_hash = Some(pre);
+    ann.post_context_hash = Some(post);
+    state.store.insert_annotation(&ann).await.unwrap();
+
+    // 顶部插入 10 行：第一个 dup（14 行）离原位置更近，但上下文属于第二个（26 行）
+    write_file(&fp, &format!("{}{}", "n\n".repeat(10), body));
+
+    let r = verify_annotations_for_file(&state, file_rel, Some(40), Some(5 * 1024 * 1024), true).await.unwrap();
+    assert_eq!(r.updated, 1);
+    assert_eq!(state.store.get_annotation("c1").await.unwrap().unwrap().start_line, 26);
+    let rel = &r.relocations[0];
+    assert_eq!((rel.from_start_line, rel.start_line, rel.method), (16, 26, "text"));
+    assert!((rel.confidence - 1.0).abs() < 1e-9);
+  }
+
+  // 选区被小幅改写：无精确出现位置时取窗口内相似度达标的行窗口，并同步 selectedText
+  #[tokio::test]
+  async fn verify_relocates_slightly_edited_text_fuzzily() {
+    let tmpdir = std::env::temp_dir().join(format!("ailoom_test_{}_6", uuid::Uuid::new_v4()));
+    let state = make_state(&tmpdir).await;
+    let file_rel = "fz.rs";
+    let fp = tmpdir.join(file_rel);
+    let body = "use a;\n\nfn compute(x: i32) -> i32 {\n  x + 1\n}\n";
+    write_file(&fp, body);
+    let lines: Vec<&str> = body.split('\n').collect();
+    let (pre, post) = context_hashes(&lines, 3, 4);
+    let mut ann = new_ann("f1", file_rel, 3, 4, "fn compute(x: i32) -> i32 {\n  x + 1");
+    ann.pre_context_hash = Some(pre);
+    ann.post_context_hash = Some(post);
+    state.store.insert_annotation(&ann).await.unwrap();
+
+    write_file(&fp, "use a;\nuse b;\n\nfn compute(x: i64) -> i64 {\n  x + 1\n}\n");
+    let r = verify_annotations_for_file(&state, file_rel, Some(40), Some(5 * 1024 * 1024), true).await.unwrap();
+    assert_eq!((r.updated, r.orphaned), (1, 0));
+    let rel = &r.relocations[0];
+    assert_eq!((rel.start_line, rel.end_line, rel.method), (4, 5, "fuzzy"));
+    assert!(rel.confidence > 0.5 && rel.confidence < 1.0);
+    let got = state.store.get_annotation("f1").await.unwrap().unwrap();
+    assert_eq!(got.selected_text, "fn compute(x: i64) -> i64 {\n  x + 1");
+
+    // 过短的选区不做模糊匹配
+    let short = new_ann("f2", file_rel, 1, 1, "use c;");
+    state.store.insert_annotation(&short).await.unwrap();
+    let r = verify_annotations_for_file(&state, file_rel, Some(40), Some(5 * 1024 * 1024), true).await.unwrap();
+    assert_eq!(r.orphaned_ids, vec!["f2".to_string()]);
+
+    // 改动过大则不作为候选
+    write_file(&fp, "use a;\n\nstruct Other;\nimpl Other {}\n");
+    let r = verify_annotations_for_file(&state, file_rel, Some(40), Some(5 * 1024 * 1024), true).await.unwrap();
+    assert_eq!(r.orphaned_ids, vec!["f1".to_string()]);
+  }
+
+  // 超出全文上限时（无全文）边界锚定的置信度按新位置所在片段计算，而不是报 0
+  #[tokio::test]
+  async fn verify_scores_relocations_without_full_text() {
+    let tmpdir = std::env::temp_dir().join(format!("ailoom_test_{}_conf", uuid::Uuid::new_v4()));
+    let state = make_state(&tmpdir).await;
+    let file_rel = "bd.rs";
+    let fp = tmpdir.join(file_rel);
+    let body = "a\nb\nc\nfn f() {\n  one();\n}\nx\ny\nz\n";
+    write_file(&fp, body);
+    let lines: Vec<&str> = body.split('\n').collect();
+    let (pre, post) = context_hashes(&lines, 4, 6);
+    let mut ann = new_ann("b1", file_rel, 4, 6, "fn f() {\n  one();\n}");
+    ann.pre_context_hash = Some(pre);
+    ann.post_context_hash = Some(post);
+    state.store.insert_annotation(&ann).await.unwrap();
+
+    // 下移两行且选区中间被整体改写：精确与模糊候选都落空，由首/尾行锚定
+    write_file(&fp, "n\nn\na\nb\nc\nfn f() {\n  two(); three(); four();\n}\nx\ny\nz\n");
+    let r = verify_annotations_for_file(&state, file_rel, Some(40), Some(1), true).await.unwrap();
+    let rel = &r.relocations[0];
+    assert_eq!((rel.start_line, rel.end_line, rel.method), (6, 8, "boundary"));
+    // 前后上下文一致（0.5），选区文本部分相似
+    assert!(rel.confidence > 0.5 && rel.confidence < 1.0, "{}", rel.confidence);
+  }
+
+  // 多个精确出现、置信度相同：优先原窗口内者，即便窗口外的离原起始行更近
+  #[tokio::test]
+  async fn verify_prefers_exact_match_inside_original_window() {
+    let tmpdir = std::env::temp_dir().join(format!("ailoom_test_{}_7", uuid::Uuid::new_v4()));
+    let state = make_state(&tmpdir).await;
+    let file_rel = "dup.txt";
+    let fp = tmpdir.join(file_rel);
+    let block: Vec<String> = (1..=11).map(|i| format!("line {i}")).collect();
+    let block = block.join("\n");
+    write_file(&fp, &format!("{}\n{}\n", "x\n".repeat(9), "y\n".repeat(10)));
+    state.store.insert_annotation(&new_ann("w1", file_rel, 10, 20, &block)).await.unwrap();
+
+    // 原位置（10..20）已无该文本；出现在 1..11（距起始行 9，窗口外）与 22..32（距 12，窗口 8..22 内）
+    write_file(&fp, &format!("{block}\n{}{block}\n", "z\n".repeat(10)));
+    let r = verify_annotations_for_file(&state, file_rel, Some(2), Some(5 * 1024 * 1024), true).await.unwrap();
+    let rel = &r.relocations[0];
+    assert_eq!((rel.start_line, rel.end_line, rel.method), (22, 32, "text"));
+  }
+
+  // 导入时重锚：能找到的改写位置后入库，找不到的以 orphaned 入库；updatedAt 保持 bundle 中的值
+  #[tokio::test]
+  async fn import_reanchors_before_storing() {
+    let tmpdir = std::env::temp_dir().join(format!("ailoom_test_{}_5", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&tmpdir).unwrap();
+    let state = make_state(&tmpdir).await;
+    write_file(&tmpdir.join("imp.txt"), "new
+lines
+fn main() {}
+");
+    let moved = new_ann("i1", "imp.txt", 1, 1, "fn main() {}");
+    let gone = new_ann("i2", "imp.txt", 2, 2, "not here");
+
+    let mut rx = state.events.subscribe();
+    let out = crate::services::annotations::import_annotations(&state, vec![moved, gone], ailoom_core::ImportStrategy::NewerWins, false, true)
+      .await
+      .unwrap();
+    assert_eq!(out.report.added, 2);
+    assert_eq!((out.relocations.len(), out.relocations[0].start_line), (1, 3));
+    assert_eq!(out.orphaned_ids, vec!["i2".to_string()]);
+    let got = state.store.get_annotation("i1").await.unwrap().unwrap();
+    assert_eq!((got.start_line, got.status, got.updated_at.as_str()), (3, AnnotationStatus::Active, "2020-01-01T00:00:00Z"));
+    assert_eq!(state.store.get_annotation("i2").await.unwrap().unwrap().status, AnnotationStatus::Orphaned);
+    // 每条新增批注一个事件，内容为入库后的位置与状态
+    let mut created = Vec::new();
+    while let Ok(ev) = rx.try_recv() {
+      match ev {
+        ServerEvent::AnnotationCreated { annotation } => created.push((annotation.id, annotation.start_line, annotation.status)),
+        ev => panic!("unexpected {}", ev.name()),
+      }
+    }
+    created.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(created, vec![("i1".to_string(), 3, AnnotationStatus::Active), ("i2".to_string(), 2, AnnotationStatus::Orphaned)]);
   }
 }
@@ -0,0 +1,120 @@
+use crate::{
+  paths::to_workspace_relative,
+  services::{events::ServerEvent, verification::verify_annotations_for_file},
+  state::AppState,
+};
+use ailoom_core::AnnotationStatus;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::{
+  collections::{BTreeSet, HashMap},
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+const DEBOUNCE_MS: u64 = 500;
+
+/// 本进程写入的文件（root 相对路径 → 写入后的 digest）。`PUT /api/file` 已自行推送 `file.changed` 并校验，
+/// 监听到的改动若内容仍等于写入时的 digest 即跳过，避免同一次保存校验两遍、推送两次事件。
+#[derive(Clone, Default)]
+pub struct OwnWrites(Arc<Mutex<HashMap<String, String>>>);
+
+impl OwnWrites {
+  /// 路径按监听事件的口径归一（root 下的真实路径），以免 `./a.rs` 与 `a.rs` 对不上
+  pub fn record(&self, root: &Path, rel_path: &str, digest: &str) {
+    let rel = root.join(rel_path).canonicalize().ok()
+      .and_then(|p| p.strip_prefix(root).ok().map(|r| r.to_string_lossy().to_string()))
+      .unwrap_or_else(|| rel_path.to_string());
+    self.0.lock().unwrap_or_else(|e| e.into_inner()).insert(rel, digest.to_string());
+  }
+
+  /// 内容仍为本进程写入的版本时返回 true；已被外部改写（或删除）则清除记录
+  pub fn is_own(&self, root: &Path, rel_path: &str) -> bool {
+    let mut writes = self.0.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(digest) = writes.get(rel_path) else { return false };
+    let current = std::fs::read(root.join(rel_path)).ok().map(|b| ailoom_fs::content_digest(&b));
+    if current.as_deref() == Some(digest.as_str()) { return true; }
+    writes.remove(rel_path);
+    false
+  }
+}
+
+/// 监听 root 下的外部改动（IDE/Agent 等），去抖后增量刷新文件索引，并对“有批注的文件”执行一次校验。
+/// 忽略规则与 `list_dir` 一致；`.ailoom/`（项目内 DB）、原子写临时文件与本进程刚保存的文件不参与。
+pub fn spawn_watcher(state: AppState) -> anyhow::Result<()> {
+  let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Vec<PathBuf>>();
+  let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+    // 读取类事件（含校验自身读文件）不计入，避免自触发
+    Ok(ev) if matches!(ev.kind, EventKind::Access(_)) => {}
+    Ok(ev) => { let _ = tx.send(ev.paths); }
+    Err(e) => tracing::warn!("watcher: {}", e),
+  })?;
+  watcher.watch(&state.root, RecursiveMode::Recursive)?;
+  tracing::info!("watcher: watching {}", state.root.display());
+
+  tokio::spawn(async move {
+    // 持有 watcher，随任务存活
+    let _watcher = watcher;
+    while let Some(first) = rx.recv().await {
+      // 去抖：收到首批事件后持续合并，直到静默 DEBOUNCE_MS
+      let mut paths: BTreeSet<PathBuf> = first.into_iter().collect();
+      while let Ok(Some(more)) = tokio::time::timeout(Duration::from_millis(DEBOUNCE_MS), rx.recv()).await {
+        paths.extend(more);
+      }
+      let rels: Vec<String> = paths.iter().filter_map(|p| watched_rel_path(&state, p)).collect();
+      state.files.refresh_paths(&state.fs, &rels);
+      // 目录本身的事件不关心；已删除路径按文件处理。忽略规则每批只解析一次
+      let mut ignore = ailoom_fs::IgnoreMatcher::new(&state.fs);
+      let changed: BTreeSet<String> = rels
+        .into_iter()
+        .filter(|rel| !state.root.join(rel).is_dir() && !ignore.is_ignored(rel, false))
+        .filter(|rel| !state.own_writes.is_own(&state.root, rel))
+        .collect();
+      if changed.is_empty() { continue; }
+      let annotated: BTreeSet<String> = match state.store.list_annotations_with_status(&[AnnotationStatus::Active]).await {
+        Ok(v) => v.into_iter().map(|a| a.file_path).collect(),
+        Err(e) => { tracing::warn!("watcher: list annotations failed: {}", e); continue; }
+      };
+      for rel in changed {
+        state.events.publish(ServerEvent::FileChanged { path: rel.clone(), digest: None });
+        if !annotated.contains(&to_workspace_relative(&state, &rel)) { continue; }
+        if let Err(e) = verify_annotations_for_file(&state, &rel, Some(40), Some(5 * 1024 * 1024), false).await {
+          tracing::warn!("watcher: verify failed for {}: {}", rel, e);
+        }
+      }
+    }
+  });
+  Ok(())
+}
+
+fn watched_rel_path(state: &AppState, abs: &std::path::Path) -> Option<String> {
+  let rel = abs.strip_prefix(&state.root).ok()?;
+  if rel.as_os_str().is_empty() || rel.starts_with(".ailoom") { return None; }
+  let name = rel.file_name()?.to_string_lossy();
+  if name.starts_with(".ailoom.tmp.") { return None; }
+  Some(rel.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn own_writes_match_until_content_changes() {
+    let root = std::env::temp_dir().join(format!("ailoom_test_{}_own", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    let root = root.canonicalize().unwrap();
+    std::fs::write(root.join("src/a.rs"), "fn a() {}\n").unwrap();
+    let writes = OwnWrites::default();
+    writes.record(&root, "./src/a.rs", &ailoom_fs::content_digest(b"fn a() {}\n"));
+    assert!(writes.is_own(&root, "src/a.rs"));
+    // 同一次保存的多批事件都跳过
+    assert!(writes.is_own(&root, "src/a.rs"));
+    assert!(!writes.is_own(&root, "src/b.rs"));
+    // 外部改写后不再视为本进程写入，即便之后内容改回
+    std::fs::write(root.join("src/a.rs"), "fn b() {}\n").unwrap();
+    assert!(!writes.is_own(&root, "src/a.rs"));
+    std::fs::write(root.join("src/a.rs"), "fn a() {}\n").unwrap();
+    assert!(!writes.is_own(&root, "src/a.rs"));
+  }
+}
@@ -0,0 +1,137 @@
+use crate::{
+  paths::map_and_filter_annotations,
+  services::events::ServerEvent,
+  state::AppState,
+};
+use ailoom_core::{TransferMode, WorkspaceSummary};
+use anyhow::Result;
+
+/// 根目录状态：只有确认不存在才算 `missing`；其他 I/O 错误（无权限、网络盘/移动盘未就绪等）为 `unknown`，不会被清理
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RootState {
+  Present,
+  Missing,
+  Unknown,
+}
+
+pub fn root_state(root_path: &str) -> RootState {
+  match std::fs::metadata(root_path) {
+    Ok(m) if m.is_dir() => RootState::Present,
+    // 原位置已变成文件
+    Ok(_) => RootState::Missing,
+    Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::NotADirectory) => RootState::Missing,
+    Err(_) => RootState::Unknown,
+  }
+}
+
+/// 工作区概况，附带是否为当前工作区、根目录状态（`rootExists` 即 `rootState == present`）
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceOut {
+  #[serde(flatten)]
+  pub summary: WorkspaceSummary,
+  pub current: bool,
+  pub root_exists: bool,
+  pub root_state: RootState,
+}
+
+pub async fn list_workspaces(state: &AppState) -> Result<Vec<WorkspaceOut>> {
+  let current = state.store.workspace_id().to_string();
+  let all = state.store.list_workspaces().await?;
+  Ok(
+    all
+      .into_iter()
+      .map(|w| {
+        let root_state = root_state(&w.root_path);
+        WorkspaceOut { current: w.id == current, root_exists: root_state == RootState::Present, root_state, summary: w }
+      })
+      .collect(),
+  )
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferOut {
+  pub mode: TransferMode,
+  pub count: usize,
+  /// 源 id → 目标工作区中的 id（move 时相同）
+  pub ids: Vec<TransferredId>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferredId {
+  pub from: String,
+  pub to: String,
+}
+
+/// 转移批注；涉及当前工作区时推送对应的 created/deleted 事件
+pub async fn transfer_annotations(state: &AppState, from: &str, to: &str, ids: &[String], mode: TransferMode) -> Result<TransferOut> {
+  let pairs = state.store.transfer_annotations(from, to, ids, mode).await?;
+  let current = state.store.workspace_id();
+  if from == current && mode == TransferMode::Move {
+    for (id, _) in &pairs { state.events.publish(ServerEvent::AnnotationDeleted { id: id.clone() }); }
+  }
+  if to == current {
+    let new_ids: Vec<String> = pairs.iter().map(|(_, to)| to.clone()).collect();
+    let anns = state.store.list_annotations_by_ids(&new_ids).await?;
+    for annotation in map_and_filter_annotations(state, anns) { state.events.publish(ServerEvent::AnnotationCreated { annotation }); }
+  }
+  Ok(TransferOut {
+    mode,
+    count: pairs.len(),
+    ids: pairs.into_iter().map(|(from, to)| TransferredId { from, to }).collect(),
+  })
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PruneOut {
+  pub pruned: Vec<WorkspaceSummary>,
+  pub removed_annotations: i64,
+  /// 根目录无法判断（`unknown`）而保留的工作区
+  pub unreachable: Vec<WorkspaceSummary>,
+  pub dry_run: bool,
+}
+
+/// 删除根目录已不存在的工作区（连同其批注）；当前工作区与根目录状态未知的不受影响
+pub async fn prune_workspaces(state: &AppState, dry_run: bool) -> Result<PruneOut> {
+  let current = state.store.workspace_id().to_string();
+  let mut stale = Vec::new();
+  let mut unreachable = Vec::new();
+  for w in state.store.list_workspaces().await?.into_iter().filter(|w| w.id != current) {
+    match root_state(&w.root_path) {
+      RootState::Present => {}
+      RootState::Missing => stale.push(w),
+      RootState::Unknown => unreachable.push(w),
+    }
+  }
+  let mut removed_annotations = 0i64;
+  for w in &stale {
+    if dry_run { removed_annotations += w.annotation_count; } else { removed_annotations += state.store.delete_workspace(&w.id).await? as i64; }
+  }
+  Ok(PruneOut { pruned: stale, removed_annotations, unreachable, dry_run })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(unix)]
+  #[test]
+  fn unreadable_roots_are_unknown_not_missing() {
+    let dir = std::env::temp_dir().join(format!("ailoom_test_{}_roots", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("file"), "").unwrap();
+    // 符号链接环：ELOOP，既不是“存在”也不能断定“不存在”
+    std::os::unix::fs::symlink(dir.join("loop_b"), dir.join("loop_a")).unwrap();
+    std::os::unix::fs::symlink(dir.join("loop_a"), dir.join("loop_b")).unwrap();
+    let at = |p: &str| root_state(&dir.join(p).to_string_lossy());
+    assert_eq!(root_state(&dir.to_string_lossy()), RootState::Present);
+    assert_eq!(at("missing"), RootState::Missing);
+    assert_eq!(at("file/sub"), RootState::Missing);
+    assert_eq!(at("loop_a/repo"), RootState::Unknown);
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+}
@@ -1,4 +1,6 @@
-use ailoom_fs::FsConfig;
+use crate::services::{events::EventBus, watcher::OwnWrites};
+use ailoom_fs::{FileIndex, FsConfig};
+use ailoom_git::GitRepo;
 use ailoom_store::Store;
 use std::path::PathBuf;
 
@@ -8,5 +10,20 @@ pub struct AppState {
   pub store: Store,
   pub root: PathBuf,
   pub workspace_root: PathBuf,
+  pub events: EventBus,
+  pub files: FileIndex,
+  pub git: Option<GitRepo>,
+  pub own_writes: OwnWrites,
 }
 
+
+#[cfg(test)]
+impl AppState {
+  /// 测试用：以临时目录为 root/workspace，DB 放在其下
+  pub async fn for_test(dir: &std::path::Path) -> Self {
+    std::fs::create_dir_all(dir).unwrap();
+    let root = dir.to_path_buf();
+    let store = Store::connect_path(&dir.join("ailoom.db"), &root.to_string_lossy(), None).await.unwrap();
+    AppState { fs: FsConfig::new(root.clone()), store, workspace_root: root.clone(), root, events: Default::default(), files: Default::default(), git: None, own_writes: Default::default() }
+  }
+}
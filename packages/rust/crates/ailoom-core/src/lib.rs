@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DirEntry {
@@ -6,6 +7,8 @@ pub struct DirEntry {
     pub path: String, // relative to root
     pub r#type: EntryType,
     pub size: Option<u64>,
+    #[serde(rename = "gitStatus", default, skip_serializing_if = "Option::is_none")]
+    pub git_status: Option<GitStatus>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,6 +18,27 @@ pub enum EntryType {
     Dir,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GitStatus {
+    Modified,
+    Added,
+    Deleted,
+    Renamed,
+    Untracked,
+    Conflicted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitFileStatus {
+    pub path: String,
+    pub status: GitStatus,
+    pub staged: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orig_path: Option<String>, // renamed from
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FileChunk {
@@ -28,6 +52,77 @@ pub struct FileChunk {
     pub truncated: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchMatch {
+    pub path: String, // relative to root
+    pub line: usize,
+    pub column: usize,     // 1-based, in chars
+    pub end_column: usize, // exclusive, in chars
+    pub text: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchSummary {
+    pub files_scanned: usize,
+    pub files_skipped: usize,
+    pub matches: usize,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileMatch {
+    pub path: String, // relative to root
+    pub score: i64,
+    pub positions: Vec<usize>, // matched char indices in `path`
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffLineKind {
+    Context,
+    Add,
+    Delete,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    /// 对应的当前文件行号：context/add 即 `new_line`；delete 挂在其后一行（文件末尾时为最后一行）
+    pub anchor_line: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffHunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub header: String,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileDiff {
+    pub path: String,
+    pub base: String,
+    pub base_commit: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_path: Option<String>,
+    pub binary: bool,
+    pub hunks: Vec<DiffHunk>,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum CoreError {
     #[error("invalid path")]
@@ -58,7 +153,43 @@ pub fn guess_language_by_ext(path: &str) -> String {
         .to_string()
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// 当前 UTC 时间（RFC3339，固定毫秒精度，便于按字符串排序）。批注与修订的时间戳都用它生成
+pub fn now_rfc3339() -> String {
+    const FORMAT: &[time::format_description::FormatItem<'static>] =
+        time::macros::format_description!("[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:3]Z");
+    time::OffsetDateTime::now_utc().format(FORMAT).unwrap_or_default()
+}
+
+/// 批注生命周期：`orphaned` 为校验时无法在文件中重新定位（保留待重新挂接）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnotationStatus {
+    #[default]
+    Active,
+    Orphaned,
+    Resolved,
+}
+
+impl AnnotationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnnotationStatus::Active => "active",
+            AnnotationStatus::Orphaned => "orphaned",
+            AnnotationStatus::Resolved => "resolved",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "active" => Some(AnnotationStatus::Active),
+            "orphaned" => Some(AnnotationStatus::Orphaned),
+            "resolved" => Some(AnnotationStatus::Resolved),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Annotation {
     pub id: String,
@@ -81,11 +212,23 @@ pub struct Annotation {
     pub tags: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub priority: Option<String>, // P0 | P1 | P2
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff_side: Option<String>, // old | new：批注针对 diff 的哪一侧
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_ref: Option<String>, // diff 基线（如 HEAD / 分支 / commit）
+    #[serde(default)]
+    pub status: AnnotationStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution_note: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateAnnotation {
     pub file_path: String,
@@ -107,6 +250,10 @@ pub struct CreateAnnotation {
     pub tags: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub priority: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff_side: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_ref: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -136,4 +283,216 @@ pub struct UpdateAnnotation {
     pub tags: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub priority: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff_side: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_ref: Option<String>,
+}
+
+/// 将批注标记为已解决（`resolvedBy` 缺省为 `user`）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolveAnnotation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved_by: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// 批注修订的来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RevisionKind {
+    /// 补录的变更前快照：历史为空的批注首次变更时记录
+    Baseline,
+    Create,
+    Edit,
+    Relocate,
+    Orphan,
+    Reattach,
+    Resolve,
+    Reopen,
+    Import,
+    Restore,
+}
+
+impl RevisionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RevisionKind::Baseline => "baseline",
+            RevisionKind::Create => "create",
+            RevisionKind::Edit => "edit",
+            RevisionKind::Relocate => "relocate",
+            RevisionKind::Orphan => "orphan",
+            RevisionKind::Reattach => "reattach",
+            RevisionKind::Resolve => "resolve",
+            RevisionKind::Reopen => "reopen",
+            RevisionKind::Import => "import",
+            RevisionKind::Restore => "restore",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "baseline" => Some(RevisionKind::Baseline),
+            "create" => Some(RevisionKind::Create),
+            "edit" => Some(RevisionKind::Edit),
+            "relocate" => Some(RevisionKind::Relocate),
+            "orphan" => Some(RevisionKind::Orphan),
+            "reattach" => Some(RevisionKind::Reattach),
+            "resolve" => Some(RevisionKind::Resolve),
+            "reopen" => Some(RevisionKind::Reopen),
+            "import" => Some(RevisionKind::Import),
+            "restore" => Some(RevisionKind::Restore),
+            _ => None,
+        }
+    }
+}
+
+/// 批注的一次修订：`snapshot` 为该次变更之后的完整批注（`baseline` 为首次变更之前的）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnotationRevision {
+    pub id: String,
+    pub annotation_id: String,
+    pub kind: RevisionKind,
+    pub snapshot: Annotation,
+    pub created_at: String,
+}
+
+/// 全文检索命中：`score` 越大越相关；片段中命中词以 `<mark>…</mark>` 包裹
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnotationSearchHit {
+    pub annotation: Annotation,
+    pub score: f64,
+    pub comment_snippet: String,
+    pub selected_text_snippet: String,
+}
+
+/// 存于数据库的自定义拼接模板（MiniJinja 语法，按工作区隔离）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StitchTemplate {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub body: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// 批注下的讨论回复（按创建时间排列成线程）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnotationComment {
+    pub id: String,
+    pub annotation_id: String,
+    pub author: String,
+    pub body: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateComment {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateComment {
+    pub body: String,
+}
+
+/// 数据库中的一个工作区及其批注概况
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceSummary {
+    pub id: String,
+    pub key: String,
+    pub root_path: String,
+    /// 与路径无关的稳定标识（git remote + 根提交，或 `.ailoom/workspace-id`）
+    pub identity: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub annotation_count: i64,
+    /// 工作区或其批注最近一次更新的时间
+    pub last_activity: String,
+}
+
+/// 跨工作区转移批注的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferMode {
+    /// 改挂到目标工作区（保留 id、回复与修订历史）
+    Move,
+    /// 以新 id 复制到目标工作区
+    Copy,
+}
+
+/// 导出/导入 bundle 的格式版本
+pub const BUNDLE_SCHEMA_VERSION: &str = "1";
+
+/// 导入时 `id` 已存在的处理策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImportStrategy {
+    /// 保留现有批注
+    Skip,
+    /// 总是以导入内容覆盖
+    Overwrite,
+    /// `updatedAt` 更新者覆盖，否则跳过
+    #[default]
+    NewerWins,
+    /// 以新 `id` 另存一份
+    DuplicateWithNewId,
+}
+
+impl ImportStrategy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "skip" => Some(Self::Skip),
+            "overwrite" => Some(Self::Overwrite),
+            "newer-wins" => Some(Self::NewerWins),
+            "duplicate-with-new-id" => Some(Self::DuplicateWithNewId),
+            _ => None,
+        }
+    }
+}
+
+/// 导入结果；`dryRun` 时为计划结果，未写入
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub added: u64,
+    pub updated: u64,
+    pub skipped: u64,
+    /// 新增批注的 id（`duplicate-with-new-id` 时为新分配的 id）
+    pub added_ids: Vec<String>,
+    pub updated_ids: Vec<String>,
+    pub skipped_ids: Vec<String>,
+    /// 导入 id → 新分配的 id（仅 `duplicate-with-new-id` 时出现）
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub id_map: BTreeMap<String, String>,
+    pub dry_run: bool,
+}
+
+/// 将孤立批注重新挂接到文件中的新位置（`selectedText` 缺省时按新位置从当前文件读取）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReattachAnnotation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    pub start_line: i64,
+    pub end_line: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_column: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_column: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selected_text: Option<String>,
 }
@@ -0,0 +1,413 @@
+use crate::{recursive_walker, FsConfig, IgnoreMatcher};
+use ailoom_core::FileMatch;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+/// 内存文件索引（root 相对路径），供“快速打开”模糊查找。
+/// 启动时全量构建，之后由文件监听按变更路径增量刷新；未监听时改为查找前按需重建（见 `rebuild_on_demand`）。
+/// 全量构建期间到达的增量刷新会在新集合换入后重放，不会被覆盖；同一时刻只有一次全量构建。
+/// `.ailoom/`（项目内 DB）不入索引。
+#[derive(Clone, Default)]
+pub struct FileIndex {
+    paths: Arc<RwLock<BTreeSet<String>>>,
+    freshness: Arc<Mutex<Freshness>>,
+    /// 全量构建互斥（single-flight）
+    building: Arc<Mutex<()>>,
+    /// 全量构建进行中时为 `Some`，记录期间刷新过的路径
+    pending: Arc<Mutex<Option<Vec<String>>>>,
+}
+
+#[derive(Default)]
+struct Freshness {
+    built_at: Option<Instant>,
+    max_age: Option<Duration>,
+}
+
+/// 一次增量刷新对索引的改动（在写锁外算好）
+enum Change {
+    Add(BTreeSet<String>),
+    Insert(String),
+    Remove(String),
+    RemoveTree(String),
+}
+
+impl FileIndex {
+    pub fn rebuild(&self, cfg: &FsConfig) {
+        let _guard = self.building.lock().unwrap_or_else(|e| e.into_inner());
+        self.rebuild_locked(cfg);
+    }
+
+    fn rebuild_locked(&self, cfg: &FsConfig) {
+        *self.pending.lock().unwrap_or_else(|e| e.into_inner()) = Some(Vec::new());
+        let files = walk_files(cfg, &cfg.root);
+        self.install(cfg, files);
+    }
+
+    /// 换入全量遍历结果，并重放遍历期间刷新过的路径
+    fn install(&self, cfg: &FsConfig, files: BTreeSet<String>) {
+        let replay = {
+            let mut paths = self.paths.write().unwrap_or_else(|e| e.into_inner());
+            *paths = files;
+            self.pending
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .take()
+                .unwrap_or_default()
+        };
+        // 遍历期间的变更可能没被遍历看到：按当前文件系统重放
+        if !replay.is_empty() {
+            self.refresh_paths(cfg, &replay);
+        }
+        self.freshness
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .built_at = Some(Instant::now());
+    }
+
+    /// 没有文件监听维护时调用：此后 `refresh_if_stale` 在索引超过 `max_age` 时全量重建
+    pub fn rebuild_on_demand(&self, max_age: Duration) {
+        self.freshness
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .max_age = Some(max_age);
+    }
+
+    fn is_stale(&self) -> bool {
+        let f = self.freshness.lock().unwrap_or_else(|e| e.into_inner());
+        match (f.max_age, f.built_at) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(max_age), Some(at)) => at.elapsed() >= max_age,
+        }
+    }
+
+    /// 按需模式下索引过期（或尚未构建）则全量重建；由监听维护时不做任何事。
+    /// 并发调用只重建一次：其余调用等待进行中的构建完成后复查，不再各自遍历
+    pub fn refresh_if_stale(&self, cfg: &FsConfig) {
+        if !self.is_stale() {
+            return;
+        }
+        let _guard = self.building.lock().unwrap_or_else(|e| e.into_inner());
+        if self.is_stale() {
+            self.rebuild_locked(cfg);
+        }
+    }
+
+    /// 按变更路径增量刷新：新目录整体遍历加入；文件按忽略规则加入/移除；不存在的路径连同其子路径移除。
+    /// 忽略规则每次调用解析一次；遍历与判定在写锁外完成。
+    pub fn refresh_paths(&self, cfg: &FsConfig, rel_paths: &[String]) {
+        let rel_paths: Vec<&String> = rel_paths.iter().filter(|r| !is_db_path(r)).collect();
+        if rel_paths.is_empty() {
+            return;
+        }
+        if let Some(pending) = self
+            .pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_mut()
+        {
+            pending.extend(rel_paths.iter().map(|r| r.to_string()));
+        }
+        let mut ignore = IgnoreMatcher::new(cfg);
+        let changes: Vec<Change> = rel_paths
+            .into_iter()
+            .filter_map(|rel| {
+                let abs = cfg.root.join(rel);
+                if abs.is_dir() {
+                    (!ignore.is_ignored(rel, true)).then(|| Change::Add(walk_files(cfg, &abs)))
+                } else if abs.is_file() {
+                    Some(if ignore.is_ignored(rel, false) {
+                        Change::Remove(rel.clone())
+                    } else {
+                        Change::Insert(rel.clone())
+                    })
+                } else {
+                    Some(Change::RemoveTree(rel.clone()))
+                }
+            })
+            .collect();
+        let mut paths = self.paths.write().unwrap_or_else(|e| e.into_inner());
+        for change in changes {
+            match change {
+                Change::Add(files) => paths.extend(files),
+                Change::Insert(rel) => {
+                    paths.insert(rel);
+                }
+                Change::Remove(rel) => {
+                    paths.remove(&rel);
+                }
+                Change::RemoveTree(rel) => {
+                    paths.remove(&rel);
+                    let prefix = format!("{}/", rel.trim_end_matches('/'));
+                    let nested: Vec<String> = paths
+                        .range(prefix.clone()..)
+                        .take_while(|p| p.starts_with(&prefix))
+                        .cloned()
+                        .collect();
+                    for p in nested {
+                        paths.remove(&p);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.read().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 按子序列模糊得分降序返回前 `limit` 条（同分时短路径优先，再按字典序）；空查询按路径字典序返回。
+    pub fn find(&self, query: &str, limit: usize) -> Vec<FileMatch> {
+        let paths = self.paths.read().unwrap_or_else(|e| e.into_inner());
+        if query.trim().is_empty() {
+            return paths
+                .iter()
+                .take(limit)
+                .map(|p| FileMatch {
+                    path: p.clone(),
+                    score: 0,
+                    positions: Vec::new(),
+                })
+                .collect();
+        }
+        let mut hits: Vec<FileMatch> = paths
+            .iter()
+            .filter_map(|p| {
+                fuzzy_score(query, p).map(|(score, positions)| FileMatch {
+                    path: p.clone(),
+                    score,
+                    positions,
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then(a.path.len().cmp(&b.path.len()))
+                .then(a.path.cmp(&b.path))
+        });
+        hits.truncate(limit);
+        hits
+    }
+}
+
+fn walk_files(cfg: &FsConfig, dir_abs: &Path) -> BTreeSet<String> {
+    let mut out = BTreeSet::new();
+    for dent in recursive_walker(cfg, dir_abs).build().flatten() {
+        if !dent.file_type().map(|t| t.is_file()).unwrap_or(false) {
+            continue;
+        }
+        let rel = pathdiff::diff_paths(dent.path(), &cfg.root).unwrap_or_else(PathBuf::new);
+        if !rel.as_os_str().is_empty() && !rel.starts_with(".ailoom") {
+            out.insert(rel.to_string_lossy().to_string());
+        }
+    }
+    out
+}
+
+fn is_db_path(rel: &str) -> bool {
+    Path::new(rel).starts_with(".ailoom")
+}
+
+/// 子序列模糊匹配（大小写不敏感，忽略查询中的空白）。未命中返回 `None`。
+/// 得分：逐字符基础分 + 连续命中/词首（分隔符或驼峰）/落在文件名内的加分，减去间隔与路径长度惩罚；
+/// 对查询首字符的每个候选起点做一次贪心匹配，取最高分。
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<(i64, Vec<usize>)> {
+    let q: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    if q.is_empty() {
+        return Some((0, Vec::new()));
+    }
+    let orig: Vec<char> = candidate.chars().collect();
+    let lower: Vec<char> = orig
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    let base_start = orig
+        .iter()
+        .rposition(|c| *c == '/' || *c == '\\')
+        .map(|i| i + 1)
+        .unwrap_or(0);
+
+    let mut best: Option<(i64, Vec<usize>)> = None;
+    for start in 0..lower.len() {
+        if lower[start] != q[0] {
+            continue;
+        }
+        let mut pos = vec![start];
+        let mut ci = start + 1;
+        for &qc in &q[1..] {
+            while ci < lower.len() && lower[ci] != qc {
+                ci += 1;
+            }
+            if ci == lower.len() {
+                break;
+            }
+            pos.push(ci);
+            ci += 1;
+        }
+        // 从更靠后的起点贪心只会更难命中
+        if pos.len() < q.len() {
+            break;
+        }
+        let score = score_positions(&orig, &pos, base_start);
+        if best.as_ref().is_none_or(|(b, _)| score > *b) {
+            best = Some((score, pos));
+        }
+    }
+    best.map(|(mut score, pos)| {
+        let base: String = lower[base_start..].iter().collect();
+        let q_str: String = q.iter().collect();
+        if base == q_str {
+            score += 100;
+        } else if base.starts_with(&q_str) {
+            score += 40;
+        }
+        (score, pos)
+    })
+}
+
+fn score_positions(orig: &[char], pos: &[usize], base_start: usize) -> i64 {
+    let is_sep = |c: char| matches!(c, '/' | '\\' | '_' | '-' | '.' | ' ');
+    let mut score = 0i64;
+    for (i, &p) in pos.iter().enumerate() {
+        score += 16;
+        let boundary =
+            p == 0 || is_sep(orig[p - 1]) || (orig[p - 1].is_lowercase() && orig[p].is_uppercase());
+        if boundary {
+            score += 24;
+        }
+        if p >= base_start {
+            score += 8;
+        }
+        if i > 0 {
+            let gap = p - pos[i - 1] - 1;
+            if gap == 0 {
+                score += 32;
+            } else {
+                score -= (gap.min(10) as i64) * 2;
+            }
+        }
+    }
+    let lead = if pos[0] >= base_start {
+        pos[0] - base_start
+    } else {
+        pos[0]
+    };
+    score -= lead.min(15) as i64;
+    score - (orig.len() as i64) / 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_prefers_basename_prefix_and_consecutive_hits() {
+        assert!(fuzzy_score("xyz", "src/lib.rs").is_none());
+        let (s_exact, pos) = fuzzy_score("lib.rs", "crates/ailoom-fs/src/lib.rs").unwrap();
+        assert_eq!(pos, (21..27).collect::<Vec<_>>());
+        let (s_scattered, _) =
+            fuzzy_score("lib.rs", "crates/ailoom-fs/src/index.rs").unwrap_or((i64::MIN, vec![]));
+        assert!(s_exact > s_scattered);
+        let (s_prefix, _) = fuzzy_score("main", "src/main.rs").unwrap();
+        let (s_inner, _) = fuzzy_score("main", "src/domain/remaining.rs").unwrap();
+        assert!(s_prefix > s_inner);
+    }
+
+    fn fixture(files: &[&str]) -> FsConfig {
+        let root = std::env::temp_dir().join(format!(
+            "ailoom_fs_index_{}_{}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        for rel in files {
+            let p = root.join(rel);
+            std::fs::create_dir_all(p.parent().unwrap()).unwrap();
+            std::fs::write(p, "").unwrap();
+        }
+        FsConfig::new(root.canonicalize().unwrap())
+    }
+
+    fn paths(hits: Vec<FileMatch>) -> Vec<String> {
+        hits.into_iter().map(|m| m.path).collect()
+    }
+
+    #[test]
+    fn find_ranks_by_score_then_length_and_lists_empty_query_in_order() {
+        let cfg = fixture(&[
+            "src/main.rs",
+            "src/domain/remaining.rs",
+            "a/very/deep/path/main.rs",
+            "b.rs",
+            ".ailoom/ailoom.db",
+        ]);
+        let index = FileIndex::default();
+        index.rebuild(&cfg);
+        assert_eq!(index.len(), 4);
+        assert_eq!(
+            paths(index.find("main", 10)),
+            [
+                "src/main.rs",
+                "a/very/deep/path/main.rs",
+                "src/domain/remaining.rs"
+            ]
+        );
+        assert_eq!(
+            paths(index.find("", 3)),
+            [
+                "a/very/deep/path/main.rs",
+                "b.rs",
+                "src/domain/remaining.rs"
+            ]
+        );
+        assert_eq!(paths(index.find("  ", 1)), ["a/very/deep/path/main.rs"]);
+        // 监听增量刷新同样跳过 DB 目录
+        std::fs::write(cfg.root.join(".ailoom/ailoom.db-wal"), "").unwrap();
+        index.refresh_paths(&cfg, &[".ailoom/ailoom.db-wal".to_string()]);
+        assert_eq!(index.len(), 4);
+    }
+
+    #[test]
+    fn on_demand_index_rebuilds_when_stale() {
+        let cfg = fixture(&["a.rs"]);
+        let index = FileIndex::default();
+        index.rebuild(&cfg);
+        std::fs::write(cfg.root.join("b.rs"), "").unwrap();
+        // 由监听维护时不重建
+        index.refresh_if_stale(&cfg);
+        assert_eq!(index.len(), 1);
+        index.rebuild_on_demand(Duration::ZERO);
+        index.refresh_if_stale(&cfg);
+        assert_eq!(index.len(), 2);
+    }
+
+    #[test]
+    fn refreshes_during_a_rebuild_survive_the_swap() {
+        let cfg = fixture(&["a.rs", "b.rs"]);
+        let index = FileIndex::default();
+        // 模拟构建进行中：遍历结果已过时，期间监听刷新了两条路径
+        *index.pending.lock().unwrap() = Some(Vec::new());
+        let walked = walk_files(&cfg, &cfg.root);
+        std::fs::write(cfg.root.join("c.rs"), "").unwrap();
+        std::fs::remove_file(cfg.root.join("b.rs")).unwrap();
+        index.refresh_paths(&cfg, &["c.rs".to_string(), "b.rs".to_string()]);
+        index.install(&cfg, walked);
+        assert_eq!(paths(index.find("", 10)), vec!["a.rs", "c.rs"]);
+        assert!(index.pending.lock().unwrap().is_none());
+    }
+}
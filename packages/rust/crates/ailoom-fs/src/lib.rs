@@ -1,7 +1,16 @@
-use ailoom_core::{DirEntry, EntryType, FileChunk};
-use ignore::WalkBuilder;
+mod index;
+
+use ailoom_core::{DirEntry, EntryType, FileChunk, SearchMatch, SearchSummary};
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    overrides::OverrideBuilder,
+    Match, WalkBuilder,
+};
+pub use index::{fuzzy_score, FileIndex};
+use regex::{Regex, RegexBuilder};
 use sha2::{Digest, Sha256};
 use std::{
+    collections::{HashMap, VecDeque},
     fs,
     io::{BufRead, BufReader, Read},
     path::{Path, PathBuf},
@@ -32,24 +41,13 @@ pub fn list_dir(cfg: &FsConfig, rel_dir: &str) -> std::io::Result<Vec<DirEntry>>
     let dir_abs = cfg.root.join(rel_dir);
     let dir_abs = dir_abs.canonicalize()?;
     if !ensure_within_root(&cfg.root, &dir_abs) {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "INVALID_PATH",
-        ));
+        return Err(std::io::Error::other("INVALID_PATH"));
     }
 
-    // Use ignore WalkBuilder to honor .gitignore and allow .ailoomignore
+    // 与递归遍历共用忽略规则（含 .ailoomignore 与 .git/node_modules 硬排除），只取一层
     let mut entries: Vec<DirEntry> = Vec::new();
-    let mut builder = WalkBuilder::new(&dir_abs);
+    let mut builder = recursive_walker(cfg, &dir_abs);
     builder.max_depth(Some(1));
-    builder.hidden(false);
-    builder.follow_links(false);
-    // merge ignore patterns: .gitignore honored by default; add .ailoomignore if present
-    let ailoom_ignore = dir_abs.join(".ailoomignore");
-    if ailoom_ignore.exists() {
-        builder.add_ignore(ailoom_ignore);
-    }
-
     for result in builder.build() {
         let dent = match result {
             Ok(d) => d,
@@ -62,10 +60,6 @@ pub fn list_dir(cfg: &FsConfig, rel_dir: &str) -> std::io::Result<Vec<DirEntry>>
             Ok(m) => m,
             Err(_) => continue,
         };
-        // basic hard excludes
-        if dent.file_name() == ".git" || dent.file_name() == "node_modules" {
-            continue;
-        }
         let r#type = if meta.is_dir() {
             EntryType::Dir
         } else {
@@ -87,6 +81,7 @@ pub fn list_dir(cfg: &FsConfig, rel_dir: &str) -> std::io::Result<Vec<DirEntry>>
             path: rel_str,
             r#type,
             size,
+            git_status: None,
         });
     }
     // sort: dirs first then files by name
@@ -98,6 +93,144 @@ pub fn list_dir(cfg: &FsConfig, rel_dir: &str) -> std::io::Result<Vec<DirEntry>>
     Ok(entries)
 }
 
+/// 判断 root 相对路径是否应被忽略，与 `recursive_walker` 口径一致：硬排除 `.git`/`node_modules`，
+/// 路径本身及其每一级上级目录按遍历器的忽略来源逐一判定（遍历器不会进入被忽略的目录），任一命中即忽略。
+/// 批量判定请用 `IgnoreMatcher`，避免每次重新解析规则文件。
+pub fn is_ignored(cfg: &FsConfig, rel_path: &str, is_dir: bool) -> bool {
+    IgnoreMatcher::new(cfg).is_ignored(rel_path, is_dir)
+}
+
+/// 可复用的 `is_ignored`：全局 excludesFile 与各级规则文件只解析一次（按目录缓存）。
+/// 规则文件本身可能变动，按批次新建（例如每批文件变更事件一个）。
+pub struct IgnoreMatcher {
+    root: PathBuf,
+    rules: IgnoreRules,
+}
+
+impl IgnoreMatcher {
+    pub fn new(cfg: &FsConfig) -> Self {
+        Self {
+            root: cfg.root.clone(),
+            rules: IgnoreRules::new(cfg),
+        }
+    }
+
+    pub fn is_ignored(&mut self, rel_path: &str, is_dir: bool) -> bool {
+        let rel = Path::new(rel_path);
+        if rel
+            .components()
+            .any(|c| c.as_os_str() == ".git" || c.as_os_str() == "node_modules")
+        {
+            return true;
+        }
+        let components: Vec<_> = rel.components().collect();
+        let mut abs = self.root.clone();
+        for (i, c) in components.iter().enumerate() {
+            abs.push(c);
+            if self.rules.ignored(&abs, is_dir || i + 1 < components.len()) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// `ignore` 遍历器的忽略来源（默认选项），优先级由高到低：逐级 `.ignore`、`.gitignore`、`.git/info/exclude`、
+/// 全局 excludesFile、root 的 `.ailoomignore`。git 相关规则仅在 git 仓库内生效（`require_git`），
+/// 且不越过仓库根向上查找；同类规则由近及远，取第一个命中。
+struct IgnoreRules {
+    global: Gitignore,
+    explicit: Gitignore,
+    dirs: HashMap<PathBuf, DirRules>,
+}
+
+struct DirRules {
+    ignore: Gitignore,
+    gitignore: Gitignore,
+    exclude: Gitignore,
+    has_git: bool,
+}
+
+impl IgnoreRules {
+    fn new(cfg: &FsConfig) -> Self {
+        Self {
+            global: GitignoreBuilder::new(&cfg.root).build_global().0,
+            explicit: gitignore_from(&cfg.root, &[cfg.root.join(".ailoomignore")]),
+            dirs: HashMap::new(),
+        }
+    }
+
+    fn load(&mut self, dir: &Path) {
+        self.dirs.entry(dir.to_path_buf()).or_insert_with(|| {
+            let git_dir = git_dir(dir);
+            DirRules {
+                ignore: gitignore_from(dir, &[dir.join(".ignore")]),
+                gitignore: gitignore_from(dir, &[dir.join(".gitignore")]),
+                exclude: match &git_dir {
+                    Some(g) => gitignore_from(dir, &[g.join("info").join("exclude")]),
+                    None => Gitignore::empty(),
+                },
+                has_git: git_dir.is_some(),
+            }
+        });
+    }
+
+    fn ignored(&mut self, abs: &Path, is_dir: bool) -> bool {
+        let parents: Vec<PathBuf> = abs.ancestors().skip(1).map(Path::to_path_buf).collect();
+        for d in &parents {
+            self.load(d);
+        }
+        let any_git = parents.iter().any(|d| self.dirs[d].has_git);
+        let (mut m_ignore, mut m_gi, mut m_exclude) = (Match::None, Match::None, Match::None);
+        let mut saw_git = false;
+        for d in &parents {
+            let rules = &self.dirs[d];
+            if m_ignore.is_none() {
+                m_ignore = rules.ignore.matched(abs, is_dir);
+            }
+            if any_git && !saw_git {
+                if m_gi.is_none() {
+                    m_gi = rules.gitignore.matched(abs, is_dir);
+                }
+                if m_exclude.is_none() {
+                    m_exclude = rules.exclude.matched(abs, is_dir);
+                }
+            }
+            saw_git = saw_git || rules.has_git;
+        }
+        let m_global = if any_git {
+            self.global.matched(abs, is_dir)
+        } else {
+            Match::None
+        };
+        m_ignore
+            .or(m_gi)
+            .or(m_exclude)
+            .or(m_global)
+            .or(self.explicit.matched(abs, is_dir))
+            .is_ignore()
+    }
+}
+
+fn gitignore_from(dir: &Path, files: &[PathBuf]) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(dir);
+    for f in files.iter().filter(|f| f.is_file()) {
+        builder.add(f);
+    }
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+/// `dir/.git` 为目录时即 git 目录；为文件（worktree/submodule）时按其中 `gitdir:` 解析
+fn git_dir(dir: &Path) -> Option<PathBuf> {
+    let dot_git = dir.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    let content = fs::read_to_string(&dot_git).ok()?;
+    let target = content.strip_prefix("gitdir:")?.trim();
+    Some(dir.join(target))
+}
+
 pub fn read_file_chunk(
     cfg: &FsConfig,
     rel_path: &str,
@@ -107,14 +240,11 @@ pub fn read_file_chunk(
     let abs = cfg.root.join(rel_path);
     let abs = abs.canonicalize()?;
     if !ensure_within_root(&cfg.root, &abs) {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "INVALID_PATH",
-        ));
+        return Err(std::io::Error::other("INVALID_PATH"));
     }
     let meta = fs::metadata(&abs)?;
     if !meta.is_file() {
-        return Err(std::io::Error::new(std::io::ErrorKind::Other, "NOT_FILE"));
+        return Err(std::io::Error::other("NOT_FILE"));
     }
 
     // 非文本/二进制快速探测（前 64KB）
@@ -175,18 +305,22 @@ pub struct FullFile {
     pub digest: String,
 }
 
+/// 文件内容摘要（SHA-256 十六进制），即 `FullFile::digest` / 批注 `fileDigest` 的格式。
+pub fn content_digest(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    hex::encode(hasher.finalize())
+}
+
 pub fn read_file_full(cfg: &FsConfig, rel_path: &str) -> std::io::Result<FullFile> {
     let abs = cfg.root.join(rel_path);
     let abs = abs.canonicalize()?;
     if !ensure_within_root(&cfg.root, &abs) {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "INVALID_PATH",
-        ));
+        return Err(std::io::Error::other("INVALID_PATH"));
     }
     let meta = fs::metadata(&abs)?;
     if !meta.is_file() {
-        return Err(std::io::Error::new(std::io::ErrorKind::Other, "NOT_FILE"));
+        return Err(std::io::Error::other("NOT_FILE"));
     }
     let size = meta.len();
     // 兜底限制：超过硬阈值直接拒绝全量读取，由上层返回 413
@@ -206,9 +340,7 @@ pub fn read_file_full(cfg: &FsConfig, rel_path: &str) -> std::io::Result<FullFil
     }
     let content = String::from_utf8(bytes)
         .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "NON_TEXT"))?;
-    let mut hasher = Sha256::new();
-    hasher.update(content.as_bytes());
-    let digest = hex::encode(hasher.finalize());
+    let digest = content_digest(content.as_bytes());
     let rel_str = pathdiff::diff_paths(&abs, &cfg.root)
         .unwrap_or_else(|| PathBuf::from(rel_path))
         .to_string_lossy()
@@ -243,23 +375,15 @@ pub fn write_file(
     let abs = cfg.root.join(rel_path);
     let abs = abs.canonicalize().map_err(WriteError::Io)?;
     if !ensure_within_root(&cfg.root, &abs) {
-        return Err(WriteError::Io(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "INVALID_PATH",
-        )));
+        return Err(WriteError::Io(std::io::Error::other("INVALID_PATH")));
     }
     if fs::metadata(&abs).map_err(WriteError::Io)?.is_dir() {
-        return Err(WriteError::Io(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "IS_DIR",
-        )));
+        return Err(WriteError::Io(std::io::Error::other("IS_DIR")));
     }
 
     // digest check
     let current = fs::read(&abs).map_err(WriteError::Io)?;
-    let mut hasher = Sha256::new();
-    hasher.update(&current);
-    let current_digest = hex::encode(hasher.finalize());
+    let current_digest = content_digest(&current);
     if let Some(b) = base_digest {
         if b != current_digest {
             return Err(WriteError::Conflict { current_digest });
@@ -267,21 +391,208 @@ pub fn write_file(
     }
 
     // atomic write: write temp then rename
-    let dir = abs.parent().ok_or_else(|| {
-        WriteError::Io(std::io::Error::new(std::io::ErrorKind::Other, "NO_PARENT"))
-    })?;
+    let dir = abs
+        .parent()
+        .ok_or_else(|| WriteError::Io(std::io::Error::other("NO_PARENT")))?;
     let tmp = dir.join(format!(".ailoom.tmp.{}", std::process::id()));
     fs::write(&tmp, new_content).map_err(WriteError::Io)?;
     fs::rename(&tmp, &abs).map_err(WriteError::Io)?;
 
-    // new digest
-    let mut hasher2 = Sha256::new();
-    hasher2.update(new_content.as_bytes());
-    let new_digest = hex::encode(hasher2.finalize());
-    Ok(new_digest)
+    Ok(content_digest(new_content.as_bytes()))
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    pub query: String,
+    pub regex: bool,
+    pub case_sensitive: bool,
+    pub glob: Option<String>,
+    pub context_lines: usize,
+    pub max_results: usize,
+}
+
+/// 全局搜索：递归遍历 root（与 `list_dir` 共用遍历器），逐行流式匹配并返回每一处命中；跳过非文本与超过硬阈值的文件。
+/// 先 `Searcher::new` 校验参数（`INVALID_REGEX`/`INVALID_GLOB`），再 `run` 执行遍历。
+pub struct Searcher {
+    re: Regex,
+    walker: WalkBuilder,
+    root: PathBuf,
+    context_lines: usize,
+    max_results: usize,
+}
+
+impl Searcher {
+    pub fn new(cfg: &FsConfig, opts: &SearchOptions) -> std::io::Result<Self> {
+        let pattern = if opts.regex {
+            opts.query.clone()
+        } else {
+            regex::escape(&opts.query)
+        };
+        let re = RegexBuilder::new(&pattern)
+            .case_insensitive(!opts.case_sensitive)
+            .build()
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "INVALID_REGEX"))?;
+
+        let mut builder = recursive_walker(cfg, &cfg.root);
+        builder.sort_by_file_name(|a, b| a.cmp(b));
+        if let Some(glob) = opts.glob.as_deref().filter(|g| !g.is_empty()) {
+            let overrides = OverrideBuilder::new(&cfg.root)
+                .add(glob)
+                .and_then(|b| b.build())
+                .map_err(|_| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, "INVALID_GLOB")
+                })?;
+            builder.overrides(overrides);
+        }
+        Ok(Self {
+            re,
+            walker: builder,
+            root: cfg.root.clone(),
+            context_lines: opts.context_lines,
+            max_results: opts.max_results,
+        })
+    }
+
+    /// 每处命中回调一次（同一行多处命中各回调一次），回调返回 `false` 可提前终止（例如客户端断开）。
+    pub fn run(&self, mut on_match: impl FnMut(SearchMatch) -> bool) -> SearchSummary {
+        let mut summary = SearchSummary::default();
+        for result in self.walker.build() {
+            let dent = match result {
+                Ok(d) => d,
+                Err(_) => continue,
+            };
+            if !dent.file_type().map(|t| t.is_file()).unwrap_or(false) {
+                continue;
+            }
+            let size = dent.metadata().map(|m| m.len()).unwrap_or(0);
+            if size > HARD_SIZE_BYTES || is_non_text(dent.path(), 64 * 1024).unwrap_or(true) {
+                summary.files_skipped += 1;
+                continue;
+            }
+            let file = match fs::File::open(dent.path()) {
+                Ok(f) => f,
+                Err(_) => {
+                    summary.files_skipped += 1;
+                    continue;
+                }
+            };
+            summary.files_scanned += 1;
+            let rel_str = pathdiff::diff_paths(dent.path(), &self.root)
+                .unwrap_or_else(|| dent.path().to_path_buf())
+                .to_string_lossy()
+                .to_string();
+            if !self.scan(BufReader::new(file), &rel_str, &mut summary, &mut on_match) {
+                break;
+            }
+        }
+        summary
+    }
+
+    /// 逐行扫描一个文件：前文保留最近 `context_lines` 行，命中先挂起直到凑齐后文（或文件结束）再回调。
+    /// 返回 `false` 表示整体终止（达到上限或回调要求）；中途遇到非 UTF-8 行按已读部分结束。
+    fn scan(
+        &self,
+        reader: impl BufRead,
+        path: &str,
+        summary: &mut SearchSummary,
+        on_match: &mut impl FnMut(SearchMatch) -> bool,
+    ) -> bool {
+        let mut before: VecDeque<String> = VecDeque::with_capacity(self.context_lines + 1);
+        let mut pending: VecDeque<SearchMatch> = VecDeque::new();
+        let mut limit_hit = false;
+        for (i, line) in reader.lines().enumerate() {
+            let Ok(line) = line else { break };
+            for hit in pending.iter_mut() {
+                hit.after.push(line.clone());
+            }
+            if !self.flush(&mut pending, false, summary, on_match) {
+                return false;
+            }
+            if limit_hit {
+                if pending.is_empty() {
+                    break;
+                }
+                continue;
+            }
+            for (start, end) in self.find_all(&line) {
+                if summary.matches >= self.max_results {
+                    summary.truncated = true;
+                    limit_hit = true;
+                    break;
+                }
+                summary.matches += 1;
+                pending.push_back(SearchMatch {
+                    path: path.to_string(),
+                    line: i + 1,
+                    column: line[..start].chars().count() + 1,
+                    end_column: line[..end].chars().count() + 1,
+                    text: line.clone(),
+                    before: before.iter().cloned().collect(),
+                    after: Vec::new(),
+                });
+            }
+            if !self.flush(&mut pending, false, summary, on_match) {
+                return false;
+            }
+            before.push_back(line);
+            if before.len() > self.context_lines {
+                before.pop_front();
+            }
+        }
+        self.flush(&mut pending, true, summary, on_match) && !limit_hit
+    }
+
+    /// 回调后文已凑齐（`all` 时为全部）的挂起命中
+    fn flush(
+        &self,
+        pending: &mut VecDeque<SearchMatch>,
+        all: bool,
+        summary: &mut SearchSummary,
+        on_match: &mut impl FnMut(SearchMatch) -> bool,
+    ) -> bool {
+        while pending
+            .front()
+            .is_some_and(|h| all || h.after.len() >= self.context_lines)
+        {
+            if let Some(hit) = pending.pop_front() {
+                if !on_match(hit) {
+                    summary.truncated = true;
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// 一行内的全部命中（字节区间）；有非空命中时忽略空命中（如 `a*`）
+    fn find_all(&self, line: &str) -> Vec<(usize, usize)> {
+        let mut spans: Vec<(usize, usize)> = self
+            .re
+            .find_iter(line)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        if spans.iter().any(|(s, e)| s < e) {
+            spans.retain(|(s, e)| s < e);
+        }
+        spans
+    }
 }
 
 // --- helpers ---
+
+// 遍历器（目录树/搜索/文件索引共用）：`.gitignore` 等默认来源 + root 的 `.ailoomignore`，并硬排除 `.git`/`node_modules`
+pub(crate) fn recursive_walker(cfg: &FsConfig, dir_abs: &Path) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(dir_abs);
+    builder.hidden(false);
+    builder.follow_links(false);
+    let ailoom_ignore = cfg.root.join(".ailoomignore");
+    if ailoom_ignore.exists() {
+        builder.add_ignore(ailoom_ignore);
+    }
+    builder.filter_entry(|d| d.file_name() != ".git" && d.file_name() != "node_modules");
+    builder
+}
+
 fn is_non_text(path: &Path, sample: usize) -> std::io::Result<bool> {
     let mut f = fs::File::open(path)?;
     let mut buf = vec![0u8; sample];
@@ -292,3 +603,196 @@ fn is_non_text(path: &Path, sample: usize) -> std::io::Result<bool> {
     }
     Ok(std::str::from_utf8(slice).is_err())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str, files: &[(&str, &str)]) -> FsConfig {
+        let root = std::env::temp_dir().join(format!(
+            "ailoom_fs_test_{}_{}_{}",
+            name,
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        for (rel, content) in files {
+            let p = root.join(rel);
+            fs::create_dir_all(p.parent().unwrap()).unwrap();
+            fs::write(p, content).unwrap();
+        }
+        FsConfig::new(root.canonicalize().unwrap())
+    }
+
+    fn walked(cfg: &FsConfig) -> Vec<String> {
+        let mut out: Vec<String> = recursive_walker(cfg, &cfg.root)
+            .build()
+            .flatten()
+            .filter(|d| d.file_type().map(|t| t.is_file()).unwrap_or(false))
+            .filter_map(|d| pathdiff::diff_paths(d.path(), &cfg.root))
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn is_ignored_agrees_with_walker_sources() {
+        let cfg = fixture(
+            "git",
+            &[
+                (".git/HEAD", "ref: refs/heads/main\n"),
+                (".git/info/exclude", "secret.txt\n"),
+                (".gitignore", "*.log\nbuild/\n"),
+                (".ailoomignore", "tmp/\n"),
+                ("sub/.gitignore", "!keep.log\n"),
+                ("sub/.ignore", "local.rs\n"),
+                ("a.rs", ""),
+                ("a.log", ""),
+                ("secret.txt", ""),
+                ("build/out.rs", ""),
+                ("tmp/x.rs", ""),
+                ("sub/keep.log", ""),
+                ("sub/other.log", ""),
+                ("sub/local.rs", ""),
+                ("node_modules/m.js", ""),
+            ],
+        );
+        let visible = walked(&cfg);
+        assert_eq!(
+            visible,
+            [
+                ".ailoomignore",
+                ".gitignore",
+                "a.rs",
+                "sub/.gitignore",
+                "sub/.ignore",
+                "sub/keep.log"
+            ]
+        );
+        for rel in [
+            "a.log",
+            "secret.txt",
+            "build/out.rs",
+            "tmp/x.rs",
+            "sub/other.log",
+            "sub/local.rs",
+            "node_modules/m.js",
+            ".git/HEAD",
+        ] {
+            assert!(is_ignored(&cfg, rel, false), "{rel}");
+        }
+        // 复用同一个 matcher 的批量判定与逐个判定一致
+        let mut matcher = IgnoreMatcher::new(&cfg);
+        for rel in &visible {
+            assert!(!is_ignored(&cfg, rel, false), "{rel}");
+            assert!(!matcher.is_ignored(rel, false), "{rel}");
+        }
+        assert!(matcher.is_ignored("sub/other.log", false));
+        assert!(matcher.is_ignored("tmp/x.rs", false));
+        assert!(is_ignored(&cfg, "build", true));
+        // 新建路径（尚不存在）同样按规则判定
+        assert!(is_ignored(&cfg, "build/new/deep.rs", false));
+        assert!(!is_ignored(&cfg, "src/new.rs", false));
+    }
+
+    #[test]
+    fn is_ignored_skips_git_rules_outside_a_repo() {
+        let cfg = fixture(
+            "nogit",
+            &[
+                (".gitignore", "*.log\n"),
+                (".ailoomignore", "*.tmp\n"),
+                ("a.log", ""),
+                ("b.tmp", ""),
+            ],
+        );
+        assert!(walked(&cfg).contains(&"a.log".to_string()));
+        assert!(!is_ignored(&cfg, "a.log", false));
+        assert!(is_ignored(&cfg, "b.tmp", false));
+    }
+
+    fn search(
+        cfg: &FsConfig,
+        query: &str,
+        context_lines: usize,
+        max_results: usize,
+    ) -> (Vec<SearchMatch>, SearchSummary) {
+        let opts = SearchOptions {
+            query: query.into(),
+            regex: false,
+            case_sensitive: false,
+            glob: None,
+            context_lines,
+            max_results,
+        };
+        let mut hits = Vec::new();
+        let summary = Searcher::new(cfg, &opts).unwrap().run(|m| {
+            hits.push(m);
+            true
+        });
+        (hits, summary)
+    }
+
+    #[test]
+    fn search_reports_every_match_with_context_and_skips_binary() {
+        let cfg = fixture(
+            "search",
+            &[
+                ("a.txt", "one\nfoo x FOO\nthree\nfoo\n"),
+                ("bin.dat", "foo\0foo\n"),
+                (".ailoomignore", "skip/\n"),
+                ("skip/c.txt", "foo\n"),
+            ],
+        );
+        let (hits, summary) = search(&cfg, "foo", 1, 100);
+        let got: Vec<_> = hits
+            .iter()
+            .map(|m| (m.path.as_str(), m.line, m.column, m.end_column))
+            .collect();
+        assert_eq!(
+            got,
+            [("a.txt", 2, 1, 4), ("a.txt", 2, 7, 10), ("a.txt", 4, 1, 4)]
+        );
+        assert_eq!(
+            (hits[0].before.as_slice(), hits[0].after.as_slice()),
+            (&["one".to_string()][..], &["three".to_string()][..])
+        );
+        // 末行命中：后文在文件结束时截止
+        assert!(hits[2].after.is_empty());
+        assert_eq!(
+            (summary.matches, summary.files_skipped, summary.truncated),
+            (3, 1, false)
+        );
+
+        let (hits, summary) = search(&cfg, "foo", 0, 2);
+        assert_eq!((hits.len(), summary.truncated), (2, true));
+    }
+
+    #[test]
+    fn list_dir_shares_walker_ignore_rules() {
+        let cfg = fixture(
+            "list",
+            &[
+                (".ailoomignore", "*.tmp\n"),
+                ("sub/a.tmp", ""),
+                ("sub/b.rs", ""),
+                ("node_modules/m.js", ""),
+            ],
+        );
+        let names: Vec<String> = list_dir(&cfg, "sub")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+        assert_eq!(names, ["sub/b.rs"]);
+        let top: Vec<String> = list_dir(&cfg, "")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(top, ["sub", ".ailoomignore"]);
+    }
+}
@@ -0,0 +1,524 @@
+use ailoom_core::{DiffHunk, DiffLine, DiffLineKind, FileDiff, GitFileStatus, GitStatus};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, OnceLock},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum GitError {
+    #[error("git failed: {0}")]
+    Command(String),
+    #[error("invalid ref: {0}")]
+    InvalidRef(String),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// 以 Git CLI 为执行层的只读仓库句柄（路径均相对仓库根，即工作区根）。
+#[derive(Debug, Clone)]
+pub struct GitRepo {
+    root: PathBuf,
+    /// 根提交（进程内缓存，克隆共享）
+    root_commit: Arc<OnceLock<String>>,
+}
+
+impl GitRepo {
+    /// 打开 `dir` 所在仓库；非仓库或本机无 git 时返回 `None`。
+    pub fn open(dir: &Path) -> Option<Self> {
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["rev-parse", "--show-toplevel"])
+            .output()
+            .ok()?;
+        if !out.status.success() {
+            return None;
+        }
+        let top = String::from_utf8_lossy(&out.stdout).trim().to_string();
+        let root = PathBuf::from(top).canonicalize().ok()?;
+        Some(Self {
+            root,
+            root_commit: Arc::default(),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub(crate) fn run(&self, args: &[&str]) -> Result<Vec<u8>, GitError> {
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(&self.root)
+            .args(["-c", "core.quotepath=off"])
+            .args(args)
+            // 只读查询不抢占 index.lock，避免与用户/IDE 的 git 操作冲突
+            .env("GIT_OPTIONAL_LOCKS", "0")
+            .output()?;
+        if !out.status.success() {
+            return Err(GitError::Command(
+                String::from_utf8_lossy(&out.stderr).trim().to_string(),
+            ));
+        }
+        Ok(out.stdout)
+    }
+
+    /// 未提交文件（含未跟踪），可选 pathspec 过滤。解析 `git status --porcelain -z`。
+    pub fn status(&self, pathspecs: &[String]) -> Result<Vec<GitFileStatus>, GitError> {
+        let mut args = vec!["status", "--porcelain=v1", "-z", "--untracked-files=all"];
+        if !pathspecs.is_empty() {
+            args.push("--");
+            args.extend(pathspecs.iter().map(|s| s.as_str()));
+        }
+        let out = self.run(&args)?;
+        Ok(parse_porcelain_z(&String::from_utf8_lossy(&out)))
+    }
+
+    /// 将 ref（分支/标签/commit/`HEAD~n` 等）解析为完整 commit id；以 `-` 开头或无法解析时返回 `InvalidRef`。
+    pub fn resolve_commit(&self, rev: &str) -> Result<String, GitError> {
+        if rev.is_empty() || rev.starts_with('-') {
+            return Err(GitError::InvalidRef(rev.to_string()));
+        }
+        let spec = format!("{}^{{commit}}", rev);
+        match self.run(&["rev-parse", "--verify", "--quiet", &spec]) {
+            Ok(out) => Ok(String::from_utf8_lossy(&out).trim().to_string()),
+            Err(GitError::Command(_)) => Err(GitError::InvalidRef(rev.to_string())),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 单文件工作区内容相对 `base` 的 diff（含未暂存改动）。
+    /// 未跟踪文件视作整体新增；行号均映射到当前文件（见 `DiffLine::anchor_line`）。
+    pub fn diff_file(&self, path: &str, base: &str) -> Result<FileDiff, GitError> {
+        let base_commit = self.resolve_commit(base)?;
+        let out = self.run(&[
+            "diff",
+            "--no-color",
+            "--no-ext-diff",
+            "--find-renames",
+            "-U3",
+            &base_commit,
+            "--",
+            path,
+        ])?;
+        let mut diff = parse_unified_diff(&String::from_utf8_lossy(&out));
+        diff.path = path.to_string();
+        diff.base = base.to_string();
+        diff.base_commit = base_commit;
+        if diff.hunks.is_empty() && !diff.binary && self.is_untracked(path)? {
+            match std::fs::read(self.root.join(path)) {
+                Ok(bytes) if bytes.contains(&0) => diff.binary = true,
+                Ok(bytes) => diff.hunks = added_file_hunk(&String::from_utf8_lossy(&bytes)),
+                Err(_) => {}
+            }
+        }
+        // 文件末尾的删除行没有“后一行”：回落到当前文件最后一行
+        let total = std::fs::read(self.root.join(path))
+            .map(|b| String::from_utf8_lossy(&b).lines().count())
+            .unwrap_or(0)
+            .max(1);
+        for l in diff.hunks.iter_mut().flat_map(|h| h.lines.iter_mut()) {
+            l.anchor_line = l.anchor_line.clamp(1, total);
+        }
+        Ok(diff)
+    }
+
+    /// 最近改动过 `path` 的提交（新→旧，最多 `max` 个），即该文件在历史中的各个版本。
+    pub fn file_commits(&self, path: &str, max: usize) -> Result<Vec<String>, GitError> {
+        let n = format!("--max-count={}", max);
+        let out = self.run(&["log", "--format=%H", &n, "--", path])?;
+        Ok(String::from_utf8_lossy(&out)
+            .lines()
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect())
+    }
+
+    /// 文件在指定提交中的原始内容（`git show <rev>:<path>`）。
+    pub fn show_file(&self, rev: &str, path: &str) -> Result<Vec<u8>, GitError> {
+        if rev.starts_with('-') {
+            return Err(GitError::InvalidRef(rev.to_string()));
+        }
+        self.run(&["show", &format!("{}:{}", rev, path)])
+    }
+
+    /// 与检出位置无关的仓库标识：`git:<规范化的 remote URL>#<根提交>`（无 remote 时省略 URL）。
+    /// 优先取 `origin`，否则取第一个 remote；尚无提交时返回 `None`。
+    pub fn identity(&self) -> Option<String> {
+        let root = self.root_commit()?;
+        let remotes = self.run(&["remote"]).unwrap_or_default();
+        let remotes = String::from_utf8_lossy(&remotes);
+        let name = remotes
+            .lines()
+            .find(|r| *r == "origin")
+            .or_else(|| remotes.lines().next());
+        let url = name
+            .and_then(|n| self.run(&["remote", "get-url", n]).ok())
+            .map(|u| normalize_remote_url(String::from_utf8_lossy(&u).trim()))
+            .unwrap_or_default();
+        Some(format!("git:{}#{}", url, root))
+    }
+
+    /// HEAD 的根提交。`rev-list` 需遍历全部历史，结果在进程内缓存（不写入 `.git`）；
+    /// 浅克隆的边界会随加深而变化，不缓存。
+    fn root_commit(&self) -> Option<String> {
+        if let Some(root) = self.root_commit.get() {
+            return Some(root.clone());
+        }
+        let roots = self.run(&["rev-list", "--max-parents=0", "HEAD"]).ok()?;
+        // 合并过多段历史时会有多个根提交：取最小者保证稳定
+        let root = String::from_utf8_lossy(&roots)
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .min()?
+            .to_string();
+        let shallow = self
+            .run(&["rev-parse", "--is-shallow-repository"])
+            .map(|o| String::from_utf8_lossy(&o).trim() == "true")
+            .unwrap_or(true);
+        if !shallow {
+            let _ = self.root_commit.set(root.clone());
+        }
+        Some(root)
+    }
+
+    fn is_untracked(&self, path: &str) -> Result<bool, GitError> {
+        let out = self.run(&["ls-files", "--others", "--exclude-standard", "--", path])?;
+        Ok(!out.is_empty())
+    }
+}
+
+/// 规范化 remote URL，使同一仓库的 https/ssh/scp 写法得到相同结果：
+/// 去掉协议、用户名、末尾的 `/` 与 `.git`，主机名小写（`git@github.com:o/r.git` → `github.com/o/r`）。
+pub fn normalize_remote_url(url: &str) -> String {
+    let mut s = url.trim();
+    let had_scheme = match s.find("://") {
+        Some(i) => {
+            s = &s[i + 3..];
+            true
+        }
+        None => false,
+    };
+    if let Some(i) = s.find('@') {
+        if !s[..i].contains('/') {
+            s = &s[i + 1..];
+        }
+    }
+    let s = s.trim_end_matches('/');
+    let s = s.strip_suffix(".git").unwrap_or(s);
+    // scp 写法 host:path（无协议且冒号前没有 `/`）
+    let (host, path) = match s.find([':', '/']) {
+        Some(i) if &s[i..i + 1] == ":" && !had_scheme => (&s[..i], &s[i + 1..]),
+        Some(i) if &s[i..i + 1] == ":" => {
+            // 带端口：host:port/path
+            let j = s[i..].find('/').map(|j| i + j).unwrap_or(s.len());
+            (&s[..i], s.get(j + 1..).unwrap_or(""))
+        }
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, ""),
+    };
+    let path = path.trim_start_matches('/');
+    if path.is_empty() {
+        host.to_ascii_lowercase()
+    } else {
+        format!("{}/{}", host.to_ascii_lowercase(), path)
+    }
+}
+
+/// 将 diff 基线侧的行号映射到当前文件行号；该行在 diff 中被删除/改写时返回 `None`。
+/// hunk 之外的行按此前 hunk 累计的行数增减平移。
+pub fn map_old_line(diff: &FileDiff, old_line: usize) -> Option<usize> {
+    let mut offset: isize = 0;
+    for h in &diff.hunks {
+        let old_end = h.old_start + h.old_lines;
+        if h.old_lines > 0 && old_line >= h.old_start && old_line < old_end {
+            return h
+                .lines
+                .iter()
+                .find(|l| l.old_line == Some(old_line))
+                .and_then(|l| l.new_line);
+        }
+        // 纯新增 hunk 的 old_start 指向插入点之前一行
+        let passed = if h.old_lines == 0 {
+            old_line > h.old_start
+        } else {
+            old_line >= old_end
+        };
+        if !passed {
+            break;
+        }
+        offset += h.new_lines as isize - h.old_lines as isize;
+    }
+    usize::try_from(old_line as isize + offset)
+        .ok()
+        .filter(|n| *n > 0)
+}
+
+fn added_file_hunk(content: &str) -> Vec<DiffHunk> {
+    let lines: Vec<DiffLine> = content
+        .lines()
+        .enumerate()
+        .map(|(i, t)| DiffLine {
+            kind: DiffLineKind::Add,
+            old_line: None,
+            new_line: Some(i + 1),
+            anchor_line: i + 1,
+            text: t.to_string(),
+        })
+        .collect();
+    if lines.is_empty() {
+        return Vec::new();
+    }
+    vec![DiffHunk {
+        old_start: 0,
+        old_lines: 0,
+        new_start: 1,
+        new_lines: lines.len(),
+        header: String::new(),
+        lines,
+    }]
+}
+
+fn parse_hunk_header(line: &str) -> Option<(usize, usize, usize, usize, String)> {
+    // @@ -a[,b] +c[,d] @@ section
+    let rest = line.strip_prefix("@@ -")?;
+    let (ranges, section) = rest.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+    let range = |r: &str| -> Option<(usize, usize)> {
+        match r.split_once(',') {
+            Some((s, n)) => Some((s.parse().ok()?, n.parse().ok()?)),
+            None => Some((r.parse().ok()?, 1)),
+        }
+    };
+    let (os, ol) = range(old)?;
+    let (ns, nl) = range(new)?;
+    Some((os, ol, ns, nl, section.trim().to_string()))
+}
+
+/// 解析单文件 `git diff` 输出。删除行的 `anchor_line` 指向其后紧跟的当前文件行（位于文件末尾时取最后一行）。
+fn parse_unified_diff(s: &str) -> FileDiff {
+    let mut diff = FileDiff {
+        path: String::new(),
+        base: String::new(),
+        base_commit: String::new(),
+        old_path: None,
+        binary: false,
+        hunks: Vec::new(),
+    };
+    let (mut old_no, mut new_no) = (0usize, 0usize);
+    for line in s.lines() {
+        if let Some((os, ol, ns, nl, header)) = parse_hunk_header(line) {
+            diff.hunks.push(DiffHunk {
+                old_start: os,
+                old_lines: ol,
+                new_start: ns,
+                new_lines: nl,
+                header,
+                lines: Vec::new(),
+            });
+            // 纯删除 hunk 的 new_start 指向删除点之前一行
+            old_no = os;
+            new_no = if nl == 0 { ns + 1 } else { ns };
+            continue;
+        }
+        let Some(hunk) = diff.hunks.last_mut() else {
+            if let Some(from) = line.strip_prefix("rename from ") {
+                diff.old_path = Some(from.to_string());
+            } else if line.starts_with("Binary files ") || line == "GIT binary patch" {
+                diff.binary = true;
+            }
+            continue;
+        };
+        let (kind, text) = match line.as_bytes().first() {
+            Some(b' ') => (DiffLineKind::Context, &line[1..]),
+            Some(b'+') => (DiffLineKind::Add, &line[1..]),
+            Some(b'-') => (DiffLineKind::Delete, &line[1..]),
+            // "\ No newline at end of file" 等元信息
+            _ => continue,
+        };
+        let (old_line, new_line, anchor_line) = match kind {
+            DiffLineKind::Context => {
+                old_no += 1;
+                new_no += 1;
+                (Some(old_no - 1), Some(new_no - 1), new_no - 1)
+            }
+            DiffLineKind::Add => {
+                new_no += 1;
+                (None, Some(new_no - 1), new_no - 1)
+            }
+            DiffLineKind::Delete => {
+                old_no += 1;
+                (Some(old_no - 1), None, new_no)
+            }
+        };
+        hunk.lines.push(DiffLine {
+            kind,
+            old_line,
+            new_line,
+            anchor_line,
+            text: text.to_string(),
+        });
+    }
+    diff
+}
+
+fn parse_porcelain_z(s: &str) -> Vec<GitFileStatus> {
+    let mut res = Vec::new();
+    let mut parts = s.split('\0');
+    while let Some(entry) = parts.next() {
+        if entry.len() < 4 {
+            continue;
+        }
+        let (x, y) = (entry.as_bytes()[0] as char, entry.as_bytes()[1] as char);
+        let path = entry[3..].to_string();
+        // 重命名/复制：下一段为原路径
+        let orig_path = if x == 'R' || x == 'C' || y == 'R' || y == 'C' {
+            parts.next().map(|p| p.to_string())
+        } else {
+            None
+        };
+        let status = match (x, y) {
+            ('?', '?') => GitStatus::Untracked,
+            ('U', _) | (_, 'U') | ('A', 'A') | ('D', 'D') => GitStatus::Conflicted,
+            ('R', _) | (_, 'R') => GitStatus::Renamed,
+            ('A', _) | ('C', _) | (_, 'A') => GitStatus::Added,
+            ('D', _) | (_, 'D') => GitStatus::Deleted,
+            _ => GitStatus::Modified,
+        };
+        let staged = x != ' ' && x != '?';
+        res.push(GitFileStatus {
+            path,
+            status,
+            staged,
+            orig_path,
+        });
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_remote_urls() {
+        for url in [
+            "https://github.com/Owner/Repo.git",
+            "git@github.com:Owner/Repo.git",
+            "ssh://git@GitHub.com/Owner/Repo/",
+            "ssh://git@github.com:22/Owner/Repo.git",
+        ] {
+            assert_eq!(
+                normalize_remote_url(url),
+                "github.com/Owner/Repo",
+                "{}",
+                url
+            );
+        }
+        assert_eq!(normalize_remote_url("/srv/git/repo.git"), "/srv/git/repo");
+    }
+
+    #[test]
+    fn caches_the_root_commit_without_touching_the_git_dir() {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir =
+            std::env::temp_dir().join(format!("ailoom_git_{}_{}_root", std::process::id(), nanos));
+        std::fs::create_dir_all(&dir).unwrap();
+        let git = |args: &[&str]| {
+            let ok = Command::new("git")
+                .arg("-C")
+                .arg(&dir)
+                .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+                .args(args)
+                .output()
+                .map(|o| o.status.success())
+                .unwrap_or(false);
+            assert!(ok, "git {:?} failed", args);
+        };
+        git(&["init", "-q"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "root"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "second"]);
+        let repo = GitRepo::open(&dir).unwrap();
+        let root = String::from_utf8(repo.run(&["rev-list", "--max-parents=0", "HEAD"]).unwrap())
+            .unwrap()
+            .trim()
+            .to_string();
+        assert_eq!(repo.identity(), Some(format!("git:#{}", root)));
+        assert!(!dir.join(".git").join("ailoom").exists());
+
+        // Clones share the cache, so later calls skip rev-list
+        let clone = repo.clone();
+        assert_eq!(clone.root_commit.get(), Some(&root));
+        assert_eq!(clone.identity(), Some(format!("git:#{}", root)));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn parses_porcelain_z_entries() {
+        let raw = " M src/a.rs\0A  new.rs\0R  b2.rs\0b.rs\0?? notes/x y.md\0UU c.rs\0";
+        let v = parse_porcelain_z(raw);
+        assert_eq!(v.len(), 5);
+        assert_eq!(
+            (v[0].path.as_str(), v[0].status, v[0].staged),
+            ("src/a.rs", GitStatus::Modified, false)
+        );
+        assert_eq!((v[1].status, v[1].staged), (GitStatus::Added, true));
+        assert_eq!(v[2].status, GitStatus::Renamed);
+        assert_eq!(v[2].orig_path.as_deref(), Some("b.rs"));
+        assert_eq!(
+            (v[3].path.as_str(), v[3].status),
+            ("notes/x y.md", GitStatus::Untracked)
+        );
+        assert_eq!(v[4].status, GitStatus::Conflicted);
+    }
+
+    #[test]
+    fn parses_unified_diff_and_maps_to_current_lines() {
+        let raw = "diff --git a/a.rs b/a.rs\nindex 1..2 100644\n--- a/a.rs\n+++ b/a.rs\n\
+@@ -1,4 +1,4 @@ fn main() {\n one\n-two\n+TWO\n three\n four\n\
+@@ -10,2 +9,0 @@\n-gone1\n-gone2\n\\ No newline at end of file\n";
+        let d = parse_unified_diff(raw);
+        assert_eq!(d.hunks.len(), 2);
+        let h = &d.hunks[0];
+        assert_eq!(h.header, "fn main() {");
+        assert_eq!(h.lines.len(), 5);
+        assert_eq!(
+            (h.lines[1].kind, h.lines[1].old_line, h.lines[1].anchor_line),
+            (DiffLineKind::Delete, Some(2), 2)
+        );
+        assert_eq!(
+            (h.lines[2].kind, h.lines[2].new_line),
+            (DiffLineKind::Add, Some(2))
+        );
+        assert_eq!(
+            (h.lines[4].old_line, h.lines[4].new_line),
+            (Some(4), Some(4))
+        );
+        // 纯删除：挂在删除点之后一行
+        let h2 = &d.hunks[1];
+        assert_eq!(h2.lines.len(), 2);
+        assert_eq!(
+            (h2.lines[1].old_line, h2.lines[1].anchor_line),
+            (Some(11), 10)
+        );
+    }
+
+    #[test]
+    fn maps_old_lines_through_hunks() {
+        // 第 2 行改写，第 5 行后插入两行，第 8 行删除
+        let raw = "@@ -2 +2 @@\n-b\n+B\n@@ -5,0 +6,2 @@\n+x\n+y\n@@ -8 +9,0 @@\n-h\n";
+        let d = parse_unified_diff(raw);
+        assert_eq!(map_old_line(&d, 1), Some(1));
+        assert_eq!(map_old_line(&d, 2), None);
+        assert_eq!(map_old_line(&d, 5), Some(5));
+        assert_eq!(map_old_line(&d, 6), Some(8));
+        assert_eq!(map_old_line(&d, 8), None);
+        assert_eq!(map_old_line(&d, 9), Some(10));
+    }
+}
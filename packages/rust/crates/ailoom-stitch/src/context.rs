@@ -0,0 +1,271 @@
+use crate::StitchOptions;
+use ailoom_core::{guess_language_by_ext, Annotation};
+use std::collections::HashMap;
+
+/// 扩展到代码块时允许的最大行数；超过则退回按行数取上下文
+pub const MAX_BLOCK_LINES: usize = 200;
+
+/// 选区周围代码上下文的取法
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContextOptions {
+    /// 选区前后各带的行数
+    pub lines: usize,
+    /// 扩展到包围选区的函数/代码块（在其基础上再加 `lines` 行）；找不到时退回按行数
+    pub expand_block: bool,
+}
+
+impl ContextOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.lines > 0 || self.expand_block
+    }
+}
+
+/// 带行号与标记的代码片段（行号 1 起，闭区间）
+#[derive(Debug, Clone)]
+pub(crate) struct CodeSnippet {
+    pub start: usize,
+    pub end: usize,
+    pub language: String,
+    pub text: String,
+}
+
+/// 一个输出单元：未取上下文时为单条批注；取上下文时同一文件中区间重叠/相邻的批注合并为一组，共用一个片段
+pub(crate) struct Group<'a> {
+    /// 组内按起始行排序；标记编号即下标 + 1
+    pub members: Vec<&'a Annotation>,
+    pub code: Option<CodeSnippet>,
+}
+
+impl Group<'_> {
+    pub fn ids(&self) -> Vec<String> {
+        self.members.iter().map(|a| a.id.clone()).collect()
+    }
+}
+
+/// 按已排序的批注生成输出单元：组的位置取其中最先出现的成员
+pub(crate) fn group_annotations<'a>(
+    anns: &'a [Annotation],
+    opts: &StitchOptions,
+) -> Vec<Group<'a>> {
+    if !opts.context.is_enabled() {
+        return anns
+            .iter()
+            .map(|a| Group {
+                members: vec![a],
+                code: None,
+            })
+            .collect();
+    }
+    let files: HashMap<&str, Vec<&str>> = opts
+        .files
+        .iter()
+        .map(|(p, text)| (p.as_str(), text.lines().collect()))
+        .collect();
+    let ranges: Vec<Option<(usize, usize)>> = anns
+        .iter()
+        .map(|a| {
+            let lines = files.get(a.file_path.as_str())?;
+            context_range(lines, a, opts.context)
+        })
+        .collect();
+
+    // 同一文件内按区间起点排序后合并重叠或相邻的区间
+    let mut by_file: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, a) in anns.iter().enumerate() {
+        if ranges[i].is_some() {
+            by_file.entry(a.file_path.as_str()).or_default().push(i);
+        }
+    }
+    let mut cluster_of: Vec<Option<usize>> = vec![None; anns.len()];
+    let mut clusters: Vec<(Vec<usize>, (usize, usize))> = Vec::new();
+    for idxs in by_file.values_mut() {
+        idxs.sort_by_key(|&i| ranges[i]);
+        for &i in idxs.iter() {
+            let (s, e) = ranges[i].unwrap_or_default();
+            match clusters.last_mut() {
+                Some((members, range))
+                    if anns[members[0]].file_path == anns[i].file_path && s <= range.1 + 1 =>
+                {
+                    members.push(i);
+                    range.1 = range.1.max(e);
+                }
+                _ => clusters.push((vec![i], (s, e))),
+            }
+            cluster_of[i] = Some(clusters.len() - 1);
+        }
+    }
+
+    let mut emitted = vec![false; clusters.len()];
+    let mut out = Vec::new();
+    for (i, a) in anns.iter().enumerate() {
+        let Some(c) = cluster_of[i] else {
+            out.push(Group {
+                members: vec![a],
+                code: None,
+            });
+            continue;
+        };
+        if std::mem::replace(&mut emitted[c], true) {
+            continue;
+        }
+        let (idxs, (start, end)) = &clusters[c];
+        let mut members: Vec<&Annotation> = idxs.iter().map(|&j| &anns[j]).collect();
+        members.sort_by_key(|m| (m.start_line, m.end_line));
+        let lines = &files[a.file_path.as_str()];
+        out.push(Group {
+            code: Some(render_snippet(lines, *start, *end, &members)),
+            members,
+        });
+    }
+    out
+}
+
+/// 单条批注的上下文区间（未合并）；文件不可用或批注不在工作区文件中时为 `None`
+pub(crate) fn context_range(
+    lines: &[&str],
+    a: &Annotation,
+    ctx: ContextOptions,
+) -> Option<(usize, usize)> {
+    // diff 旧侧选中的是基线内容，不在工作区文件中
+    if a.diff_side.as_deref() == Some("old") || lines.is_empty() {
+        return None;
+    }
+    let start = a.start_line.max(1) as usize;
+    if start > lines.len() {
+        return None;
+    }
+    let end = (a.end_line.max(a.start_line) as usize).min(lines.len());
+    let (s, e) = if ctx.expand_block {
+        enclosing_block(lines, start, end)
+            .filter(|(s, e)| e - s < MAX_BLOCK_LINES)
+            .unwrap_or((start, end))
+    } else {
+        (start, end)
+    };
+    Some((
+        s.saturating_sub(ctx.lines).max(1),
+        (e + ctx.lines).min(lines.len()),
+    ))
+}
+
+/// 渲染 `[start, end]` 行：每行带行号，落在某条批注选区内的行标注 `>N`（N 为组内编号）
+pub(crate) fn render_snippet(
+    lines: &[&str],
+    start: usize,
+    end: usize,
+    members: &[&Annotation],
+) -> CodeSnippet {
+    let width = end.to_string().len();
+    let marks: Vec<String> = (start..=end)
+        .map(|n| {
+            let hit: Vec<String> = members
+                .iter()
+                .enumerate()
+                .filter(|(_, m)| (m.start_line as usize) <= n && n <= (m.end_line as usize))
+                .map(|(i, _)| (i + 1).to_string())
+                .collect();
+            if hit.is_empty() {
+                String::new()
+            } else {
+                format!(">{}", hit.join(","))
+            }
+        })
+        .collect();
+    let mark_width = marks.iter().map(String::len).max().unwrap_or(0);
+    let text = (start..=end)
+        .zip(&marks)
+        .map(|(n, mark)| {
+            let line = format!("{n:>width$} {mark:<mark_width$} | {}", lines[n - 1]);
+            line.trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    CodeSnippet {
+        start,
+        end,
+        language: guess_language_by_ext(&members[0].file_path),
+        text,
+    }
+}
+
+/// 包围 `[start, end]` 的最近代码块（1 起闭区间）：优先按花括号配对，
+/// 找不到时按缩进（如 Python）；选区已处于顶层时返回 `None`
+pub fn enclosing_block(lines: &[&str], start: usize, end: usize) -> Option<(usize, usize)> {
+    if start == 0 || end < start || end > lines.len() {
+        return None;
+    }
+    brace_block(lines, start - 1, end - 1)
+        .or_else(|| indent_block(lines, start - 1, end - 1))
+        .map(|(s, e)| (s + 1, e + 1))
+}
+
+/// 逐行的最小花括号深度（含行首）；忽略字符串与 `//` 注释中的花括号
+fn brace_depths(lines: &[&str]) -> Vec<i32> {
+    let mut depth = 0i32;
+    lines
+        .iter()
+        .map(|line| {
+            let mut min = depth;
+            let mut in_str = false;
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' if in_str => {
+                        chars.next();
+                    }
+                    '"' => in_str = !in_str,
+                    '/' if !in_str && chars.peek() == Some(&'/') => break,
+                    '{' if !in_str => depth += 1,
+                    '}' if !in_str => {
+                        depth -= 1;
+                        min = min.min(depth);
+                    }
+                    _ => {}
+                }
+            }
+            min
+        })
+        .collect()
+}
+
+fn brace_block(lines: &[&str], s: usize, e: usize) -> Option<(usize, usize)> {
+    let depths = brace_depths(lines);
+    let level = depths[s..=e].iter().copied().min()?;
+    if level <= 0 {
+        return None;
+    }
+    let open = (0..s).rev().find(|&i| depths[i] < level)?;
+    let close = (e + 1..lines.len()).find(|&i| depths[i] < level)?;
+    // Allman 风格：`{` 独占一行时带上上一行的签名
+    let open = if open > 0 && lines[open].trim() == "{" {
+        open - 1
+    } else {
+        open
+    };
+    Some((open, close))
+}
+
+fn indent_block(lines: &[&str], s: usize, e: usize) -> Option<(usize, usize)> {
+    let indent = |l: &str| l.len() - l.trim_start().len();
+    let blank = |l: &str| l.trim().is_empty();
+    let span = lines[s..=e]
+        .iter()
+        .filter(|l| !blank(l))
+        .map(|l| indent(l))
+        .min()?;
+    let open = (0..s)
+        .rev()
+        .find(|&i| !blank(lines[i]) && indent(lines[i]) < span)?;
+    let base = indent(lines[open]);
+    let mut close = e;
+    for (i, l) in lines.iter().enumerate().skip(e + 1) {
+        if blank(l) {
+            continue;
+        }
+        if indent(l) <= base {
+            break;
+        }
+        close = i;
+    }
+    Some((open, close))
+}
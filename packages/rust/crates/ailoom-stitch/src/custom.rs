@@ -0,0 +1,265 @@
+use crate::context::{context_range, render_snippet, ContextOptions};
+use crate::{
+    collapse_middle_lines, diff_note, fence_for, pack, resolution, sort_annotations,
+    InclusionLevel, StitchOptions, StitchResult,
+};
+use ailoom_core::{guess_language_by_ext, Annotation, AnnotationComment};
+use minijinja::{AutoEscape, Environment, Error, ErrorKind, Value};
+use serde::Serialize;
+use std::fmt;
+
+const TEMPLATE_NAME: &str = "stitch";
+/// 未指定上下文行数时 `before`/`after` 的行数
+const DEFAULT_SURROUNDING_LINES: usize = 3;
+
+/// 自定义模板的编译或渲染错误
+#[derive(Debug, Clone)]
+pub struct TemplateError {
+    pub message: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+impl From<Error> for TemplateError {
+    fn from(e: Error) -> Self {
+        let mut message = e.to_string();
+        if let Some(detail) = e.detail() {
+            if !message.contains(detail) {
+                message = format!("{message}: {detail}");
+            }
+        }
+        Self { message }
+    }
+}
+
+/// 自定义拼接模板（MiniJinja 语法）。
+///
+/// 模板以宏描述输出：必须定义 `item(item)`，可选 `header(stats)` 与 `footer(stats)`；宏之外的文本不输出。
+/// 预算装箱与内置模板一致：先输出 header，再逐条输出 item，最后输出 footer（其长度预先计入预算）；
+/// 预算不足时 item 以 `item.level` 为 collapsed / comment 重新渲染。
+pub struct CustomTemplate {
+    name: String,
+    env: Environment<'static>,
+}
+
+impl fmt::Debug for CustomTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomTemplate")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+/// header/footer 宏可见的统计；header 渲染时 `used`/`truncated` 尚未确定，按全部纳入计
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatsCtx<'a> {
+    template: &'a str,
+    total: usize,
+    used: usize,
+    truncated: bool,
+    omitted: usize,
+    max_chars: usize,
+    max_tokens: Option<usize>,
+    tokenizer: &'a str,
+}
+
+/// item 宏的入参：批注全部字段外加派生信息
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ItemCtx<'a> {
+    #[serde(flatten)]
+    annotation: &'a Annotation,
+    /// 序号（从 1 开始）
+    index: usize,
+    /// 纳入程度：full / collapsed / comment；后两者的片段更短或为空，不带上下文与线程
+    level: &'static str,
+    language: String,
+    /// 按行做中间省略后的选中文本
+    snippet: String,
+    fence: &'static str,
+    diff_note: Option<String>,
+    resolution: Option<String>,
+    thread: &'a [AnnotationComment],
+    before: String,
+    after: String,
+    /// 取上下文时的带行号片段（单条批注，不与其他批注合并）
+    code: Option<CodeCtx>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CodeCtx {
+    start_line: usize,
+    end_line: usize,
+    text: String,
+}
+
+impl CustomTemplate {
+    /// 编译模板并用示例批注试渲染，尽早暴露语法错误、缺少 `item` 宏或运行期错误
+    pub fn compile(name: &str, source: &str) -> Result<Self, TemplateError> {
+        let mut env = Environment::new();
+        env.set_auto_escape_callback(|_| AutoEscape::None);
+        // 块标签所在行不留空行/缩进，便于按行书写宏
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+        env.add_template_owned(TEMPLATE_NAME, source.to_string())?;
+        let tpl = Self {
+            name: name.to_string(),
+            env,
+        };
+        let sample = Annotation {
+            id: "sample".into(),
+            file_path: "src/main.rs".into(),
+            start_line: 1,
+            end_line: 1,
+            selected_text: "fn main() {}".into(),
+            comment: "sample".into(),
+            priority: Some("P1".into()),
+            ..Default::default()
+        };
+        generate_custom(&tpl, usize::MAX, vec![sample], &StitchOptions::default())?;
+        Ok(tpl)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// 以自定义模板拼接：排序与预算规则与 [`crate::generate_prompt`] 相同
+pub fn generate_custom(
+    tpl: &CustomTemplate,
+    max_chars: usize,
+    mut anns: Vec<Annotation>,
+    opts: &StitchOptions,
+) -> Result<StitchResult, TemplateError> {
+    sort_annotations(&mut anns);
+    let template = tpl.env.get_template(TEMPLATE_NAME)?;
+    let captured = template.render_captured(())?;
+    let state = captured.state();
+    if state.lookup("item").is_none() {
+        return Err(Error::new(
+            ErrorKind::InvalidOperation,
+            "template must define macro `item(item)`",
+        )
+        .into());
+    }
+    let has = |name: &str| state.lookup(name).is_some();
+    let stats = |used: usize| StatsCtx {
+        template: tpl.name(),
+        total: anns.len(),
+        used,
+        truncated: used < anns.len(),
+        omitted: anns.len() - used,
+        max_chars,
+        max_tokens: opts.max_tokens,
+        tokenizer: opts.tokenizer.name(),
+    };
+    let call = |name: &str, arg: Value| -> Result<String, Error> {
+        if has(name) {
+            state.call_macro(name, &[arg])
+        } else {
+            Ok(String::new())
+        }
+    };
+
+    let header = call("header", Value::from_serialize(stats(anns.len())))?;
+    let footer = |used: usize| call("footer", Value::from_serialize(stats(used)));
+    // footer 随纳入条数变化：预留全部纳入与全部省略两端中较长者
+    let (all, none) = (footer(anns.len())?, footer(0)?);
+    let mut reserve = if none.chars().count() > all.chars().count() {
+        none
+    } else {
+        all
+    };
+    let ids = |a: &Annotation| vec![a.id.clone()];
+    let mut render = |i: usize, a: &Annotation, level: InclusionLevel| {
+        let raw = a.selected_text.trim();
+        let snippet = match level {
+            InclusionLevel::Full => collapse_middle_lines(raw, 20, 20, 40),
+            InclusionLevel::Collapsed => collapse_middle_lines(raw, 3, 3, 6),
+            _ => String::new(),
+        };
+        let full = level == InclusionLevel::Full;
+        let lines: Vec<&str> = opts
+            .files
+            .get(&a.file_path)
+            .map(|t| t.lines().collect())
+            .unwrap_or_default();
+        let n = match opts.context.lines {
+            0 => DEFAULT_SURROUNDING_LINES,
+            n => n,
+        };
+        let (before, after) = match context_range(
+            &lines,
+            a,
+            ContextOptions {
+                lines: 0,
+                expand_block: false,
+            },
+        ) {
+            Some((s, e)) if full => (
+                lines[s.saturating_sub(n + 1)..s - 1].join("\n"),
+                lines[e..(e + n).min(lines.len())].join("\n"),
+            ),
+            _ => (String::new(), String::new()),
+        };
+        let code = (full && opts.context.is_enabled())
+            .then(|| context_range(&lines, a, opts.context))
+            .flatten()
+            .map(|(s, e)| {
+                let c = render_snippet(&lines, s, e, &[a]);
+                CodeCtx {
+                    start_line: c.start,
+                    end_line: c.end,
+                    text: c.text,
+                }
+            });
+        let ctx = ItemCtx {
+            annotation: a,
+            index: i + 1,
+            level: level.as_str(),
+            language: guess_language_by_ext(&a.file_path),
+            fence: fence_for(&snippet),
+            snippet,
+            diff_note: diff_note(a),
+            resolution: resolution(a),
+            thread: opts
+                .threads
+                .get(&a.id)
+                .filter(|_| full)
+                .map(Vec::as_slice)
+                .unwrap_or(&[]),
+            before,
+            after,
+            code,
+        };
+        call("item", Value::from_serialize(&ctx))
+    };
+    loop {
+        let mut packed = pack(
+            header.clone(),
+            &reserve,
+            &anns,
+            ids,
+            max_chars,
+            opts,
+            &mut render,
+        )?;
+        let actual = footer(packed.used)?;
+        // 中间条数下的 footer 仍可能更长：按实际长度预留后重新装箱
+        if actual.chars().count() > reserve.chars().count() {
+            reserve = actual;
+            continue;
+        }
+        packed.out.push_str(&actual);
+        return Ok(packed.finish(anns.len(), opts));
+    }
+}
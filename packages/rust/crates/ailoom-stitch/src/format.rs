@@ -0,0 +1,299 @@
+use crate::context::Group;
+use crate::{
+    builtin_snippet, diff_note, group_code, omission_note, resolution, InclusionLevel,
+    StitchOptions, TemplateId,
+};
+use ailoom_core::{guess_language_by_ext, Annotation};
+use serde::Serialize;
+
+/// 内置模板的输出格式：markdown（默认）、JSON、XML 标签；三者共用排序与预算装箱。
+/// 请求模型以 unified diff 作答的格式不在此列（暂不提供）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Markdown,
+    /// 一个 JSON 对象，`items` 中每个输出单元占一行
+    Json,
+    /// `<annotation file= lines=>` 标签，包在 `<annotations>` 中
+    Xml,
+}
+
+impl OutputFormat {
+    /// 格式名（大小写不敏感）：markdown / md / json / xml；未知名称返回 `None`
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "markdown" | "md" => Some(Self::Markdown),
+            "json" => Some(Self::Json),
+            "xml" => Some(Self::Xml),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Markdown => "markdown",
+            Self::Json => "json",
+            Self::Xml => "xml",
+        }
+    }
+}
+
+/// JSON 输出的一个单元：同一文件的一条批注，或取上下文时合并的一组批注与共用片段
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonItem<'a> {
+    file: &'a str,
+    language: String,
+    annotations: Vec<JsonAnnotation<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<JsonCode>,
+}
+
+/// 单条批注；columns、priority、tags、resolution 仅 detailed 输出，thread 仅在完整纳入时输出
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonAnnotation<'a> {
+    id: &'a str,
+    start_line: i64,
+    end_line: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_column: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_column: Option<i64>,
+    /// 组内编号，对应 `code.text` 中的 `>N` 标记
+    #[serde(skip_serializing_if = "Option::is_none")]
+    marker: Option<usize>,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    priority: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<&'a [String]>,
+    comment: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolution: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    selected: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    thread: Vec<JsonReply<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonReply<'a> {
+    author: &'a str,
+    created_at: &'a str,
+    body: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonCode {
+    start_line: usize,
+    end_line: usize,
+    text: String,
+}
+
+/// JSON 输出的首尾：`{"template", "note", "items": [...]}`
+pub(crate) fn json_frame(template: TemplateId) -> (String, String) {
+    let head = format!(
+        "{{\"template\":{},\"note\":{},\"items\":[\n",
+        serde_json::Value::from(template.name()),
+        serde_json::Value::from(omission_note(template)),
+    );
+    (head, "\n]}\n".to_string())
+}
+
+/// 一个单元的 JSON（单行）；除首个单元外以 `,` 开头。装箱总是纳入前缀，因此逗号与数组保持合法；
+/// 序列化失败时返回错误，而不是写入空单元破坏数组
+pub(crate) fn render_json(
+    template: TemplateId,
+    index: usize,
+    g: &Group,
+    opts: &StitchOptions,
+    level: InclusionLevel,
+) -> Result<String, serde_json::Error> {
+    let detailed = template == TemplateId::Detailed;
+    let code = g.code.as_ref().and_then(|c| group_code(g, c, level));
+    let annotations = g
+        .members
+        .iter()
+        .enumerate()
+        .map(|(i, a)| JsonAnnotation {
+            id: &a.id,
+            start_line: a.start_line,
+            end_line: a.end_line,
+            start_column: a.start_column.filter(|_| detailed),
+            end_column: a.end_column.filter(|_| detailed),
+            marker: code.as_ref().map(|_| i + 1),
+            status: a.status.as_str(),
+            priority: a.priority.as_deref().filter(|_| detailed),
+            tags: a.tags.as_deref().filter(|_| detailed),
+            comment: a.comment.trim(),
+            diff: diff_note(a),
+            resolution: resolution(a).filter(|_| detailed),
+            selected: match g.code {
+                Some(_) => None,
+                None => builtin_snippet(template, a, level),
+            },
+            thread: thread(opts, a, level == InclusionLevel::Full)
+                .map(|c| JsonReply {
+                    author: &c.author,
+                    created_at: &c.created_at,
+                    body: c.body.trim(),
+                })
+                .collect(),
+        })
+        .collect();
+    let item = JsonItem {
+        file: &g.members[0].file_path,
+        language: match &g.code {
+            Some(c) => c.language.clone(),
+            None => guess_language_by_ext(&g.members[0].file_path),
+        },
+        annotations,
+        code: code.map(|(start_line, end_line, text)| JsonCode {
+            start_line,
+            end_line,
+            text,
+        }),
+    };
+    let json = serde_json::to_string(&item)?;
+    Ok(if index == 0 {
+        json
+    } else {
+        format!(",\n{json}")
+    })
+}
+
+/// XML 输出的首尾
+pub(crate) fn xml_frame(template: TemplateId) -> (String, String) {
+    (
+        format!(
+            "<annotations template=\"{}\">\n<note>{}</note>\n\n",
+            template.name(),
+            xml_escape(omission_note(template))
+        ),
+        "</annotations>\n".to_string(),
+    )
+}
+
+/// 一个单元的 XML：每条批注一个 `<annotation>`；合并组的共用片段为其后的 `<code>`，以 `marker` 对应
+pub(crate) fn render_xml(
+    template: TemplateId,
+    g: &Group,
+    opts: &StitchOptions,
+    level: InclusionLevel,
+) -> String {
+    let detailed = template == TemplateId::Detailed;
+    let code = g.code.as_ref().and_then(|c| group_code(g, c, level));
+    let mut out = String::new();
+    for (i, a) in g.members.iter().enumerate() {
+        let mut attrs = vec![
+            ("id", a.id.clone()),
+            ("file", a.file_path.clone()),
+            ("lines", format!("{}-{}", a.start_line, a.end_line)),
+        ];
+        if detailed {
+            if let (Some(s), Some(e)) = (a.start_column, a.end_column) {
+                attrs.push(("columns", format!("{s}-{e}")));
+            }
+        }
+        if code.is_some() {
+            attrs.push(("marker", (i + 1).to_string()));
+        }
+        attrs.push(("status", a.status.as_str().to_string()));
+        if detailed {
+            if let Some(p) = &a.priority {
+                attrs.push(("priority", p.clone()));
+            }
+            if let Some(t) = a.tags.as_ref().filter(|t| !t.is_empty()) {
+                attrs.push(("tags", t.join(",")));
+            }
+        }
+        out.push_str(&open_tag("annotation", &attrs));
+        out.push_str(&format!(
+            "\n<comment>{}</comment>\n",
+            xml_escape(a.comment.trim())
+        ));
+        if let Some(d) = diff_note(a) {
+            out.push_str(&format!("<diff>{}</diff>\n", xml_escape(&d)));
+        }
+        if let Some(r) = resolution(a).filter(|_| detailed) {
+            out.push_str(&format!("<resolution>{}</resolution>\n", xml_escape(&r)));
+        }
+        if g.code.is_none() {
+            if let Some(snippet) = builtin_snippet(template, a, level) {
+                out.push_str(&format!(
+                    "<selected>\n{}\n</selected>\n",
+                    xml_escape(&snippet)
+                ));
+            }
+        }
+        let mut replies = thread(opts, a, level == InclusionLevel::Full).peekable();
+        if replies.peek().is_some() {
+            out.push_str("<thread>\n");
+            for c in replies {
+                out.push_str(&open_tag(
+                    "reply",
+                    &[("author", c.author.clone()), ("at", c.created_at.clone())],
+                ));
+                out.push_str(&format!("{}</reply>\n", xml_escape(c.body.trim())));
+            }
+            out.push_str("</thread>\n");
+        }
+        out.push_str("</annotation>\n");
+    }
+    if let (Some((start, end, text)), Some(c)) = (code, &g.code) {
+        let attrs = [
+            ("file", g.members[0].file_path.clone()),
+            ("lines", format!("{start}-{end}")),
+            ("language", c.language.clone()),
+        ];
+        out.push_str(&open_tag("code", &attrs));
+        out.push_str(&format!("\n{}\n</code>\n", xml_escape(&text)));
+    }
+    out.push('\n');
+    out
+}
+
+/// `includeThreads` 时批注的回复；`enabled` 为假时为空
+fn thread<'a>(
+    opts: &'a StitchOptions,
+    a: &Annotation,
+    enabled: bool,
+) -> impl Iterator<Item = &'a ailoom_core::AnnotationComment> {
+    opts.threads
+        .get(&a.id)
+        .filter(|_| enabled)
+        .into_iter()
+        .flatten()
+}
+
+fn open_tag(name: &str, attrs: &[(&str, String)]) -> String {
+    let mut out = format!("<{name}");
+    for (k, v) in attrs {
+        out.push_str(&format!(" {k}=\"{}\"", xml_escape(v)));
+    }
+    out.push('>');
+    out
+}
+
+/// 转义文本与属性值中的 XML 特殊字符；XML 1.0 不允许的字符（除制表、换行、回车外的控制字符，
+/// U+FFFE/U+FFFF）无法以任何形式出现，替换为 U+FFFD
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => out.push('\u{fffd}'),
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -1,4 +1,17 @@
-use ailoom_core::Annotation;
+use ailoom_core::{Annotation, AnnotationComment, AnnotationStatus};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use context::{CodeSnippet, Group};
+
+mod context;
+mod custom;
+mod format;
+mod tokenizer;
+pub use context::{enclosing_block, ContextOptions, MAX_BLOCK_LINES};
+pub use custom::{generate_custom, CustomTemplate, TemplateError};
+pub use format::OutputFormat;
+pub use tokenizer::{tokenizer_by_name, BpeTokenizer, HeuristicTokenizer, Tokenizer};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TemplateId {
@@ -8,9 +21,22 @@ pub enum TemplateId {
 
 impl TemplateId {
     pub fn parse(s: &str) -> Self {
+        Self::builtin(s).unwrap_or(Self::Concise)
+    }
+
+    /// 内置模板名（大小写不敏感）；非内置名称返回 `None`
+    pub fn builtin(s: &str) -> Option<Self> {
         match s.to_ascii_lowercase().as_str() {
-            "detailed" => Self::Detailed,
-            _ => Self::Concise,
+            "concise" => Some(Self::Concise),
+            "detailed" => Some(Self::Detailed),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Concise => "concise",
+            Self::Detailed => "detailed",
         }
     }
 }
@@ -20,7 +46,50 @@ pub struct StitchStats {
     pub total: usize,
     pub used: usize,
     pub truncated: bool,
+    /// 输出的字符数（Unicode 标量，非字节）
     pub chars: usize,
+    /// 整个输出的估算 token 数
+    pub tokens: usize,
+    /// 估算所用分词器名称
+    pub tokenizer: String,
+    /// 各输出单元按排序顺序的纳入情况（含未纳入的单元）
+    pub items: Vec<ItemStats>,
+}
+
+/// 单个输出单元的占用；取上下文时合并为一段片段的其余批注列于 `merged_ids`
+#[derive(Debug, Clone)]
+pub struct ItemStats {
+    pub id: String,
+    pub tokens: usize,
+    pub merged_ids: Vec<String>,
+    pub level: InclusionLevel,
+}
+
+/// 输出单元的纳入程度：预算不足时逐级降级为折叠片段、仅评论
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InclusionLevel {
+    /// 完整片段（模板默认的省略规则）
+    Full,
+    /// 片段进一步折叠；上下文片段只保留选区行，不带讨论线程
+    Collapsed,
+    /// 仅位置与评论，不带片段
+    CommentOnly,
+    /// 仅评论形式仍超出预算的首个单元，被截断（围栏保持配对）
+    Truncated,
+    /// 未纳入
+    Omitted,
+}
+
+impl InclusionLevel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Collapsed => "collapsed",
+            Self::CommentOnly => "comment",
+            Self::Truncated => "truncated",
+            Self::Omitted => "omitted",
+        }
+    }
 }
 
 pub struct StitchResult {
@@ -28,12 +97,96 @@ pub struct StitchResult {
     pub stats: StitchStats,
 }
 
+/// 拼接选项（模板与字符预算之外的附加内容）
+#[derive(Debug, Clone)]
+pub struct StitchOptions {
+    /// 按批注 id 附带的讨论线程；所有模板在批注完整纳入时输出（降级后不带）
+    pub threads: HashMap<String, Vec<AnnotationComment>>,
+    /// token 预算；与 `max_chars` 同时生效，先触及者为准
+    pub max_tokens: Option<usize>,
+    /// 估算 token 的分词器，默认启发式
+    pub tokenizer: Arc<dyn Tokenizer>,
+    /// 选区周围的代码上下文；默认不取
+    pub context: ContextOptions,
+    /// 按 `file_path`（与批注一致）提供的当前文件内容；上下文片段与自定义模板的 `before`/`after` 由此截取
+    pub files: HashMap<String, String>,
+    /// 内置模板的输出格式，默认 markdown；自定义模板不受影响
+    pub format: OutputFormat,
+}
+
+impl Default for StitchOptions {
+    fn default() -> Self {
+        Self {
+            threads: HashMap::new(),
+            max_tokens: None,
+            tokenizer: Arc::new(HeuristicTokenizer),
+            context: ContextOptions::default(),
+            files: HashMap::new(),
+            format: OutputFormat::default(),
+        }
+    }
+}
+
 pub fn generate_prompt(
     template: TemplateId,
     max_chars: usize,
     mut anns: Vec<Annotation>,
-) -> StitchResult {
-    // sort: priority -> file_path -> start_line
+    opts: &StitchOptions,
+) -> Result<StitchResult, serde_json::Error> {
+    sort_annotations(&mut anns);
+
+    let (header, footer) = match opts.format {
+        OutputFormat::Markdown => {
+            let title = match template {
+                TemplateId::Concise => "Concise",
+                TemplateId::Detailed => "Detailed",
+            };
+            (
+                format!(
+                    "# Annotations ({title})\n\n> 说明: {}\n\n",
+                    omission_note(template)
+                ),
+                String::new(),
+            )
+        }
+        OutputFormat::Json => format::json_frame(template),
+        OutputFormat::Xml => format::xml_frame(template),
+    };
+    let groups = context::group_annotations(&anns, opts);
+    let mut packed = pack(
+        header,
+        &footer,
+        &groups,
+        Group::ids,
+        max_chars,
+        opts,
+        |i, g, level| {
+            Ok(match (opts.format, &g.code) {
+                (OutputFormat::Json, _) => format::render_json(template, i, g, opts, level)?,
+                (OutputFormat::Xml, _) => format::render_xml(template, g, opts, level),
+                (OutputFormat::Markdown, Some(code)) => {
+                    render_with_code(template, g, code, opts, level)
+                }
+                (OutputFormat::Markdown, None) => {
+                    render_builtin(template, g.members[0], opts, level)
+                }
+            })
+        },
+    )?;
+    packed.out.push_str(&footer);
+    Ok(packed.finish(anns.len(), opts))
+}
+
+/// 内置模板对片段省略标记的说明（各输出格式共用）
+pub(crate) fn omission_note(template: TemplateId) -> &'static str {
+    match template {
+        TemplateId::Concise => "片段中若发生省略，将使用 <<<OMITTED ~N CHARS>>> 进行标记；请勿臆测缺失内容，定位以文件路径与行号为准。",
+        TemplateId::Detailed => "片段中若发生省略，将使用 <<<OMITTED ~N LINES>>> 进行标记；请勿臆测缺失内容，定位以文件路径与行号为准。",
+    }
+}
+
+/// 排序：priority P0→P1→P2（缺省排最后），其次 file_path，文件内 start_line
+pub(crate) fn sort_annotations(anns: &mut [Annotation]) {
     fn prio_rank(p: &Option<String>) -> i32 {
         match p.as_deref() {
             Some("P0") => 0,
@@ -48,88 +201,433 @@ pub fn generate_prompt(
             .then(a.file_path.cmp(&b.file_path))
             .then(a.start_line.cmp(&b.start_line))
     });
+}
 
-    let mut out = String::new();
+/// 内置模板的单条输出
+fn render_builtin(
+    template: TemplateId,
+    a: &Annotation,
+    opts: &StitchOptions,
+    level: InclusionLevel,
+) -> String {
+    let snippet = builtin_snippet(template, a, level);
+
+    let mut item = match template {
+        TemplateId::Concise => format!(
+            "- [{}:L{}-L{}]{}{} {}\n",
+            a.file_path,
+            a.start_line,
+            a.end_line,
+            diff_note(a)
+                .map(|d| format!(" ({})", d))
+                .unwrap_or_default(),
+            if a.status == AnnotationStatus::Resolved {
+                " [resolved]"
+            } else {
+                ""
+            },
+            a.comment.trim(),
+        ),
+        TemplateId::Detailed => format!(
+            concat!(
+                "- file: {}\n  span: L{}-L{}{}{}\n{}",
+                "  tags: {}  priority: {}\n  comment: {}\n{}",
+            ),
+            a.file_path,
+            a.start_line,
+            a.end_line,
+            a.start_column
+                .map(|c| format!(":{}", c))
+                .unwrap_or_default(),
+            a.end_column.map(|c| format!("-{}", c)).unwrap_or_default(),
+            diff_note(a)
+                .map(|d| format!("  diff: {}\n", d))
+                .unwrap_or_default(),
+            a.tags.as_ref().map(|v| v.join(",")).unwrap_or_default(),
+            a.priority.clone().unwrap_or_else(|| "P1".into()),
+            a.comment.trim(),
+            resolution(a)
+                .map(|r| format!("  resolved: {}\n", r))
+                .unwrap_or_default(),
+        ),
+    };
+    if let Some(snippet) = snippet {
+        let fence = fence_for(&snippet);
+        if template == TemplateId::Detailed {
+            item.push_str("  selected:\n");
+        }
+        item.push_str(&format!("  {fence}\n{}\n{fence}\n", snippet));
+    }
+    if level == InclusionLevel::Full {
+        push_thread(&mut item, template, opts, &a.id);
+    }
+    item.push('\n');
+    item
+}
+
+/// 内置模板的选中文本：按模板做“中间省略”裁剪（concise 基于字符，detailed 基于行），折叠时更紧；仅评论时无片段
+pub(crate) fn builtin_snippet(
+    template: TemplateId,
+    a: &Annotation,
+    level: InclusionLevel,
+) -> Option<String> {
+    let raw = a.selected_text.trim();
+    match (template, level) {
+        (_, InclusionLevel::CommentOnly) => None,
+        (TemplateId::Concise, InclusionLevel::Full) => {
+            Some(collapse_middle_chars(raw, 60, 60, 120))
+        }
+        (TemplateId::Concise, _) => Some(collapse_middle_chars(raw, 20, 20, 40)),
+        (TemplateId::Detailed, InclusionLevel::Full) => {
+            Some(collapse_middle_lines(raw, 20, 20, 40))
+        }
+        (TemplateId::Detailed, _) => Some(collapse_middle_lines(raw, 3, 3, 6)),
+    }
+}
+
+/// 讨论线程：detailed 为带时间的 `thread:` 列表，concise 为紧凑的 `> 作者: 内容` 行
+fn push_thread(item: &mut String, template: TemplateId, opts: &StitchOptions, id: &str) {
+    let Some(thread) = opts.threads.get(id).filter(|t| !t.is_empty()) else {
+        return;
+    };
     match template {
         TemplateId::Concise => {
-            out.push_str("# Annotations (Concise)\n\n");
-            out.push_str(
-                "> 说明: 片段中若发生省略，将使用 <<<OMITTED ~N CHARS>>> 进行标记；请勿臆测缺失内容，定位以文件路径与行号为准。\n\n",
-            );
+            for c in thread {
+                let body = c.body.trim().replace('\n', "\n    ");
+                item.push_str(&format!("  > {}: {}\n", c.author, body));
+            }
         }
         TemplateId::Detailed => {
-            out.push_str("# Annotations (Detailed)\n\n");
-            out.push_str(
-                "> 说明: 片段中若发生省略，将使用 <<<OMITTED ~N LINES>>> 进行标记；请勿臆测缺失内容，定位以文件路径与行号为准。\n\n",
-            );
+            item.push_str("  thread:\n");
+            for c in thread {
+                let body = c.body.trim().replace('\n', "\n      ");
+                item.push_str(&format!(
+                    "    - {} ({}): {}\n",
+                    c.author, c.created_at, body
+                ));
+            }
         }
     }
-    let mut used = 0usize;
-    for a in anns.iter() {
-        // 选中文本：按模板做“中间省略”裁剪（concise 基于字符，detailed 基于行）
-        let raw = a.selected_text.trim();
-        let snippet = match template {
-            TemplateId::Concise => collapse_middle_chars(raw, 60, 60, 120),
-            TemplateId::Detailed => collapse_middle_lines(raw, 20, 20, 40),
-        };
+}
 
-        // 若片段内含有三反引号，则用四反引号包裹，避免围栏冲突
-        let fence = if snippet.contains("```") {
-            "````"
+/// 带上下文片段的一组批注：逐条列出（标注组内编号 `>N`），其后为共用的代码片段；
+/// 折叠时片段只保留各选区覆盖的行，仅评论时不带片段与编号
+fn render_with_code(
+    template: TemplateId,
+    g: &Group,
+    code: &CodeSnippet,
+    opts: &StitchOptions,
+    level: InclusionLevel,
+) -> String {
+    let with_code = level != InclusionLevel::CommentOnly;
+    let mut item = String::new();
+    for (i, a) in g.members.iter().enumerate() {
+        let marker = if with_code {
+            format!(" >{}", i + 1)
         } else {
-            "```"
+            String::new()
         };
-
-        let item = match template {
-            TemplateId::Concise => format!(
-                "- [{}:L{}-L{}] {}\n  {fence}\n{}\n{fence}\n\n",
-                a.file_path, a.start_line, a.end_line, a.comment.trim(), snippet
-            ),
-            TemplateId::Detailed => format!(
-                "- file: {}\n  span: L{}-L{}{}{}\n  tags: {}  priority: {}\n  comment: {}\n  selected:\n  {fence}\n{}\n{fence}\n\n",
+        match template {
+            TemplateId::Concise => item.push_str(&format!(
+                "- [{}:L{}-L{}]{}{}{} {}\n",
                 a.file_path,
-                a.start_line, a.end_line,
-                a.start_column.map(|c| format!(":{}", c)).unwrap_or_default(),
-                a.end_column.map(|c| format!("-{}", c)).unwrap_or_default(),
-                a.tags.as_ref().map(|v| v.join(",")).unwrap_or_default(),
-                a.priority.clone().unwrap_or_else(|| "P1".into()),
+                a.start_line,
+                a.end_line,
+                marker,
+                diff_note(a)
+                    .map(|d| format!(" ({})", d))
+                    .unwrap_or_default(),
+                if a.status == AnnotationStatus::Resolved {
+                    " [resolved]"
+                } else {
+                    ""
+                },
                 a.comment.trim(),
-                snippet
-            ),
-        };
-        // budget check
-        if out.len() + item.len() > max_chars && used > 0 {
-            break;
+            )),
+            TemplateId::Detailed => {
+                item.push_str(&format!(
+                    "- file: {}\n  span: L{}-L{}{}{}{}\n{}  tags: {}  priority: {}\n  comment: {}\n{}",
+                    a.file_path,
+                    a.start_line, a.end_line,
+                    a.start_column.map(|c| format!(":{}", c)).unwrap_or_default(),
+                    a.end_column.map(|c| format!("-{}", c)).unwrap_or_default(),
+                    if with_code { format!("  marker:{}", marker) } else { String::new() },
+                    diff_note(a).map(|d| format!("  diff: {}\n", d)).unwrap_or_default(),
+                    a.tags.as_ref().map(|v| v.join(",")).unwrap_or_default(),
+                    a.priority.clone().unwrap_or_else(|| "P1".into()),
+                    a.comment.trim(),
+                    resolution(a).map(|r| format!("  resolved: {}\n", r)).unwrap_or_default(),
+                ));
+            }
+        }
+        if level == InclusionLevel::Full {
+            push_thread(&mut item, template, opts, &a.id);
+        }
+    }
+    let Some((start, end, text)) = group_code(g, code, level) else {
+        item.push('\n');
+        return item;
+    };
+    let fence = fence_for(&text);
+    let lang = if code.language == "plaintext" {
+        ""
+    } else {
+        code.language.as_str()
+    };
+    if template == TemplateId::Detailed {
+        item.push_str(&format!("  code: L{}-L{}\n", start, end));
+    }
+    item.push_str(&format!("  {fence}{lang}\n{}\n{fence}\n\n", text));
+    item
+}
+
+/// 组内共用片段按纳入程度的取舍：完整、只保留选区覆盖的行（折叠），或不带片段
+pub(crate) fn group_code(
+    g: &Group,
+    code: &CodeSnippet,
+    level: InclusionLevel,
+) -> Option<(usize, usize, String)> {
+    match level {
+        InclusionLevel::Full => Some((code.start, code.end, code.text.clone())),
+        InclusionLevel::Collapsed => {
+            let lo = g
+                .members
+                .iter()
+                .map(|a| a.start_line.max(1) as usize)
+                .min()
+                .unwrap_or(code.start)
+                .max(code.start);
+            let hi = g
+                .members
+                .iter()
+                .map(|a| a.end_line.max(a.start_line) as usize)
+                .max()
+                .unwrap_or(code.end)
+                .min(code.end);
+            let lines: Vec<&str> = code
+                .text
+                .lines()
+                .skip(lo - code.start)
+                .take(hi + 1 - lo)
+                .collect();
+            Some((lo, hi, collapse_middle_lines(&lines.join("\n"), 5, 5, 10)))
+        }
+        _ => None,
+    }
+}
+
+/// 若片段内含有三反引号，则用四反引号包裹，避免围栏冲突
+pub(crate) fn fence_for(snippet: &str) -> &'static str {
+    if snippet.contains("```") {
+        "````"
+    } else {
+        "```"
+    }
+}
+
+/// 按预算装箱后的中间结果
+pub(crate) struct Packed {
+    pub out: String,
+    pub items: Vec<ItemStats>,
+    pub used: usize,
+}
+
+impl Packed {
+    pub fn finish(self, total: usize, opts: &StitchOptions) -> StitchResult {
+        let tok = opts.tokenizer.as_ref();
+        let chars = self.out.chars().count();
+        let tokens = tok.count(&self.out);
+        let cut = self
+            .items
+            .iter()
+            .any(|i| i.level == InclusionLevel::Truncated);
+        StitchResult {
+            prompt: self.out,
+            stats: StitchStats {
+                total,
+                used: self.used,
+                truncated: self.used < total || cut,
+                chars,
+                tokens,
+                tokenizer: tok.name().to_string(),
+                items: self.items,
+            },
         }
-        if out.len() + item.len() > max_chars {
-            // single item larger than budget: hard cut
-            let remain = max_chars.saturating_sub(out.len());
-            out.push_str(
-                &item[..item
-                    .char_indices()
-                    .take_while(|(i, _)| *i < remain)
-                    .map(|(_, c)| c)
-                    .collect::<String>()
-                    .len()
-                    .min(item.len())],
-            );
-            used += 1;
+    }
+}
+
+/// 预算装箱（内置与自定义模板共用）：`header` 之后按顺序输出各单元，字符与 token 预算同时生效。
+///
+/// 先按顺序以仅评论形式纳入尽可能多的单元（高优先级单元不会因低优先级的长片段被挤掉），
+/// 再按同样顺序把每个单元升级为完整或折叠形式，升级只使用其余单元占用之外的预算。
+/// `reserve` 为尾部预留的内容，只计入预算、不写入输出；`ids` 给出单元包含的批注（首个记为条目 id）
+pub(crate) fn pack<U, E>(
+    header: String,
+    reserve: &str,
+    units: &[U],
+    ids: impl Fn(&U) -> Vec<String>,
+    max_chars: usize,
+    opts: &StitchOptions,
+    mut render: impl FnMut(usize, &U, InclusionLevel) -> Result<String, E>,
+) -> Result<Packed, E> {
+    let tok = opts.tokenizer.as_ref();
+    let cost = |s: &str| (s.chars().count(), tok.count(s));
+    let (header_chars, header_tokens) = cost(&header);
+    let (reserve_chars, reserve_tokens) = cost(reserve);
+    // 剩余预算 (chars, tokens)
+    let mut left = (
+        max_chars.saturating_sub(header_chars + reserve_chars),
+        opts.max_tokens
+            .unwrap_or(usize::MAX)
+            .saturating_sub(header_tokens + reserve_tokens),
+    );
+
+    let mut chosen: Vec<(InclusionLevel, String, (usize, usize))> = Vec::new();
+    for (i, u) in units.iter().enumerate() {
+        let item = render(i, u, InclusionLevel::CommentOnly)?;
+        let c = cost(&item);
+        if c.0 > left.0 || c.1 > left.1 {
+            if chosen.is_empty() && opts.format == OutputFormat::Markdown {
+                // 首个单元仅评论仍超出预算：截断到剩余预算内（结构化格式截断后不再合法，直接不纳入）
+                let cut = cut_balanced(&item, left.0, left.1, tok);
+                let c = cost(&cut);
+                chosen.push((InclusionLevel::Truncated, cut, c));
+            }
             break;
         }
-        out.push_str(&item);
-        used += 1;
-    }
-    let truncated = used < anns.len();
-    let chars = out.len();
-    StitchResult {
-        prompt: out,
-        stats: StitchStats {
-            total: anns.len(),
-            used,
-            truncated,
-            chars,
-        },
+        left = (left.0 - c.0, left.1 - c.1);
+        chosen.push((InclusionLevel::CommentOnly, item, c));
     }
+    for (i, slot) in chosen.iter_mut().enumerate() {
+        if slot.0 != InclusionLevel::CommentOnly {
+            continue;
+        }
+        // 当前单元可用的预算：剩余预算加上它已占用的部分
+        let avail = (left.0 + slot.2 .0, left.1.saturating_add(slot.2 .1));
+        for level in [InclusionLevel::Full, InclusionLevel::Collapsed] {
+            let item = render(i, &units[i], level)?;
+            let c = cost(&item);
+            if c.0 <= avail.0 && c.1 <= avail.1 {
+                left = (avail.0 - c.0, avail.1 - c.1);
+                *slot = (level, item, c);
+                break;
+            }
+        }
+    }
+
+    let mut out = header;
+    let mut items = Vec::with_capacity(units.len());
+    let mut used = 0usize;
+    for (i, u) in units.iter().enumerate() {
+        let mut merged_ids = ids(u);
+        let id = merged_ids.remove(0);
+        let (level, tokens) = match chosen.get(i) {
+            Some((level, item, (_, tokens))) => {
+                out.push_str(item);
+                used += merged_ids.len() + 1;
+                (*level, *tokens)
+            }
+            None => (InclusionLevel::Omitted, 0),
+        };
+        items.push(ItemStats {
+            id,
+            tokens,
+            merged_ids,
+            level,
+        });
+    }
+    Ok(Packed { out, items, used })
+}
+
+/// 截断到预算内并保持代码围栏配对：截断点落在围栏内时补上闭合围栏（计入预算）
+pub(crate) fn cut_balanced(s: &str, chars: usize, tokens: usize, tok: &dyn Tokenizer) -> String {
+    let cut = cut_to_budget(s, chars, tokens, tok);
+    if open_fence(cut).is_none() {
+        return cut.to_string();
+    }
+    let close = "\n````\n";
+    let cut = cut_to_budget(
+        s,
+        chars.saturating_sub(close.chars().count()),
+        tokens.saturating_sub(tok.count(close)),
+        tok,
+    );
+    match open_fence(cut) {
+        Some(fence) if cut.ends_with('\n') => format!("{cut}{fence}\n"),
+        Some(fence) => format!("{cut}\n{fence}\n"),
+        None => cut.to_string(),
+    }
+}
+
+/// 文本末尾仍未闭合的代码围栏（反引号串）
+fn open_fence(s: &str) -> Option<&str> {
+    let mut open: Option<&str> = None;
+    for line in s.lines() {
+        let t = line.trim_start();
+        let ticks = &t[..t.len() - t.trim_start_matches('`').len()];
+        if ticks.len() < 3 {
+            continue;
+        }
+        match open {
+            None => open = Some(ticks),
+            Some(f) if ticks.len() >= f.len() && t.trim_end() == ticks => open = None,
+            _ => {}
+        }
+    }
+    open
+}
+
+/// 取 `s` 的最长前缀，使其字符数与 token 数都不超过剩余预算（按字符二分）
+pub(crate) fn cut_to_budget<'a>(
+    s: &'a str,
+    chars: usize,
+    tokens: usize,
+    tok: &dyn Tokenizer,
+) -> &'a str {
+    let bounds: Vec<usize> = s
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(s.len()))
+        .collect();
+    let (mut lo, mut hi) = (0usize, chars.min(bounds.len() - 1));
+    while lo < hi {
+        let mid = (lo + hi).div_ceil(2);
+        if tok.count(&s[..bounds[mid]]) <= tokens {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    &s[..bounds[lo]]
+}
+
+/// diff 批注的上下文说明，如 "in the change from HEAD to working tree, old side"
+pub(crate) fn diff_note(a: &Annotation) -> Option<String> {
+    let base = a.base_ref.as_deref()?;
+    let side = match a.diff_side.as_deref() {
+        Some("old") => ", old side",
+        Some("new") => ", new side",
+        _ => "",
+    };
+    Some(format!(
+        "in the change from {} to working tree{}",
+        base, side
+    ))
+}
+
+/// 已解决批注的说明，如 "by alice at 2024-01-01T00:00:00Z — fixed in #12"
+pub(crate) fn resolution(a: &Annotation) -> Option<String> {
+    if a.status != AnnotationStatus::Resolved {
+        return None;
+    }
+    let mut out = format!("by {}", a.resolved_by.as_deref().unwrap_or("user"));
+    if let Some(at) = a.resolved_at.as_deref() {
+        out.push_str(&format!(" at {}", at));
+    }
+    if let Some(note) = a.resolution_note.as_deref() {
+        out.push_str(&format!(" — {}", note.trim()));
+    }
+    Some(out)
 }
 
 pub fn version() -> &'static str {
@@ -163,7 +661,7 @@ fn collapse_middle_chars(s: &str, head: usize, tail: usize, max_total: usize) ->
 }
 
 // detailed：按行数预算做中间省略，保留前 head 与后 tail 行；最大不超过 max_lines 行
-fn collapse_middle_lines(s: &str, head: usize, tail: usize, max_lines: usize) -> String {
+pub(crate) fn collapse_middle_lines(s: &str, head: usize, tail: usize, max_lines: usize) -> String {
     let lines: Vec<&str> = s.lines().collect();
     let n = lines.len();
     if n <= max_lines || n <= head + tail {
@@ -188,3 +686,298 @@ fn collapse_middle_lines(s: &str, head: usize, tail: usize, max_lines: usize) ->
     }
     out
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ann(id: &str, comment: &str) -> Annotation {
+        Annotation {
+            id: id.into(),
+            file_path: "src/lib.rs".into(),
+            start_line: 1,
+            end_line: 1,
+            selected_text: "fn main() {}".into(),
+            comment: comment.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn budgets_count_chars_and_tokens() {
+        // 中文注释按字符而非字节计入 max_chars
+        let anns = vec![ann("a", &"注释".repeat(100)), ann("b", "short")];
+        let r = generate_prompt(
+            TemplateId::Concise,
+            600,
+            anns.clone(),
+            &StitchOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(r.stats.used, 2);
+        assert_eq!(r.stats.chars, r.prompt.chars().count());
+        assert_eq!(r.stats.tokenizer, "heuristic");
+        assert_eq!(r.stats.items.len(), 2);
+
+        // token 预算更紧时以 token 为准，超出的首条被截断
+        let opts = StitchOptions {
+            max_tokens: Some(150),
+            ..Default::default()
+        };
+        let r = generate_prompt(TemplateId::Concise, 600, anns, &opts).unwrap();
+        assert_eq!((r.stats.used, r.stats.truncated), (1, true));
+        assert!(r.stats.tokens <= 150);
+    }
+
+    #[test]
+    fn budget_degrades_items_by_priority() {
+        let code: String = (1..=100).map(|n| format!("let x{n} = {n};\n")).collect();
+        let mut p0 = ann("p0", "urgent");
+        p0.priority = Some("P0".into());
+        p0.selected_text = code.clone();
+        let mut p1 = ann("p1", "later");
+        p1.selected_text = code;
+        let fences_balanced = |s: &str| {
+            s.lines()
+                .filter(|l| l.trim_start().starts_with("```"))
+                .count()
+                % 2
+                == 0
+        };
+        let levels = |r: &StitchResult| -> Vec<InclusionLevel> {
+            r.stats.items.iter().map(|i| i.level).collect()
+        };
+
+        // 放不下两段完整片段：P0 完整，P1 降级而不是被丢弃
+        let opts = StitchOptions::default();
+        let r = generate_prompt(
+            TemplateId::Detailed,
+            1000,
+            vec![p1.clone(), p0.clone()],
+            &opts,
+        )
+        .unwrap();
+        assert_eq!((r.stats.used, r.stats.truncated), (2, false));
+        assert_eq!(r.stats.items[0].id, "p0");
+        assert_eq!(
+            levels(&r),
+            vec![InclusionLevel::Full, InclusionLevel::Collapsed]
+        );
+        assert!(r.stats.chars <= 1000);
+        assert!(fences_balanced(&r.prompt));
+
+        // 更紧时两条都只剩评论；再紧则从低优先级起不纳入
+        let r = generate_prompt(
+            TemplateId::Detailed,
+            300,
+            vec![p1.clone(), p0.clone()],
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(
+            levels(&r),
+            vec![InclusionLevel::CommentOnly, InclusionLevel::CommentOnly]
+        );
+        assert!(!r.prompt.contains("```"));
+        let r = generate_prompt(TemplateId::Detailed, 200, vec![p1, p0], &opts).unwrap();
+        assert_eq!(
+            levels(&r),
+            vec![InclusionLevel::CommentOnly, InclusionLevel::Omitted]
+        );
+        assert!(r.stats.truncated);
+
+        // 截断点落在围栏内时补上闭合围栏
+        let fenced = "note\n```rust\nfn a() {}\nfn b() {}\n```\n";
+        let cut = cut_balanced(fenced, 30, usize::MAX, &HeuristicTokenizer);
+        assert!(cut.chars().count() <= 30);
+        assert!(cut.ends_with("```\n") && fences_balanced(&cut));
+    }
+
+    #[test]
+    fn structured_formats_share_sort_and_budget() {
+        let mut p0 = ann("b", "a < b & c");
+        p0.priority = Some("P0".into());
+        let anns = vec![ann("a", "later"), p0];
+        let json = |max_chars: usize| {
+            let opts = StitchOptions {
+                format: OutputFormat::Json,
+                ..Default::default()
+            };
+            let r = generate_prompt(TemplateId::Concise, max_chars, anns.clone(), &opts).unwrap();
+            let v: serde_json::Value = serde_json::from_str(&r.prompt).unwrap();
+            (v, r.stats)
+        };
+        let (v, stats) = json(10_000);
+        assert_eq!(stats.used, 2);
+        assert_eq!(v["template"], "concise");
+        assert_eq!(v["items"][0]["annotations"][0]["id"], "b");
+        assert_eq!(v["items"][1]["annotations"][0]["selected"], "fn main() {}");
+        // 预算不足时仍是合法 JSON：低优先级条目不纳入
+        let (v, stats) = json(300);
+        assert_eq!((stats.used, stats.truncated), (1, true));
+        assert_eq!(v["items"].as_array().unwrap().len(), 1);
+
+        let opts = StitchOptions {
+            format: OutputFormat::Xml,
+            ..Default::default()
+        };
+        let r = generate_prompt(TemplateId::Concise, 10_000, anns, &opts).unwrap();
+        assert!(r.prompt.starts_with("<annotations template=\"concise\">"));
+        assert!(r.prompt.contains(
+            "<annotation id=\"b\" file=\"src/lib.rs\" lines=\"1-1\" status=\"active\">\n<comment>a &lt; b &amp; c</comment>\n<selected>\nfn main() {}\n</selected>\n</annotation>\n"
+        ));
+        assert!(r.prompt.ends_with("</annotations>\n"));
+        assert_eq!(OutputFormat::parse("XML"), Some(OutputFormat::Xml));
+        assert!(OutputFormat::parse("yaml").is_none());
+    }
+
+    #[test]
+    fn xml_output_replaces_characters_illegal_in_xml() {
+        let mut a = ann("a", "bell\u{7} tab\there\u{ffff}");
+        a.selected_text = "esc\u{1b}[0m".into();
+        let opts = StitchOptions {
+            format: OutputFormat::Xml,
+            ..Default::default()
+        };
+        let r = generate_prompt(TemplateId::Concise, 10_000, vec![a], &opts).unwrap();
+        assert!(r
+            .prompt
+            .contains("<comment>bell\u{fffd} tab\there\u{fffd}</comment>"));
+        assert!(r.prompt.contains("esc\u{fffd}[0m"));
+        assert!(!r
+            .prompt
+            .chars()
+            .any(|c| c < ' ' && !matches!(c, '\t' | '\n' | '\r')));
+    }
+
+    #[test]
+    fn threads_render_in_every_template() {
+        let reply = AnnotationComment {
+            id: "c1".into(),
+            annotation_id: "a".into(),
+            author: "bob".into(),
+            body: "agreed\nwill fix".into(),
+            created_at: "2024-01-01T00:00:00Z".into(),
+            updated_at: "2024-01-01T00:00:00Z".into(),
+        };
+        let opts = |format| StitchOptions {
+            threads: HashMap::from([("a".to_string(), vec![reply.clone()])]),
+            format,
+            ..Default::default()
+        };
+        let render = |t, format| {
+            generate_prompt(t, 10_000, vec![ann("a", "why?")], &opts(format))
+                .unwrap()
+                .prompt
+        };
+        assert!(render(TemplateId::Concise, OutputFormat::Markdown)
+            .contains("- [src/lib.rs:L1-L1] why?\n  ```\nfn main() {}\n```\n  > bob: agreed\n    will fix\n"));
+        assert!(render(TemplateId::Detailed, OutputFormat::Markdown)
+            .contains("  thread:\n    - bob (2024-01-01T00:00:00Z): agreed\n      will fix\n"));
+        let v: serde_json::Value =
+            serde_json::from_str(&render(TemplateId::Concise, OutputFormat::Json)).unwrap();
+        assert_eq!(
+            v["items"][0]["annotations"][0]["thread"][0]["author"],
+            "bob"
+        );
+        assert!(render(TemplateId::Concise, OutputFormat::Xml)
+            .contains("<thread>\n<reply author=\"bob\""));
+        // 降级（折叠/仅评论）时不带线程
+        let mut long = ann("a", "why?");
+        long.selected_text = "y".repeat(400);
+        let r = generate_prompt(
+            TemplateId::Concise,
+            200,
+            vec![long],
+            &opts(OutputFormat::Markdown),
+        )
+        .unwrap();
+        assert_eq!(r.stats.used, 1);
+        assert_ne!(r.stats.items[0].level, InclusionLevel::Full);
+        assert!(!r.prompt.contains("bob"));
+    }
+
+    #[test]
+    fn bpe_tokenizers_by_name() {
+        let t = tokenizer_by_name("CL100K").unwrap();
+        assert_eq!((t.name(), t.count("hello world")), ("cl100k", 2));
+        assert_eq!(tokenizer_by_name("o200k_base").unwrap().name(), "o200k");
+        assert!(tokenizer_by_name("gpt2").is_none());
+    }
+
+    #[test]
+    fn custom_templates_share_sort_and_budget() {
+        let src = "{% macro header(stats) %}\n## {{ stats.total }} notes\n{% endmacro %}\n{% macro item(item) %}\n{{ item.index }}. {{ item.filePath }} ({{ item.language }}) {{ item.priority }}: {{ item.comment }}\n{% endmacro %}\n{% macro footer(stats) %}\n({{ stats.omitted }} omitted)\n{% endmacro %}\n";
+        let tpl = CustomTemplate::compile("team", src).unwrap();
+        let mut p0 = ann("b", "urgent");
+        p0.priority = Some("P0".into());
+        let anns = vec![ann("a", "later"), p0];
+        let r = generate_custom(&tpl, 10_000, anns.clone(), &StitchOptions::default()).unwrap();
+        assert_eq!(
+            r.prompt,
+            "## 2 notes\n1. src/lib.rs (rust) P0: urgent\n2. src/lib.rs (rust) : later\n(0 omitted)\n"
+        );
+
+        let r = generate_custom(&tpl, 60, anns, &StitchOptions::default()).unwrap();
+        assert_eq!((r.stats.used, r.stats.truncated), (1, true));
+        assert!(r.prompt.ends_with("(1 omitted)\n"));
+        assert!(r.stats.chars <= 60, "{}", r.stats.chars);
+
+        // footer 仅在有省略时输出：预算按较长的一端预留，不会因此超出
+        let src = "{% macro item(item) %}\n{{ item.index }}. {{ item.comment }}\n{% endmacro %}\n{% macro footer(stats) %}\n{% if stats.truncated %}\n... {{ stats.omitted }} more notes omitted to fit the budget\n{% endif %}\n{% endmacro %}\n";
+        let tpl = CustomTemplate::compile("tail", src).unwrap();
+        let long = (0..3)
+            .map(|i| ann(&format!("l{i}"), "a comment long enough to matter"))
+            .collect::<Vec<_>>();
+        let r = generate_custom(&tpl, 80, long, &StitchOptions::default()).unwrap();
+        assert_eq!((r.stats.used, r.stats.truncated), (1, true));
+        assert!(r.prompt.ends_with("2 more notes omitted to fit the budget\n"));
+        assert!(r.stats.chars <= 80, "{}", r.stats.chars);
+
+        let err = CustomTemplate::compile("bad", "{{ oops").unwrap_err();
+        assert!(!err.message.is_empty());
+        assert!(CustomTemplate::compile("no-item", "hello").is_err());
+    }
+
+    #[test]
+    fn context_merges_overlapping_spans() {
+        let file = "use std::io;\n\nfn main() {\n    let a = 1;\n    let b = 2;\n    println!(\"{a}{b}\");\n}\n\nfn other() {}\n";
+        let mut first = ann("a", "first");
+        (first.start_line, first.end_line) = (4, 4);
+        let mut second = ann("b", "second");
+        (second.start_line, second.end_line) = (5, 6);
+        let mut far = ann("c", "far");
+        (far.start_line, far.end_line) = (9, 9);
+        let opts = StitchOptions {
+            context: ContextOptions {
+                lines: 0,
+                expand_block: true,
+            },
+            files: HashMap::from([("src/lib.rs".to_string(), file.to_string())]),
+            ..Default::default()
+        };
+        let r =
+            generate_prompt(TemplateId::Concise, 10_000, vec![far, second, first], &opts).unwrap();
+        assert!(r.prompt.contains(
+            "- [src/lib.rs:L4-L4] >1 first\n- [src/lib.rs:L5-L6] >2 second\n  ```rust\n3    | fn main() {\n4 >1 |     let a = 1;\n5 >2 |     let b = 2;\n"
+        ));
+        assert_eq!(r.stats.used, 3);
+        assert_eq!(r.stats.items.len(), 2);
+        assert_eq!(r.stats.items[0].merged_ids, vec!["b".to_string()]);
+
+        // 顶层选区没有包围块：退回选区本身
+        let lines: Vec<&str> = file.lines().collect();
+        assert_eq!(enclosing_block(&lines, 5, 5), Some((3, 7)));
+        assert_eq!(enclosing_block(&lines, 9, 9), None);
+        let py = [
+            "def f(x):",
+            "    if x:",
+            "        return 1",
+            "    return 2",
+            "",
+            "y = 3",
+        ];
+        assert_eq!(enclosing_block(&py, 3, 3), Some((2, 3)));
+        assert_eq!(enclosing_block(&py, 4, 4), Some((1, 4)));
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+use std::sync::Arc;
+use tiktoken_rs::CoreBPE;
+
+/// 分词器：估算文本的 token 数，拼接预算与统计共用
+pub trait Tokenizer: Send + Sync + fmt::Debug {
+    fn name(&self) -> &str;
+    fn count(&self, text: &str) -> usize;
+}
+
+/// 启发式估算：ASCII 约 4 字符 1 token，其余（CJK 等）每字符按 1 token 计
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeuristicTokenizer;
+
+impl Tokenizer for HeuristicTokenizer {
+    fn name(&self) -> &str {
+        "heuristic"
+    }
+
+    fn count(&self, text: &str) -> usize {
+        let (ascii, other) = text.chars().fold((0usize, 0usize), |(a, o), c| {
+            if c.is_ascii() {
+                (a + 1, o)
+            } else {
+                (a, o + 1)
+            }
+        });
+        ascii.div_ceil(4) + other
+    }
+}
+
+/// 本地 BPE 分词（cl100k_base / o200k_base），词表随二进制内置，无需联网
+pub struct BpeTokenizer {
+    name: &'static str,
+    bpe: &'static CoreBPE,
+}
+
+impl BpeTokenizer {
+    pub fn cl100k() -> Self {
+        Self {
+            name: "cl100k",
+            bpe: tiktoken_rs::cl100k_base_singleton(),
+        }
+    }
+
+    pub fn o200k() -> Self {
+        Self {
+            name: "o200k",
+            bpe: tiktoken_rs::o200k_base_singleton(),
+        }
+    }
+}
+
+impl fmt::Debug for BpeTokenizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BpeTokenizer")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl Tokenizer for BpeTokenizer {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn count(&self, text: &str) -> usize {
+        self.bpe.encode_ordinary(text).len()
+    }
+}
+
+/// 按名称取分词器（大小写不敏感）：heuristic / cl100k(_base) / o200k(_base)；未知名称返回 `None`
+pub fn tokenizer_by_name(name: &str) -> Option<Arc<dyn Tokenizer>> {
+    match name.to_ascii_lowercase().as_str() {
+        "heuristic" => Some(Arc::new(HeuristicTokenizer)),
+        "cl100k" | "cl100k_base" => Some(Arc::new(BpeTokenizer::cl100k())),
+        "o200k" | "o200k_base" => Some(Arc::new(BpeTokenizer::o200k())),
+        _ => None,
+    }
+}
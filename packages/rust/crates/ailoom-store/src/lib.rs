@@ -1,15 +1,178 @@
 use ailoom_core as core;
 use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous};
 use sqlx::Row;
+use std::collections::HashMap;
 use std::path::Path;
 use thiserror::Error;
 
+mod migrations;
+
+pub use migrations::SCHEMA_VERSION;
+
 #[derive(Debug, Error)]
 pub enum StoreError {
     #[error("sqlx error: {0}")]
     Sqlx(#[from] sqlx::Error),
+    #[error("invalid cursor")]
+    InvalidCursor,
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("database schema version {found} is newer than supported version {supported}")]
+    SchemaTooNew { found: i64, supported: i64 },
+}
+
+/// Sort key for `Store::query_annotations`; `id` is always the final tiebreak.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AnnotationSort {
+    #[default]
+    CreatedAt,
+    UpdatedAt,
+    FilePath,
+    Priority,
+}
+
+impl AnnotationSort {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "createdAt" => Some(Self::CreatedAt),
+            "updatedAt" => Some(Self::UpdatedAt),
+            "filePath" => Some(Self::FilePath),
+            "priority" => Some(Self::Priority),
+            _ => None,
+        }
+    }
+
+    // Column expressions forming the keyset; unset priority sorts after P2. Each list has a matching
+    // index (migrations 5 and 9), so changing an expression here needs a new index too
+    fn keys(self) -> &'static [&'static str] {
+        match self {
+            Self::CreatedAt => &["created_at", "id"],
+            Self::UpdatedAt => &["updated_at", "id"],
+            Self::FilePath => &["file_path", "start_line", "id"],
+            Self::Priority => &["COALESCE(priority, 'P9')", "created_at", "id"],
+        }
+    }
+}
+
+/// Filters pushed down into SQL. Paths are workspace-relative; empty vectors mean "no filter".
+#[derive(Debug, Clone, Default)]
+pub struct AnnotationFilter {
+    pub statuses: Vec<core::AnnotationStatus>,
+    pub file_path: Option<String>,
+    /// Plain string prefix of `file_path` (directory prefixes should end with `/`).
+    pub path_prefixes: Vec<String>,
+    /// Every tag must be present.
+    pub tags: Vec<String>,
+    pub priorities: Vec<String>,
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    pub updated_after: Option<String>,
+    pub updated_before: Option<String>,
+    /// Substring match on comment or selected text. Uses `LIKE`, so only ASCII letters match
+    /// case-insensitively; use `Store::search_annotations` for Unicode-aware matching.
+    pub text: Option<String>,
+    pub sort: AnnotationSort,
+    pub descending: bool,
+    pub limit: Option<usize>,
+    /// Opaque cursor returned as `AnnotationPage::next_cursor`.
+    pub cursor: Option<String>,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct AnnotationPage {
+    pub items: Vec<core::Annotation>,
+    pub next_cursor: Option<String>,
+}
+
+// Escape GLOB metacharacters so the prefix is matched literally (keeps the file_path index usable)
+fn glob_prefix(prefix: &str) -> String {
+    let mut out = String::with_capacity(prefix.len() + 1);
+    for ch in prefix.chars() {
+        match ch {
+            '*' | '?' | '[' => {
+                out.push('[');
+                out.push(ch);
+                out.push(']');
+            }
+            _ => out.push(ch),
+        }
+    }
+    out.push('*');
+    out
+}
+
+fn like_contains(text: &str) -> String {
+    let mut out = String::from("%");
+    for ch in text.chars() {
+        if matches!(ch, '%' | '_' | '\\') {
+            out.push('\\');
+        }
+        out.push(ch);
+    }
+    out.push('%');
+    out
+}
+
+// User text -> FTS5 query: every term quoted (no operator injection) and prefix-matched
+fn fts_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|t| format!("\"{}\"*", t.replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+// Cursor = hex(JSON array of the last row's keyset values)
+fn encode_cursor(values: &[serde_json::Value]) -> String {
+    let raw = serde_json::to_vec(values).unwrap_or_default();
+    raw.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_cursor(cursor: &str, sort: AnnotationSort) -> Result<Vec<serde_json::Value>, StoreError> {
+    if !cursor.len().is_multiple_of(2) || !cursor.is_ascii() {
+        return Err(StoreError::InvalidCursor);
+    }
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| StoreError::InvalidCursor)?;
+    let values: Vec<serde_json::Value> = serde_json::from_slice(&bytes).map_err(|_| StoreError::InvalidCursor)?;
+    let shape_ok = values.len() == sort.keys().len()
+        && values.iter().all(|v| v.is_string() || v.is_i64());
+    if !shape_ok {
+        return Err(StoreError::InvalidCursor);
+    }
+    Ok(values)
+}
+
+fn keyset_values(a: &core::Annotation, sort: AnnotationSort) -> Vec<serde_json::Value> {
+    use serde_json::Value;
+    match sort {
+        AnnotationSort::CreatedAt => vec![Value::from(a.created_at.clone()), Value::from(a.id.clone())],
+        AnnotationSort::UpdatedAt => vec![Value::from(a.updated_at.clone()), Value::from(a.id.clone())],
+        AnnotationSort::FilePath => vec![
+            Value::from(a.file_path.clone()),
+            Value::from(a.start_line),
+            Value::from(a.id.clone()),
+        ],
+        AnnotationSort::Priority => vec![
+            Value::from(a.priority.clone().unwrap_or_else(|| "P9".into())),
+            Value::from(a.created_at.clone()),
+            Value::from(a.id.clone()),
+        ],
+    }
+}
+
+/// Column list shared by every annotation SELECT (order matches `AnnotationRow`).
+const ANNOTATION_COLUMNS: &str = "id, file_path, start_line, end_line, start_column, end_column, selected_text, comment, \
+    pre_context_hash, post_context_hash, file_digest, tags, priority, diff_side, base_ref, status, \
+    resolved_at, resolved_by, resolution_note, created_at, updated_at";
+
 #[derive(Clone)]
 pub struct Store {
     pool: sqlx::SqlitePool,
@@ -17,8 +180,10 @@ pub struct Store {
 }
 
 impl Store {
-    /// Connect to sqlite file path and scope to a workspace (by key -> id mapping).
-    pub async fn connect_path(path: &Path, workspace_key: &str) -> Result<Self, StoreError> {
+    /// Connect to sqlite file path and scope to a workspace. The workspace is found by its stable
+    /// `identity` (git remote + root commit, or an explicit id) when given, else by `workspace_key`
+    /// (the normalized root path); see `ensure_workspace_get_id`.
+    pub async fn connect_path(path: &Path, workspace_key: &str, identity: Option<&str>) -> Result<Self, StoreError> {
         let options = SqliteConnectOptions::new()
             .filename(path)
             .create_if_missing(true)
@@ -37,7 +202,7 @@ impl Store {
         s.migrate().await?;
         // ensure workspace row and get id; use workspace_key as root_path fallback
         let ws_id = s
-            .ensure_workspace_get_id(workspace_key, workspace_key)
+            .ensure_workspace_get_id(workspace_key, workspace_key, identity)
             .await?;
         s.workspace_id = ws_id;
         Ok(s)
@@ -56,7 +221,7 @@ impl Store {
         let mut s = Self { pool, workspace_id: workspace_key.to_string() };
         s.migrate().await?;
         let ws_id = s
-            .ensure_workspace_get_id(workspace_key, workspace_key)
+            .ensure_workspace_get_id(workspace_key, workspace_key, None)
             .await?;
         s.workspace_id = ws_id;
         Ok(s)
@@ -73,118 +238,110 @@ impl Store {
         sqlx::query("PRAGMA busy_timeout=3000;")
             .execute(&self.pool)
             .await?;
+        migrations::run(&self.pool).await
+    }
 
-        // Workspaces table first: referenced by annotations FK
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS workspaces (
-                id TEXT PRIMARY KEY,
-                key TEXT UNIQUE NOT NULL,
-                root_path TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
-            );
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
-
-        // Annotations with explicit FK to workspaces(id)
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS annotations (
-                id TEXT PRIMARY KEY,
-                file_path TEXT NOT NULL,
-                start_line INTEGER NOT NULL,
-                end_line INTEGER NOT NULL,
-                start_column INTEGER,
-                end_column INTEGER,
-                selected_text TEXT NOT NULL,
-                comment TEXT NOT NULL,
-                pre_context_hash TEXT,
-                post_context_hash TEXT,
-                file_digest TEXT,
-                tags TEXT,
-                priority TEXT,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-                workspace_id TEXT NOT NULL,
-                FOREIGN KEY(workspace_id) REFERENCES workspaces(id)
-                    ON DELETE RESTRICT
-                    ON UPDATE CASCADE
-            );
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
-
-        // Clean schema (fresh DB expected during development). No legacy backfill.
-
-        sqlx::query(
-            "CREATE INDEX IF NOT EXISTS idx_annotations_file_path ON annotations(file_path);",
-        )
-        .execute(&self.pool)
-        .await?;
-        sqlx::query(
-            "CREATE INDEX IF NOT EXISTS idx_annotations_created_at ON annotations(created_at);",
-        )
-        .execute(&self.pool)
-        .await?;
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_annotations_file_span_created ON annotations(file_path, start_line, end_line, created_at);")
-            .execute(&self.pool)
-            .await?;
-        // workspace-scoped indices
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_annotations_ws_id ON annotations(workspace_id);")
-            .execute(&self.pool)
-            .await?;
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_annotations_ws_id_file ON annotations(workspace_id, file_path);")
-            .execute(&self.pool)
-            .await?;
-        // workspace indices
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_workspaces_updated_at ON workspaces(updated_at);")
-            .execute(&self.pool)
-            .await?;
-        Ok(())
+    /// Schema version recorded in the database (see `migrations`).
+    pub async fn schema_version(&self) -> Result<i64, StoreError> {
+        let mut conn = self.pool.acquire().await?;
+        migrations::current_version(&mut conn).await
     }
 
-    /// Upsert current workspace row by unique `key` and return its id (UUID-like or existing).
-    pub async fn ensure_workspace_get_id(&self, key: &str, root_path: &str) -> Result<String, StoreError> {
-        // Try select existing by key
-        if let Some(row) = sqlx::query("SELECT id FROM workspaces WHERE key = ?1")
+    /// Resolve the workspace row for this checkout and return its id.
+    ///
+    /// With an `identity` (see `connect_path`), a row carrying the same identity wins when it is bound to
+    /// `key` already, was detached from this `root_path`, or its recorded `root_path` no longer exists (the
+    /// repo was moved or re-cloned); it is then rebound to `key`/`root_path`. A second live checkout of the
+    /// same repo keeps its own row. Otherwise the row with the same `key` is used (path fallback) unless it
+    /// belongs to a different identity, in which case it is detached and a new row is created.
+    pub async fn ensure_workspace_get_id(
+        &self,
+        key: &str,
+        root_path: &str,
+        identity: Option<&str>,
+    ) -> Result<String, StoreError> {
+        let now = core::now_rfc3339();
+        let mut tx = self.pool.begin().await?;
+        let by_key: Option<(String, Option<String>)> = sqlx::query_as("SELECT id, identity FROM workspaces WHERE key = ?1")
             .bind(key)
-            .fetch_optional(&self.pool)
-            .await?
-        {
-            let id: String = row.get::<String, _>("id");
-            // Update root_path/updated_at for hygiene
-            let _ = sqlx::query(
-                "UPDATE workspaces SET root_path=?2, updated_at=strftime('%Y-%m-%dT%H:%M:%SZ','now') WHERE key=?1",
+            .fetch_optional(&mut *tx)
+            .await?;
+        if let Some(identity) = identity {
+            let rows: Vec<(String, String, String)> = sqlx::query_as(
+                "SELECT id, key, root_path FROM workspaces WHERE identity = ?1 ORDER BY updated_at DESC",
             )
-            .bind(key)
-            .bind(root_path)
-            .execute(&self.pool)
-            .await;
-            return Ok(id);
+            .bind(identity)
+            .fetch_all(&mut *tx)
+            .await?;
+            // Same key, then a row detached from this very path earlier, then one whose root is gone
+            let hit = rows
+                .iter()
+                .find(|(_, k, _)| k == key)
+                .or_else(|| rows.iter().find(|(_, _, rp)| rp == root_path))
+                .or_else(|| rows.iter().find(|(_, _, rp)| !Path::new(rp).exists()));
+            if let Some((id, old_key, _)) = hit {
+                if old_key != key {
+                    if let Some((other, _)) = &by_key {
+                        // The path is held by another (stale or unrelated) workspace: keep that one
+                        // reachable for the admin APIs under a detached key
+                        detach_workspace_with(&mut *tx, other).await?;
+                    }
+                }
+                sqlx::query(
+                    "UPDATE workspaces SET key=?2, root_path=?3, updated_at=?4 WHERE id=?1",
+                )
+                .bind(id)
+                .bind(key)
+                .bind(root_path)
+                .bind(&now)
+                .execute(&mut *tx)
+                .await?;
+                tx.commit().await?;
+                return Ok(id.clone());
+            }
+        }
+        match by_key {
+            // Another repository now lives at this path (e.g. a different repo cloned into it): the old
+            // row keeps its identity and annotations under a detached key, and this one gets a new row
+            Some((id, Some(old))) if identity.is_some_and(|i| i != old) => {
+                detach_workspace_with(&mut *tx, &id).await?;
+            }
+            Some((id, _)) => {
+                // Update root_path/updated_at for hygiene; adopt the identity when we know it
+                sqlx::query(
+                    "UPDATE workspaces SET root_path=?2, identity=COALESCE(?3, identity), updated_at=?4 WHERE id=?1",
+                )
+                .bind(&id)
+                .bind(root_path)
+                .bind(identity)
+                .bind(&now)
+                .execute(&mut *tx)
+                .await?;
+                tx.commit().await?;
+                return Ok(id);
+            }
+            None => {}
         }
         // Insert new row with generated id
         let id = uuid::Uuid::new_v4().to_string();
         sqlx::query(
-            r#"INSERT INTO workspaces (id, key, root_path, created_at, updated_at)
-               VALUES (?1, ?2, ?3, strftime('%Y-%m-%dT%H:%M:%SZ','now'), strftime('%Y-%m-%dT%H:%M:%SZ','now'))"#,
+            r#"INSERT INTO workspaces (id, key, root_path, identity, created_at, updated_at)
+               VALUES (?1, ?2, ?3, ?4, ?5, ?5)"#,
         )
         .bind(&id)
         .bind(key)
         .bind(root_path)
-        .execute(&self.pool)
+        .bind(identity)
+        .bind(&now)
+        .execute(&mut *tx)
         .await?;
+        tx.commit().await?;
         Ok(id)
     }
 
     pub async fn list_annotations(&self) -> Result<Vec<core::Annotation>, StoreError> {
         let rows = sqlx::query_as::<_, AnnotationRow>(
-            r#"SELECT id, file_path, start_line, end_line, start_column, end_column, selected_text, comment,
-               pre_context_hash, post_context_hash, file_digest, tags, priority, created_at, updated_at
-               FROM annotations WHERE workspace_id = ?1 ORDER BY created_at DESC"#
+            &format!("SELECT {ANNOTATION_COLUMNS} FROM annotations WHERE workspace_id = ?1 ORDER BY created_at DESC"),
         )
         .bind(&self.workspace_id)
         .fetch_all(&self.pool)
@@ -192,53 +349,215 @@ impl Store {
         Ok(rows.into_iter().map(AnnotationRow::into_core).collect())
     }
 
+    /// Annotations whose status is one of `statuses` (newest first).
+    pub async fn list_annotations_with_status(
+        &self,
+        statuses: &[core::AnnotationStatus],
+    ) -> Result<Vec<core::Annotation>, StoreError> {
+        if statuses.is_empty() {
+            return Ok(vec![]);
+        }
+        let placeholders: Vec<String> = (0..statuses.len()).map(|i| format!("?{}", i + 2)).collect();
+        let q = format!(
+            "SELECT {ANNOTATION_COLUMNS} FROM annotations WHERE workspace_id = ?1 AND status IN ({}) ORDER BY created_at DESC",
+            placeholders.join(",")
+        );
+        let mut query = sqlx::query_as::<_, AnnotationRow>(&q).bind(&self.workspace_id);
+        for st in statuses {
+            query = query.bind(st.as_str());
+        }
+        let rows = query.fetch_all(&self.pool).await?;
+        Ok(rows.into_iter().map(AnnotationRow::into_core).collect())
+    }
+
+    /// Filtered, sorted and keyset-paginated listing of the current workspace.
+    pub async fn query_annotations(&self, f: &AnnotationFilter) -> Result<AnnotationPage, StoreError> {
+        enum Arg {
+            Text(String),
+            Int(i64),
+        }
+        let mut args: Vec<Arg> = vec![Arg::Text(self.workspace_id.clone())];
+        fn next(args: &mut Vec<Arg>, a: Arg) -> String {
+            args.push(a);
+            format!("?{}", args.len())
+        }
+        let mut conds: Vec<String> = vec!["workspace_id = ?1".into()];
+        if !f.statuses.is_empty() {
+            let ph: Vec<String> = f.statuses.iter().map(|st| next(&mut args, Arg::Text(st.as_str().into()))).collect();
+            conds.push(format!("status IN ({})", ph.join(",")));
+        }
+        if let Some(p) = &f.file_path {
+            conds.push(format!("file_path = {}", next(&mut args, Arg::Text(p.clone()))));
+        }
+        for prefix in &f.path_prefixes {
+            conds.push(format!("file_path GLOB {}", next(&mut args, Arg::Text(glob_prefix(prefix)))));
+        }
+        for tag in &f.tags {
+            conds.push(format!(
+                "id IN (SELECT annotation_id FROM annotation_tags WHERE tag = {})",
+                next(&mut args, Arg::Text(tag.clone()))
+            ));
+        }
+        if !f.priorities.is_empty() {
+            let ph: Vec<String> = f.priorities.iter().map(|p| next(&mut args, Arg::Text(p.clone()))).collect();
+            conds.push(format!("priority IN ({})", ph.join(",")));
+        }
+        for (col, op, v) in [
+            ("created_at", ">=", &f.created_after),
+            ("created_at", "<", &f.created_before),
+            ("updated_at", ">=", &f.updated_after),
+            ("updated_at", "<", &f.updated_before),
+        ] {
+            if let Some(v) = v {
+                conds.push(format!("{col} {op} {}", next(&mut args, Arg::Text(v.clone()))));
+            }
+        }
+        if let Some(t) = f.text.as_deref().filter(|t| !t.is_empty()) {
+            let ph = next(&mut args, Arg::Text(like_contains(t)));
+            conds.push(format!("(comment LIKE {ph} ESCAPE '\\' OR selected_text LIKE {ph} ESCAPE '\\')"));
+        }
+        let keys = f.sort.keys();
+        if let Some(c) = &f.cursor {
+            let values = decode_cursor(c, f.sort)?;
+            let ph: Vec<String> = values
+                .into_iter()
+                .map(|v| match v {
+                    serde_json::Value::Number(n) => next(&mut args, Arg::Int(n.as_i64().unwrap_or_default())),
+                    other => next(&mut args, Arg::Text(other.as_str().unwrap_or_default().to_string())),
+                })
+                .collect();
+            let op = if f.descending { "<" } else { ">" };
+            conds.push(format!("({}) {op} ({})", keys.join(", "), ph.join(", ")));
+        }
+        let dir = if f.descending { "DESC" } else { "ASC" };
+        let order: Vec<String> = keys.iter().map(|k| format!("{k} {dir}")).collect();
+        let mut q = format!(
+            "SELECT {ANNOTATION_COLUMNS} FROM annotations WHERE {} ORDER BY {}",
+            conds.join(" AND "),
+            order.join(", ")
+        );
+        // Fetch one extra row to learn whether another page exists
+        if let Some(limit) = f.limit {
+            q.push_str(&format!(" LIMIT {}", limit + 1));
+        }
+        let mut query = sqlx::query_as::<_, AnnotationRow>(&q);
+        for a in args {
+            query = match a {
+                Arg::Text(v) => query.bind(v),
+                Arg::Int(v) => query.bind(v),
+            };
+        }
+        let rows = query.fetch_all(&self.pool).await?;
+        let mut items: Vec<core::Annotation> = rows.into_iter().map(AnnotationRow::into_core).collect();
+        let mut next_cursor = None;
+        if let Some(limit) = f.limit {
+            if items.len() > limit {
+                items.truncate(limit);
+                next_cursor = items.last().map(|a| encode_cursor(&keyset_values(a, f.sort)));
+            }
+        }
+        Ok(AnnotationPage { items, next_cursor })
+    }
+
+    /// Full-text search (best match first). Each whitespace-separated term is matched as a prefix and all
+    /// terms must occur; `statuses` / `path_prefixes` narrow the hits like in `AnnotationFilter`.
+    pub async fn search_annotations(
+        &self,
+        text: &str,
+        statuses: &[core::AnnotationStatus],
+        path_prefixes: &[String],
+        limit: usize,
+    ) -> Result<Vec<core::AnnotationSearchHit>, StoreError> {
+        let Some(fts_query) = fts_query(text) else {
+            return Ok(vec![]);
+        };
+        let cols: Vec<String> = ANNOTATION_COLUMNS.split(", ").map(|c| format!("a.{}", c.trim())).collect();
+        let mut q = format!(
+            "SELECT {}, bm25(annotations_fts) AS rank, \
+             snippet(annotations_fts, 0, char(2), char(3), '…', 16) AS comment_snippet, \
+             snippet(annotations_fts, 1, char(2), char(3), '…', 16) AS selected_text_snippet \
+             FROM annotations_fts \
+             JOIN annotation_fts_ids m ON m.id = annotations_fts.rowid \
+             JOIN annotations a ON a.id = m.annotation_id \
+             WHERE annotations_fts MATCH ?1 AND a.workspace_id = ?2",
+            cols.join(", ")
+        );
+        let mut binds: Vec<String> = vec![fts_query, self.workspace_id.clone()];
+        if !statuses.is_empty() {
+            let ph: Vec<String> = statuses
+                .iter()
+                .map(|st| {
+                    binds.push(st.as_str().to_string());
+                    format!("?{}", binds.len())
+                })
+                .collect();
+            q.push_str(&format!(" AND a.status IN ({})", ph.join(",")));
+        }
+        for prefix in path_prefixes {
+            binds.push(glob_prefix(prefix));
+            q.push_str(&format!(" AND a.file_path GLOB ?{}", binds.len()));
+        }
+        q.push_str(&format!(" ORDER BY rank LIMIT {limit}"));
+        let mut query = sqlx::query(&q);
+        for b in binds {
+            query = query.bind(b);
+        }
+        let rows = query.fetch_all(&self.pool).await?;
+        let mut out = Vec::with_capacity(rows.len());
+        for row in rows {
+            let rank: f64 = row.try_get("rank")?;
+            let comment_snippet: String = row.try_get("comment_snippet")?;
+            let selected_text_snippet: String = row.try_get("selected_text_snippet")?;
+            let ann = <AnnotationRow as sqlx::FromRow<_>>::from_row(&row)?.into_core();
+            out.push(core::AnnotationSearchHit {
+                annotation: ann,
+                // bm25() is "lower is better"; flip so callers can sort descending
+                score: -rank,
+                comment_snippet: snippet_html(&comment_snippet),
+                selected_text_snippet: snippet_html(&selected_text_snippet),
+            });
+        }
+        Ok(out)
+    }
+
     pub async fn insert_annotation(&self, ann: &core::Annotation) -> Result<(), StoreError> {
-        sqlx::query(
-            r#"INSERT INTO annotations
-               (id, file_path, start_line, end_line, start_column, end_column, selected_text, comment,
-                pre_context_hash, post_context_hash, file_digest, tags, priority, created_at, updated_at, workspace_id)
-               VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)"#,
-        )
-        .bind(&ann.id)
-        .bind(&ann.file_path)
-        .bind(ann.start_line)
-        .bind(ann.end_line)
-        .bind(ann.start_column)
-        .bind(ann.end_column)
-        .bind(&ann.selected_text)
-        .bind(&ann.comment)
-        .bind(&ann.pre_context_hash)
-        .bind(&ann.post_context_hash)
-        .bind(&ann.file_digest)
-        .bind(ann.tags.as_ref().map(|v| serde_json::to_string(v).unwrap_or("[]".into())))
-        .bind(&ann.priority)
-        .bind(&ann.created_at)
-        .bind(&ann.updated_at)
-        .bind(&self.workspace_id)
-        .execute(&self.pool)
-        .await?;
-        Ok(())
+        insert_annotation_with(&self.pool, &self.workspace_id, ann).await
     }
 
     pub async fn get_annotation(&self, id: &str) -> Result<Option<core::Annotation>, StoreError> {
+        self.get_annotation_with(&self.pool, id).await
+    }
+
+    async fn get_annotation_with<'e, E: sqlx::SqliteExecutor<'e>>(
+        &self,
+        exec: E,
+        id: &str,
+    ) -> Result<Option<core::Annotation>, StoreError> {
         let r = sqlx::query_as::<_, AnnotationRow>(
-            r#"SELECT id, file_path, start_line, end_line, start_column, end_column, selected_text, comment,
-                pre_context_hash, post_context_hash, file_digest, tags, priority, created_at, updated_at
-                FROM annotations WHERE id = ?1 AND workspace_id = ?2"#
+            &format!("SELECT {ANNOTATION_COLUMNS} FROM annotations WHERE id = ?1 AND workspace_id = ?2"),
         )
         .bind(id)
         .bind(&self.workspace_id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(exec)
         .await?;
         Ok(r.map(AnnotationRow::into_core))
     }
 
     pub async fn update_annotation(&self, ann: &core::Annotation) -> Result<(), StoreError> {
+        self.update_annotation_with(&self.pool, ann).await
+    }
+
+    async fn update_annotation_with<'e, E: sqlx::SqliteExecutor<'e>>(
+        &self,
+        exec: E,
+        ann: &core::Annotation,
+    ) -> Result<(), StoreError> {
         sqlx::query(
             r#"UPDATE annotations SET
                 file_path=?2, start_line=?3, end_line=?4, start_column=?5, end_column=?6,
                 selected_text=?7, comment=?8, pre_context_hash=?9, post_context_hash=?10, file_digest=?11,
-                tags=?12, priority=?13, created_at=?14, updated_at=?15
+                tags=?12, priority=?13, created_at=?14, updated_at=?15, diff_side=?17, base_ref=?18, status=?19,
+                resolved_at=?20, resolved_by=?21, resolution_note=?22
               WHERE id=?1 AND workspace_id=?16"#
         )
         .bind(&ann.id)
@@ -257,7 +576,13 @@ impl Store {
         .bind(&ann.created_at)
         .bind(&ann.updated_at)
         .bind(&self.workspace_id)
-        .execute(&self.pool)
+        .bind(&ann.diff_side)
+        .bind(&ann.base_ref)
+        .bind(ann.status.as_str())
+        .bind(&ann.resolved_at)
+        .bind(&ann.resolved_by)
+        .bind(&ann.resolution_note)
+        .execute(exec)
         .await?;
         Ok(())
     }
@@ -275,28 +600,266 @@ impl Store {
         self.list_annotations().await
     }
 
+    /// Merge `anns` into the current workspace in one transaction. With `dry_run` the same plan is
+    /// computed and rolled back, so the report matches what a real import would do.
     pub async fn import_annotations(
         &self,
         anns: &[core::Annotation],
-    ) -> Result<(u64, u64, u64), StoreError> {
-        let mut added = 0u64;
-        let mut updated = 0u64;
-        let mut skipped = 0u64;
+        strategy: core::ImportStrategy,
+        dry_run: bool,
+    ) -> Result<core::ImportReport, StoreError> {
+        let mut tx = self.pool.begin().await?;
+        let mut report = core::ImportReport { dry_run, ..Default::default() };
+        let now = core::now_rfc3339();
         for a in anns {
-            let existing = self.get_annotation(&a.id).await?;
-            if let Some(old) = existing {
-                if a.updated_at > old.updated_at {
-                    self.update_annotation(a).await?;
-                    updated += 1;
-                } else {
-                    skipped += 1;
+            let existing = self.get_annotation_with(&mut *tx, &a.id).await?;
+            // The same id may already belong to another workspace (ids are globally unique)
+            let foreign = existing.is_none()
+                && sqlx::query("SELECT 1 FROM annotations WHERE id = ?1")
+                    .bind(&a.id)
+                    .fetch_optional(&mut *tx)
+                    .await?
+                    .is_some();
+            let conflict = existing.is_some() || foreign;
+            match (conflict, strategy) {
+                (false, _) => {
+                    insert_annotation_with(&mut *tx, &self.workspace_id, a).await?;
+                    self.record_revision_with(&mut *tx, a, core::RevisionKind::Import, &now).await?;
+                    report.added_ids.push(a.id.clone());
+                }
+                (true, core::ImportStrategy::DuplicateWithNewId) => {
+                    let mut dup = a.clone();
+                    dup.id = uuid::Uuid::new_v4().to_string();
+                    insert_annotation_with(&mut *tx, &self.workspace_id, &dup).await?;
+                    self.record_revision_with(&mut *tx, &dup, core::RevisionKind::Import, &now).await?;
+                    report.id_map.insert(a.id.clone(), dup.id.clone());
+                    report.added_ids.push(dup.id);
+                }
+                (true, core::ImportStrategy::Overwrite) if !foreign => {
+                    if let Some(old) = &existing {
+                        self.backfill_baseline_with(&mut tx, old).await?;
+                    }
+                    self.update_annotation_with(&mut *tx, a).await?;
+                    self.record_revision_with(&mut *tx, a, core::RevisionKind::Import, &now).await?;
+                    report.updated_ids.push(a.id.clone());
+                }
+                (true, core::ImportStrategy::NewerWins)
+                    if existing.as_ref().is_some_and(|old| a.updated_at > old.updated_at) =>
+                {
+                    if let Some(old) = &existing {
+                        self.backfill_baseline_with(&mut tx, old).await?;
+                    }
+                    self.update_annotation_with(&mut *tx, a).await?;
+                    self.record_revision_with(&mut *tx, a, core::RevisionKind::Import, &now).await?;
+                    report.updated_ids.push(a.id.clone());
                 }
-            } else {
-                self.insert_annotation(a).await?;
-                added += 1;
+                (true, _) => report.skipped_ids.push(a.id.clone()),
             }
         }
-        Ok((added, updated, skipped))
+        if dry_run {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+        }
+        report.added = report.added_ids.len() as u64;
+        report.updated = report.updated_ids.len() as u64;
+        report.skipped = report.skipped_ids.len() as u64;
+        Ok(report)
+    }
+}
+
+// Revision history (scoped to the current workspace through their annotation)
+impl Store {
+    /// Insert `ann` together with its first revision, in one transaction.
+    pub async fn insert_annotation_recorded(&self, ann: &core::Annotation, kind: core::RevisionKind) -> Result<(), StoreError> {
+        let mut tx = self.pool.begin().await?;
+        insert_annotation_with(&mut *tx, &self.workspace_id, ann).await?;
+        self.record_revision_with(&mut *tx, ann, kind, &core::now_rfc3339()).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Write `ann` and record it as a `kind` revision, in one transaction. An annotation without any
+    /// history (created before revisions existed) first gets its current row as a `baseline`, so the
+    /// change can be rolled back. Returns `false` when the annotation does not exist.
+    pub async fn update_annotation_recorded(&self, ann: &core::Annotation, kind: core::RevisionKind) -> Result<bool, StoreError> {
+        let mut tx = self.pool.begin().await?;
+        let Some(old) = self.get_annotation_with(&mut *tx, &ann.id).await? else { return Ok(false) };
+        self.backfill_baseline_with(&mut tx, &old).await?;
+        self.update_annotation_with(&mut *tx, ann).await?;
+        self.record_revision_with(&mut *tx, ann, kind, &core::now_rfc3339()).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    // Baseline is stamped with the old row's `updated_at`: that is when this state came to be
+    async fn backfill_baseline_with(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        old: &core::Annotation,
+    ) -> Result<(), StoreError> {
+        let has_history = sqlx::query("SELECT 1 FROM annotation_revisions WHERE annotation_id = ?1 LIMIT 1")
+            .bind(&old.id)
+            .fetch_optional(&mut **tx)
+            .await?
+            .is_some();
+        if !has_history {
+            self.record_revision_with(&mut **tx, old, core::RevisionKind::Baseline, &old.updated_at).await?;
+        }
+        Ok(())
+    }
+
+    async fn record_revision_with<'e, E: sqlx::SqliteExecutor<'e>>(
+        &self,
+        exec: E,
+        ann: &core::Annotation,
+        kind: core::RevisionKind,
+        created_at: &str,
+    ) -> Result<(), StoreError> {
+        sqlx::query(
+            r#"INSERT INTO annotation_revisions (id, annotation_id, kind, snapshot, created_at)
+               VALUES (?1, ?2, ?3, ?4, ?5)"#,
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&ann.id)
+        .bind(kind.as_str())
+        .bind(serde_json::to_string(ann)?)
+        .bind(created_at)
+        .execute(exec)
+        .await?;
+        Ok(())
+    }
+
+    /// Revisions of one annotation, newest first.
+    pub async fn list_revisions(&self, annotation_id: &str) -> Result<Vec<core::AnnotationRevision>, StoreError> {
+        let rows = sqlx::query_as::<_, RevisionRow>(
+            r#"SELECT r.id, r.annotation_id, r.kind, r.snapshot, r.created_at
+               FROM annotation_revisions r JOIN annotations a ON a.id = r.annotation_id
+               WHERE r.annotation_id = ?1 AND a.workspace_id = ?2
+               ORDER BY r.created_at DESC, r.rowid DESC"#,
+        )
+        .bind(annotation_id)
+        .bind(&self.workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().filter_map(RevisionRow::into_core).collect())
+    }
+
+    pub async fn get_revision(&self, annotation_id: &str, id: &str) -> Result<Option<core::AnnotationRevision>, StoreError> {
+        let r = sqlx::query_as::<_, RevisionRow>(
+            r#"SELECT r.id, r.annotation_id, r.kind, r.snapshot, r.created_at
+               FROM annotation_revisions r JOIN annotations a ON a.id = r.annotation_id
+               WHERE r.id = ?1 AND r.annotation_id = ?2 AND a.workspace_id = ?3"#,
+        )
+        .bind(id)
+        .bind(annotation_id)
+        .bind(&self.workspace_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(r.and_then(RevisionRow::into_core))
+    }
+}
+
+// Comment threads (scoped to the current workspace through their annotation)
+impl Store {
+    pub async fn list_comments(&self, annotation_id: &str) -> Result<Vec<core::AnnotationComment>, StoreError> {
+        let rows = sqlx::query_as::<_, CommentRow>(
+            r#"SELECT c.id, c.annotation_id, c.author, c.body, c.created_at, c.updated_at
+               FROM annotation_comments c JOIN annotations a ON a.id = c.annotation_id
+               WHERE c.annotation_id = ?1 AND a.workspace_id = ?2
+               ORDER BY c.created_at ASC, c.id ASC"#,
+        )
+        .bind(annotation_id)
+        .bind(&self.workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(CommentRow::into_core).collect())
+    }
+
+    /// Threads for several annotations at once, keyed by annotation id.
+    pub async fn list_comments_for(
+        &self,
+        annotation_ids: &[String],
+    ) -> Result<HashMap<String, Vec<core::AnnotationComment>>, StoreError> {
+        let mut out: HashMap<String, Vec<core::AnnotationComment>> = HashMap::new();
+        if annotation_ids.is_empty() {
+            return Ok(out);
+        }
+        let placeholders: Vec<String> = (0..annotation_ids.len()).map(|i| format!("?{}", i + 2)).collect();
+        let q = format!(
+            "SELECT c.id, c.annotation_id, c.author, c.body, c.created_at, c.updated_at
+             FROM annotation_comments c JOIN annotations a ON a.id = c.annotation_id
+             WHERE a.workspace_id = ?1 AND c.annotation_id IN ({})
+             ORDER BY c.created_at ASC, c.id ASC",
+            placeholders.join(",")
+        );
+        let mut query = sqlx::query_as::<_, CommentRow>(&q).bind(&self.workspace_id);
+        for id in annotation_ids {
+            query = query.bind(id);
+        }
+        for row in query.fetch_all(&self.pool).await? {
+            let c = row.into_core();
+            out.entry(c.annotation_id.clone()).or_default().push(c);
+        }
+        Ok(out)
+    }
+
+    pub async fn get_comment(&self, annotation_id: &str, id: &str) -> Result<Option<core::AnnotationComment>, StoreError> {
+        let r = sqlx::query_as::<_, CommentRow>(
+            r#"SELECT c.id, c.annotation_id, c.author, c.body, c.created_at, c.updated_at
+               FROM annotation_comments c JOIN annotations a ON a.id = c.annotation_id
+               WHERE c.id = ?1 AND c.annotation_id = ?2 AND a.workspace_id = ?3"#,
+        )
+        .bind(id)
+        .bind(annotation_id)
+        .bind(&self.workspace_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(r.map(CommentRow::into_core))
+    }
+
+    pub async fn insert_comment(&self, c: &core::AnnotationComment) -> Result<(), StoreError> {
+        sqlx::query(
+            r#"INSERT INTO annotation_comments (id, annotation_id, author, body, created_at, updated_at)
+               VALUES (?1, ?2, ?3, ?4, ?5, ?6)"#,
+        )
+        .bind(&c.id)
+        .bind(&c.annotation_id)
+        .bind(&c.author)
+        .bind(&c.body)
+        .bind(&c.created_at)
+        .bind(&c.updated_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn update_comment(&self, c: &core::AnnotationComment) -> Result<bool, StoreError> {
+        let r = sqlx::query(
+            r#"UPDATE annotation_comments SET body=?3, updated_at=?4 WHERE id=?1 AND annotation_id=?2
+               AND annotation_id IN (SELECT id FROM annotations WHERE workspace_id=?5)"#,
+        )
+        .bind(&c.id)
+        .bind(&c.annotation_id)
+        .bind(&c.body)
+        .bind(&c.updated_at)
+        .bind(&self.workspace_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(r.rows_affected() > 0)
+    }
+
+    pub async fn delete_comment(&self, annotation_id: &str, id: &str) -> Result<bool, StoreError> {
+        let r = sqlx::query(
+            r#"DELETE FROM annotation_comments WHERE id=?1 AND annotation_id=?2
+               AND annotation_id IN (SELECT id FROM annotations WHERE workspace_id=?3)"#,
+        )
+        .bind(id)
+        .bind(annotation_id)
+        .bind(&self.workspace_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(r.rows_affected() > 0)
     }
 }
 
@@ -309,7 +872,7 @@ impl Store {
             return self.list_annotations().await;
         }
         // Dynamically build IN clause (scoped by workspace_id)
-        let mut q = String::from("SELECT id, file_path, start_line, end_line, start_column, end_column, selected_text, comment, pre_context_hash, post_context_hash, file_digest, tags, priority, created_at, updated_at FROM annotations WHERE workspace_id = ?1 AND id IN (");
+        let mut q = format!("SELECT {ANNOTATION_COLUMNS} FROM annotations WHERE workspace_id = ?1 AND id IN (");
         for i in 0..ids.len() {
             if i > 0 {
                 q.push(',');
@@ -327,6 +890,255 @@ impl Store {
     }
 }
 
+// Cross-workspace administration (not scoped to the current workspace)
+impl Store {
+    /// Id of the workspace this store is scoped to.
+    pub fn workspace_id(&self) -> &str {
+        &self.workspace_id
+    }
+
+    /// All workspaces with annotation counts, most recently active first.
+    pub async fn list_workspaces(&self) -> Result<Vec<core::WorkspaceSummary>, StoreError> {
+        let rows = sqlx::query_as::<_, WorkspaceRow>(&format!("{WORKSPACE_SUMMARY_SELECT} GROUP BY w.id ORDER BY last_activity DESC"))
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(WorkspaceRow::into_core).collect())
+    }
+
+    pub async fn get_workspace(&self, id: &str) -> Result<Option<core::WorkspaceSummary>, StoreError> {
+        let row = sqlx::query_as::<_, WorkspaceRow>(&format!("{WORKSPACE_SUMMARY_SELECT} WHERE w.id = ?1 GROUP BY w.id"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(WorkspaceRow::into_core))
+    }
+
+    /// Move or copy annotations from one workspace to another in one transaction (`ids` empty = all).
+    /// Returns `(source id, id in target)` pairs; copies get new ids and an `import` revision.
+    pub async fn transfer_annotations(
+        &self,
+        from: &str,
+        to: &str,
+        ids: &[String],
+        mode: core::TransferMode,
+    ) -> Result<Vec<(String, String)>, StoreError> {
+        let mut tx = self.pool.begin().await?;
+        let now = core::now_rfc3339();
+        let mut q = format!("SELECT {ANNOTATION_COLUMNS} FROM annotations WHERE workspace_id = ?1");
+        if !ids.is_empty() {
+            let ph: Vec<String> = (0..ids.len()).map(|i| format!("?{}", i + 2)).collect();
+            q.push_str(&format!(" AND id IN ({})", ph.join(",")));
+        }
+        let mut query = sqlx::query_as::<_, AnnotationRow>(&q).bind(from);
+        for id in ids {
+            query = query.bind(id);
+        }
+        let anns: Vec<core::Annotation> = query.fetch_all(&mut *tx).await?.into_iter().map(AnnotationRow::into_core).collect();
+        let mut out = Vec::with_capacity(anns.len());
+        for a in anns {
+            match mode {
+                core::TransferMode::Move => {
+                    sqlx::query("UPDATE annotations SET workspace_id = ?2 WHERE id = ?1")
+                        .bind(&a.id)
+                        .bind(to)
+                        .execute(&mut *tx)
+                        .await?;
+                    out.push((a.id.clone(), a.id));
+                }
+                core::TransferMode::Copy => {
+                    let mut copy = a.clone();
+                    copy.id = uuid::Uuid::new_v4().to_string();
+                    insert_annotation_with(&mut *tx, to, &copy).await?;
+                    self.record_revision_with(&mut *tx, &copy, core::RevisionKind::Import, &now).await?;
+                    out.push((a.id, copy.id));
+                }
+            }
+        }
+        sqlx::query("UPDATE workspaces SET updated_at = ?3 WHERE id IN (?1, ?2)")
+            .bind(from)
+            .bind(to)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(out)
+    }
+
+    /// Delete a workspace together with its annotations (replies and revisions cascade).
+    /// Returns the number of annotations removed.
+    pub async fn delete_workspace(&self, id: &str) -> Result<u64, StoreError> {
+        let mut tx = self.pool.begin().await?;
+        let removed = sqlx::query("DELETE FROM annotations WHERE workspace_id = ?1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        sqlx::query("DELETE FROM stitch_templates WHERE workspace_id = ?1").bind(id).execute(&mut *tx).await?;
+        sqlx::query("DELETE FROM workspaces WHERE id = ?1").bind(id).execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(removed)
+    }
+}
+
+// Stitch templates (scoped to the current workspace)
+impl Store {
+    pub async fn list_stitch_templates(&self) -> Result<Vec<core::StitchTemplate>, StoreError> {
+        let rows = sqlx::query_as::<_, StitchTemplateRow>(
+            "SELECT name, description, body, created_at, updated_at FROM stitch_templates WHERE workspace_id = ?1 ORDER BY name",
+        )
+        .bind(&self.workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(StitchTemplateRow::into_core).collect())
+    }
+
+    pub async fn get_stitch_template(&self, name: &str) -> Result<Option<core::StitchTemplate>, StoreError> {
+        let r = sqlx::query_as::<_, StitchTemplateRow>(
+            "SELECT name, description, body, created_at, updated_at FROM stitch_templates WHERE workspace_id = ?1 AND name = ?2",
+        )
+        .bind(&self.workspace_id)
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(r.map(StitchTemplateRow::into_core))
+    }
+
+    pub async fn insert_stitch_template(&self, t: &core::StitchTemplate) -> Result<(), StoreError> {
+        sqlx::query(
+            r#"INSERT INTO stitch_templates (workspace_id, name, description, body, created_at, updated_at)
+               VALUES (?1, ?2, ?3, ?4, ?5, ?6)"#,
+        )
+        .bind(&self.workspace_id)
+        .bind(&t.name)
+        .bind(&t.description)
+        .bind(&t.body)
+        .bind(&t.created_at)
+        .bind(&t.updated_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn update_stitch_template(&self, t: &core::StitchTemplate) -> Result<bool, StoreError> {
+        let r = sqlx::query("UPDATE stitch_templates SET description=?3, body=?4, updated_at=?5 WHERE workspace_id=?1 AND name=?2")
+            .bind(&self.workspace_id)
+            .bind(&t.name)
+            .bind(&t.description)
+            .bind(&t.body)
+            .bind(&t.updated_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(r.rows_affected() > 0)
+    }
+
+    pub async fn delete_stitch_template(&self, name: &str) -> Result<bool, StoreError> {
+        let r = sqlx::query("DELETE FROM stitch_templates WHERE workspace_id=?1 AND name=?2")
+            .bind(&self.workspace_id)
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(r.rows_affected() > 0)
+    }
+}
+
+const WORKSPACE_SUMMARY_SELECT: &str = "SELECT w.id, w.key, w.root_path, w.identity, w.created_at, w.updated_at, \
+    COUNT(a.id) AS annotation_count, \
+    CASE WHEN MAX(a.updated_at) > w.updated_at THEN MAX(a.updated_at) ELSE w.updated_at END AS last_activity \
+    FROM workspaces w LEFT JOIN annotations a ON a.workspace_id = w.id";
+
+#[derive(Debug, sqlx::FromRow)]
+struct WorkspaceRow {
+    id: String,
+    key: String,
+    root_path: String,
+    identity: Option<String>,
+    created_at: String,
+    updated_at: String,
+    annotation_count: i64,
+    last_activity: String,
+}
+
+impl WorkspaceRow {
+    fn into_core(self) -> core::WorkspaceSummary {
+        core::WorkspaceSummary {
+            id: self.id,
+            key: self.key,
+            root_path: self.root_path,
+            identity: self.identity,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            annotation_count: self.annotation_count,
+            last_activity: self.last_activity,
+        }
+    }
+}
+
+/// Snippets are built with STX/ETX around the hits; escape the user text for HTML and only then turn
+/// those markers into `<mark>` tags, so a comment containing markup cannot inject any.
+fn snippet_html(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len() + 16);
+    for ch in raw.chars() {
+        match ch {
+            '\u{2}' => out.push_str("<mark>"),
+            '\u{3}' => out.push_str("</mark>"),
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+async fn detach_workspace_with<'e, E: sqlx::SqliteExecutor<'e>>(exec: E, id: &str) -> Result<(), StoreError> {
+    sqlx::query("UPDATE workspaces SET key = key || '#detached-' || id WHERE id = ?1")
+        .bind(id)
+        .execute(exec)
+        .await?;
+    Ok(())
+}
+
+async fn insert_annotation_with<'e, E: sqlx::SqliteExecutor<'e>>(
+    exec: E,
+    workspace_id: &str,
+    ann: &core::Annotation,
+) -> Result<(), StoreError> {
+    sqlx::query(
+        r#"INSERT INTO annotations
+           (id, file_path, start_line, end_line, start_column, end_column, selected_text, comment,
+            pre_context_hash, post_context_hash, file_digest, tags, priority, created_at, updated_at, workspace_id,
+            diff_side, base_ref, status, resolved_at, resolved_by, resolution_note)
+           VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)"#,
+    )
+    .bind(&ann.id)
+    .bind(&ann.file_path)
+    .bind(ann.start_line)
+    .bind(ann.end_line)
+    .bind(ann.start_column)
+    .bind(ann.end_column)
+    .bind(&ann.selected_text)
+    .bind(&ann.comment)
+    .bind(&ann.pre_context_hash)
+    .bind(&ann.post_context_hash)
+    .bind(&ann.file_digest)
+    .bind(ann.tags.as_ref().map(|v| serde_json::to_string(v).unwrap_or("[]".into())))
+    .bind(&ann.priority)
+    .bind(&ann.created_at)
+    .bind(&ann.updated_at)
+    .bind(workspace_id)
+    .bind(&ann.diff_side)
+    .bind(&ann.base_ref)
+    .bind(ann.status.as_str())
+    .bind(&ann.resolved_at)
+    .bind(&ann.resolved_by)
+    .bind(&ann.resolution_note)
+    .execute(exec)
+    .await?;
+    Ok(())
+}
+
 #[derive(Debug, sqlx::FromRow)]
 struct AnnotationRow {
     id: String,
@@ -342,6 +1154,12 @@ struct AnnotationRow {
     file_digest: Option<String>,
     tags: Option<String>,
     priority: Option<String>,
+    diff_side: Option<String>,
+    base_ref: Option<String>,
+    status: String,
+    resolved_at: Option<String>,
+    resolved_by: Option<String>,
+    resolution_note: Option<String>,
     created_at: String,
     updated_at: String,
 }
@@ -362,8 +1180,422 @@ impl AnnotationRow {
             file_digest: self.file_digest,
             tags: self.tags.and_then(|s| serde_json::from_str(&s).ok()),
             priority: self.priority,
+            diff_side: self.diff_side,
+            base_ref: self.base_ref,
+            status: core::AnnotationStatus::parse(&self.status).unwrap_or_default(),
+            resolved_at: self.resolved_at,
+            resolved_by: self.resolved_by,
+            resolution_note: self.resolution_note,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct StitchTemplateRow {
+    name: String,
+    description: Option<String>,
+    body: String,
+    created_at: String,
+    updated_at: String,
+}
+
+impl StitchTemplateRow {
+    fn into_core(self) -> core::StitchTemplate {
+        core::StitchTemplate {
+            name: self.name,
+            description: self.description,
+            body: self.body,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct CommentRow {
+    id: String,
+    annotation_id: String,
+    author: String,
+    body: String,
+    created_at: String,
+    updated_at: String,
+}
+
+impl CommentRow {
+    fn into_core(self) -> core::AnnotationComment {
+        core::AnnotationComment {
+            id: self.id,
+            annotation_id: self.annotation_id,
+            author: self.author,
+            body: self.body,
             created_at: self.created_at,
             updated_at: self.updated_at,
         }
     }
 }
+
+#[derive(Debug, sqlx::FromRow)]
+struct RevisionRow {
+    id: String,
+    annotation_id: String,
+    kind: String,
+    snapshot: String,
+    created_at: String,
+}
+
+impl RevisionRow {
+    // Rows with an unreadable snapshot or unknown kind are skipped
+    fn into_core(self) -> Option<core::AnnotationRevision> {
+        Some(core::AnnotationRevision {
+            id: self.id,
+            annotation_id: self.annotation_id,
+            kind: core::RevisionKind::parse(&self.kind)?,
+            snapshot: serde_json::from_str(&self.snapshot).ok()?,
+            created_at: self.created_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ann(id: &str, comment: &str, updated_at: &str) -> core::Annotation {
+        core::Annotation {
+            id: id.into(),
+            file_path: "src/lib.rs".into(),
+            start_line: 1,
+            end_line: 1,
+            selected_text: "fn".into(),
+            comment: comment.into(),
+            created_at: "2024-01-01T00:00:00Z".into(),
+            updated_at: updated_at.into(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn import_strategies_and_dry_run() {
+        let db = std::env::temp_dir().join(format!("ailoom_store_{}_import.db", uuid::Uuid::new_v4()));
+        let store = Store::connect_path(&db, "/ws", None).await.unwrap();
+        store.insert_annotation(&ann("a", "old", "2024-02-01T00:00:00Z")).await.unwrap();
+        let bundle = vec![ann("a", "older", "2024-01-15T00:00:00Z"), ann("b", "new", "2024-01-15T00:00:00Z")];
+
+        // Dry run reports the plan but writes nothing
+        let r = store.import_annotations(&bundle, core::ImportStrategy::NewerWins, true).await.unwrap();
+        assert_eq!((r.added_ids.clone(), r.skipped_ids.clone()), (vec!["b".to_string()], vec!["a".to_string()]));
+        assert!(store.get_annotation("b").await.unwrap().is_none());
+
+        let r = store.import_annotations(&bundle, core::ImportStrategy::Overwrite, false).await.unwrap();
+        assert_eq!((r.added, r.updated, r.skipped), (1, 1, 0));
+        assert_eq!(store.get_annotation("a").await.unwrap().unwrap().comment, "older");
+
+        let r = store.import_annotations(&bundle, core::ImportStrategy::Skip, false).await.unwrap();
+        assert_eq!(r.skipped_ids, vec!["a".to_string(), "b".to_string()]);
+
+        let r = store.import_annotations(&bundle, core::ImportStrategy::DuplicateWithNewId, false).await.unwrap();
+        assert_eq!(r.added, 2);
+        assert!(r.added_ids.iter().all(|id| id != "a" && id != "b"));
+        assert_eq!(store.list_annotations().await.unwrap().len(), 4);
+        let _ = std::fs::remove_file(&db);
+    }
+
+    #[tokio::test]
+    async fn revisions_backfill_a_baseline_and_commit_with_the_change() {
+        let db = std::env::temp_dir().join(format!("ailoom_store_{}_revisions.db", uuid::Uuid::new_v4()));
+        let store = Store::connect_path(&db, "/ws", None).await.unwrap();
+        // Written without history, like annotations from before revisions existed
+        store.insert_annotation(&ann("a", "v1", "2024-01-01T00:00:00Z")).await.unwrap();
+
+        let v2 = ann("a", "v2", &core::now_rfc3339());
+        assert!(store.update_annotation_recorded(&v2, core::RevisionKind::Edit).await.unwrap());
+        let revs = store.list_revisions("a").await.unwrap();
+        let kinds: Vec<_> = revs.iter().map(|r| (r.kind, r.snapshot.comment.as_str())).collect();
+        assert_eq!(kinds, vec![(core::RevisionKind::Edit, "v2"), (core::RevisionKind::Baseline, "v1")]);
+        assert_eq!(revs[1].created_at, "2024-01-01T00:00:00Z");
+
+        // Only the first change backfills
+        let v3 = ann("a", "v3", &core::now_rfc3339());
+        store.update_annotation_recorded(&v3, core::RevisionKind::Relocate).await.unwrap();
+        assert_eq!(store.list_revisions("a").await.unwrap().len(), 3);
+
+        store.insert_annotation_recorded(&ann("b", "new", &core::now_rfc3339()), core::RevisionKind::Create).await.unwrap();
+        let revs = store.list_revisions("b").await.unwrap();
+        assert_eq!(revs.iter().map(|r| r.kind).collect::<Vec<_>>(), vec![core::RevisionKind::Create]);
+        assert_eq!(revs[0].created_at.len(), "2024-01-01T00:00:00.000Z".len());
+
+        // Missing annotation: nothing written
+        assert!(!store.update_annotation_recorded(&ann("zz", "x", "2024-01-01T00:00:00Z"), core::RevisionKind::Edit).await.unwrap());
+        assert!(store.list_revisions("zz").await.unwrap().is_empty());
+
+        // A failing revision insert rolls the annotation change back
+        sqlx::query("CREATE TRIGGER fail_rev BEFORE INSERT ON annotation_revisions BEGIN SELECT RAISE(ABORT, 'boom'); END")
+            .execute(&store.pool)
+            .await
+            .unwrap();
+        assert!(store.update_annotation_recorded(&ann("a", "v4", &core::now_rfc3339()), core::RevisionKind::Edit).await.is_err());
+        assert_eq!(store.get_annotation("a").await.unwrap().unwrap().comment, "v3");
+        let _ = std::fs::remove_file(&db);
+    }
+
+    #[tokio::test]
+    async fn comments_stay_in_their_workspace_and_go_with_their_annotation() {
+        let db = std::env::temp_dir().join(format!("ailoom_store_{}_comments.db", uuid::Uuid::new_v4()));
+        let a = Store::connect_path(&db, "/ws-a", None).await.unwrap();
+        let b = Store::connect_path(&db, "/ws-b", None).await.unwrap();
+        a.insert_annotation(&ann("x", "c", "2024-01-01T00:00:00Z")).await.unwrap();
+        let comment = |id: &str, body: &str| core::AnnotationComment {
+            id: id.into(),
+            annotation_id: "x".into(),
+            author: "user".into(),
+            body: body.into(),
+            created_at: "2024-01-01T00:00:00Z".into(),
+            updated_at: "2024-01-01T00:00:00Z".into(),
+        };
+        a.insert_comment(&comment("c1", "first")).await.unwrap();
+        a.insert_comment(&comment("c2", "second")).await.unwrap();
+        assert!(a.update_comment(&comment("c1", "edited")).await.unwrap());
+        assert_eq!(a.get_comment("x", "c1").await.unwrap().unwrap().body, "edited");
+        assert!(!a.update_comment(&comment("missing", "edited")).await.unwrap());
+
+        // Another workspace can neither see nor change the thread
+        assert!(b.get_comment("x", "c1").await.unwrap().is_none());
+        assert!(b.list_comments("x").await.unwrap().is_empty());
+        assert!(!b.update_comment(&comment("c1", "hijacked")).await.unwrap());
+        assert!(!b.delete_comment("x", "c1").await.unwrap());
+        assert_eq!(a.get_comment("x", "c1").await.unwrap().unwrap().body, "edited");
+
+        assert!(a.delete_comment("x", "c2").await.unwrap());
+        assert!(!a.delete_comment("x", "c2").await.unwrap());
+        assert_eq!(a.list_comments("x").await.unwrap().len(), 1);
+
+        // Deleting the annotation removes its thread
+        a.delete_annotation("x").await.unwrap();
+        let (left,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM annotation_comments").fetch_one(&a.pool).await.unwrap();
+        assert_eq!(left, 0);
+        let _ = std::fs::remove_file(&db);
+    }
+
+    #[tokio::test]
+    async fn keyset_pages_cover_every_row_once_in_order() {
+        let db = std::env::temp_dir().join(format!("ailoom_store_{}_paging.db", uuid::Uuid::new_v4()));
+        let store = Store::connect_path(&db, "/ws", None).await.unwrap();
+        let priorities = [Some("P0"), None, Some("P2"), Some("P1"), None, Some("P0"), Some("P1")];
+        for (i, p) in priorities.iter().enumerate() {
+            let mut a = ann(&format!("a{i}"), "c", "2024-01-01T00:00:00Z");
+            // Duplicate timestamps force the id tiebreak
+            a.created_at = format!("2024-01-0{}T00:00:00Z", 1 + i % 3);
+            a.file_path = format!("src/{}.rs", i % 2);
+            a.start_line = i as i64;
+            a.priority = p.map(String::from);
+            a.tags = Some(if i % 2 == 0 { vec!["even".into(), "x".into()] } else { vec!["x".into()] });
+            store.insert_annotation(&a).await.unwrap();
+        }
+        let sorts = [AnnotationSort::CreatedAt, AnnotationSort::UpdatedAt, AnnotationSort::FilePath, AnnotationSort::Priority];
+        for sort in sorts {
+            for descending in [false, true] {
+                let all = AnnotationFilter { sort, descending, ..Default::default() };
+                let want: Vec<String> = store.query_annotations(&all).await.unwrap().items.into_iter().map(|a| a.id).collect();
+                let mut got = Vec::new();
+                let mut cursor = None;
+                loop {
+                    let f = AnnotationFilter { sort, descending, limit: Some(2), cursor: cursor.take(), ..Default::default() };
+                    let page = store.query_annotations(&f).await.unwrap();
+                    assert!(page.items.len() <= 2);
+                    got.extend(page.items.into_iter().map(|a| a.id));
+                    match page.next_cursor {
+                        Some(c) => cursor = Some(c),
+                        None => break,
+                    }
+                }
+                assert_eq!(got, want, "{sort:?} desc={descending}");
+            }
+        }
+        // Unset priority sorts after P2
+        let f = AnnotationFilter { sort: AnnotationSort::Priority, ..Default::default() };
+        let order: Vec<Option<String>> = store.query_annotations(&f).await.unwrap().items.into_iter().map(|a| a.priority).collect();
+        assert_eq!(order[4..].to_vec(), vec![Some("P2".into()), None, None]);
+
+        // Tag filters go through the join table, which follows updates and deletes
+        let f = AnnotationFilter { tags: vec!["even".into(), "x".into()], ..Default::default() };
+        assert_eq!(store.query_annotations(&f).await.unwrap().items.len(), 4);
+        let mut a0 = store.get_annotation("a0").await.unwrap().unwrap();
+        a0.tags = Some(vec!["x".into()]);
+        store.update_annotation(&a0).await.unwrap();
+        store.delete_annotation("a2").await.unwrap();
+        assert_eq!(store.query_annotations(&f).await.unwrap().items.len(), 2);
+
+        let bad = AnnotationFilter { cursor: Some("zz".into()), ..Default::default() };
+        assert!(matches!(store.query_annotations(&bad).await, Err(StoreError::InvalidCursor)));
+        let _ = std::fs::remove_file(&db);
+    }
+
+    #[tokio::test]
+    async fn priority_sort_and_tag_filter_use_indexes() {
+        let db = std::env::temp_dir().join(format!("ailoom_store_{}_plan.db", uuid::Uuid::new_v4()));
+        let store = Store::connect_path(&db, "/ws", None).await.unwrap();
+        let plan = |sql: &'static str| {
+            let pool = store.pool.clone();
+            async move {
+                let rows = sqlx::query(&format!("EXPLAIN QUERY PLAN {sql}")).fetch_all(&pool).await.unwrap();
+                rows.iter().map(|r| r.get::<String, _>("detail")).collect::<Vec<_>>().join("\n")
+            }
+        };
+        let sorted = plan(
+            "SELECT id FROM annotations WHERE workspace_id = 'w' ORDER BY COALESCE(priority, 'P9'), created_at, id",
+        )
+        .await;
+        assert!(sorted.contains("idx_annotations_ws_priority_key") && !sorted.contains("TEMP B-TREE"), "{sorted}");
+        let tagged = plan(
+            "SELECT id FROM annotations WHERE workspace_id = 'w' AND id IN (SELECT annotation_id FROM annotation_tags WHERE tag = 'x')",
+        )
+        .await;
+        assert!(!tagged.contains("SCAN annotation_tags"), "{tagged}");
+        let _ = std::fs::remove_file(&db);
+    }
+
+    #[tokio::test]
+    async fn search_follows_changes_and_survives_vacuum() {
+        let db = std::env::temp_dir().join(format!("ailoom_store_{}_search.db", uuid::Uuid::new_v4()));
+        let store = Store::connect_path(&db, "/ws", None).await.unwrap();
+        for (id, comment) in [("a", "alpha parser"), ("b", "bravo lexer"), ("c", "charlie tokenizer")] {
+            store.insert_annotation(&ann(id, comment, "2024-01-01T00:00:00Z")).await.unwrap();
+        }
+        let hits = |q: &'static str| {
+            let store = store.clone();
+            async move { store.search_annotations(q, &[], &[], 10).await.unwrap().into_iter().map(|h| h.annotation.id).collect::<Vec<_>>() }
+        };
+        assert_eq!(hits("pars").await, vec!["a"]);
+        let hit = &store.search_annotations("bravo", &[], &[], 10).await.unwrap()[0];
+        assert_eq!(hit.comment_snippet, "<mark>bravo</mark> lexer");
+
+        // Snippets are HTML: user text is escaped, only the hit markers are tags
+        let mut x = ann("x", "<script>alert('x')</script> & bravo", "2024-01-01T00:00:00Z");
+        x.selected_text = "a < b".into();
+        store.insert_annotation(&x).await.unwrap();
+        let hit = &store.search_annotations("alert", &[], &[], 10).await.unwrap()[0];
+        assert_eq!(hit.comment_snippet, "&lt;script&gt;<mark>alert</mark>(&#39;x&#39;)&lt;/script&gt; &amp; bravo");
+        assert_eq!(hit.selected_text_snippet, "a &lt; b");
+        store.delete_annotation("x").await.unwrap();
+
+        let mut b = store.get_annotation("b").await.unwrap().unwrap();
+        b.comment = "bravo grammar".into();
+        store.update_annotation(&b).await.unwrap();
+        assert!(hits("lexer").await.is_empty());
+        assert_eq!(hits("grammar").await, vec!["b"]);
+
+        store.delete_annotation("a").await.unwrap();
+        assert!(hits("alpha").await.is_empty());
+        // The implicit rowids of `annotations` are not stable (VACUUM or a table rebuild may renumber them)
+        sqlx::query("VACUUM").execute(&store.pool).await.unwrap();
+        sqlx::query("UPDATE annotations SET rowid = rowid + 100").execute(&store.pool).await.unwrap();
+        assert_eq!(hits("charlie").await, vec!["c"]);
+        assert_eq!(hits("grammar").await, vec!["b"]);
+        let _ = std::fs::remove_file(&db);
+    }
+
+    #[tokio::test]
+    async fn transfer_between_workspaces() {
+        let db = std::env::temp_dir().join(format!("ailoom_store_{}_transfer.db", uuid::Uuid::new_v4()));
+        let old = Store::connect_path(&db, "/old", None).await.unwrap();
+        let new = Store::connect_path(&db, "/new", None).await.unwrap();
+        old.insert_annotation(&ann("a", "keep", "2024-01-01T00:00:00Z")).await.unwrap();
+        old.insert_annotation(&ann("b", "move", "2024-01-01T00:00:00Z")).await.unwrap();
+
+        let copied = new.transfer_annotations(old.workspace_id(), new.workspace_id(), &["a".into()], core::TransferMode::Copy).await.unwrap();
+        assert_ne!(copied[0].1, "a");
+        let moved = new.transfer_annotations(old.workspace_id(), new.workspace_id(), &["b".into()], core::TransferMode::Move).await.unwrap();
+        assert_eq!(moved, vec![("b".to_string(), "b".to_string())]);
+        assert_eq!(old.list_annotations().await.unwrap().len(), 1);
+        assert_eq!(new.list_annotations().await.unwrap().len(), 2);
+
+        let counts: Vec<(String, i64)> =
+            new.list_workspaces().await.unwrap().into_iter().map(|w| (w.key, w.annotation_count)).collect();
+        assert!(counts.contains(&("/old".to_string(), 1)) && counts.contains(&("/new".to_string(), 2)));
+        // Workspace and annotation timestamps share one format, so the latest activity compares correctly
+        let edited = ann("c", "now", &core::now_rfc3339());
+        new.insert_annotation(&edited).await.unwrap();
+        let ws = new.get_workspace(new.workspace_id()).await.unwrap().unwrap();
+        assert_eq!(ws.updated_at.len(), edited.updated_at.len());
+        assert_eq!(ws.last_activity, edited.updated_at);
+        assert_eq!(new.delete_workspace(old.workspace_id()).await.unwrap(), 1);
+        assert!(new.get_workspace(old.workspace_id()).await.unwrap().is_none());
+        let _ = std::fs::remove_file(&db);
+    }
+
+    #[tokio::test]
+    async fn workspace_identity_survives_moves() {
+        let db = std::env::temp_dir().join(format!("ailoom_store_{}_identity.db", uuid::Uuid::new_v4()));
+        let gone = format!("/nonexistent/ailoom_{}", uuid::Uuid::new_v4());
+        let first = Store::connect_path(&db, &gone, Some("git:example.com/r#abc")).await.unwrap();
+        first.insert_annotation(&ann("a", "c", "2024-01-01T00:00:00Z")).await.unwrap();
+
+        // Old root is gone: the same identity at a new path rebinds the workspace
+        let live = std::env::temp_dir().to_string_lossy().to_string();
+        let moved = Store::connect_path(&db, &live, Some("git:example.com/r#abc")).await.unwrap();
+        assert_eq!(moved.workspace_id(), first.workspace_id());
+        assert_eq!(moved.list_annotations().await.unwrap().len(), 1);
+        let ws = moved.get_workspace(moved.workspace_id()).await.unwrap().unwrap();
+        assert_eq!(ws.root_path, live);
+
+        // The bound root still exists: a second checkout gets its own workspace
+        let other = Store::connect_path(&db, "/another/checkout", Some("git:example.com/r#abc")).await.unwrap();
+        assert_ne!(other.workspace_id(), first.workspace_id());
+        let _ = std::fs::remove_file(&db);
+    }
+
+    #[tokio::test]
+    async fn another_repo_at_the_same_path_gets_its_own_workspace() {
+        let db = std::env::temp_dir().join(format!("ailoom_store_{}_reclone.db", uuid::Uuid::new_v4()));
+        let path = std::env::temp_dir().to_string_lossy().to_string();
+        let first = Store::connect_path(&db, &path, Some("git:example.com/a#111")).await.unwrap();
+        first.insert_annotation(&ann("a", "c", "2024-01-01T00:00:00Z")).await.unwrap();
+
+        // A different repository cloned into the same path must not take over the old annotations
+        let second = Store::connect_path(&db, &path, Some("git:example.com/b#222")).await.unwrap();
+        assert_ne!(second.workspace_id(), first.workspace_id());
+        assert!(second.list_annotations().await.unwrap().is_empty());
+        let old = second.get_workspace(first.workspace_id()).await.unwrap().unwrap();
+        assert_eq!(old.identity.as_deref(), Some("git:example.com/a#111"));
+        assert_ne!(old.key, path);
+
+        // Switching back finds the detached row again instead of creating a third one
+        let back = Store::connect_path(&db, &path, Some("git:example.com/a#111")).await.unwrap();
+        assert_eq!(back.workspace_id(), first.workspace_id());
+        assert_eq!(back.list_annotations().await.unwrap().len(), 1);
+        assert_eq!(back.list_workspaces().await.unwrap().len(), 2);
+
+        // Unknown identity (no git, no id file) keeps using the row at the path
+        let unknown = Store::connect_path(&db, &path, None).await.unwrap();
+        assert_eq!(unknown.workspace_id(), first.workspace_id());
+        let _ = std::fs::remove_file(&db);
+    }
+
+    #[tokio::test]
+    async fn stitch_templates_are_per_workspace() {
+        let db = std::env::temp_dir().join(format!("ailoom_store_{}_templates.db", uuid::Uuid::new_v4()));
+        let a = Store::connect_path(&db, "/ws-a", None).await.unwrap();
+        let b = Store::connect_path(&db, "/ws-b", None).await.unwrap();
+        let mut t = core::StitchTemplate {
+            name: "team".into(),
+            description: None,
+            body: "{% macro item(item) %}{{ item.comment }}{% endmacro %}".into(),
+            created_at: "2024-01-01T00:00:00Z".into(),
+            updated_at: "2024-01-01T00:00:00Z".into(),
+        };
+        a.insert_stitch_template(&t).await.unwrap();
+        assert!(a.insert_stitch_template(&t).await.is_err());
+        assert!(b.get_stitch_template("team").await.unwrap().is_none());
+
+        t.body = "changed".into();
+        assert!(a.update_stitch_template(&t).await.unwrap());
+        assert!(!b.update_stitch_template(&t).await.unwrap());
+        assert_eq!(a.list_stitch_templates().await.unwrap()[0].body, "changed");
+
+        assert!(a.delete_stitch_template("team").await.unwrap());
+        assert!(a.list_stitch_templates().await.unwrap().is_empty());
+        let _ = std::fs::remove_file(&db);
+    }
+}
@@ -0,0 +1,450 @@
+//! Versioned schema migrations.
+//!
+//! Every database carries a `schema_version` table with one row per applied step. On connect, all
+//! steps newer than the recorded version run in order inside a single `BEGIN IMMEDIATE` transaction,
+//! so a failed upgrade leaves the database untouched. Steps are written to be idempotent: databases
+//! created before versioning existed (no `schema_version` table) are upgraded from step 1.
+
+use crate::StoreError;
+use sqlx::{Row, SqliteConnection};
+
+/// Newest schema version this build understands.
+pub const SCHEMA_VERSION: i64 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+enum Step {
+    Sql(&'static str),
+    /// `ALTER TABLE .. ADD COLUMN`, skipped when the column already exists.
+    AddColumn { table: &'static str, column: &'static str, decl: &'static str },
+}
+
+struct Migration {
+    version: i64,
+    name: &'static str,
+    steps: &'static [Step],
+}
+
+// Append only: never edit a step that has shipped, add a new version instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        steps: &[
+            // Workspaces table first: referenced by annotations FK
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS workspaces (
+                    id TEXT PRIMARY KEY,
+                    key TEXT UNIQUE NOT NULL,
+                    root_path TEXT NOT NULL,
+                    created_at TEXT NOT NULL,
+                    updated_at TEXT NOT NULL
+                );
+                "#,
+            ),
+            // Annotations with explicit FK to workspaces(id)
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS annotations (
+                    id TEXT PRIMARY KEY,
+                    file_path TEXT NOT NULL,
+                    start_line INTEGER NOT NULL,
+                    end_line INTEGER NOT NULL,
+                    start_column INTEGER,
+                    end_column INTEGER,
+                    selected_text TEXT NOT NULL,
+                    comment TEXT NOT NULL,
+                    pre_context_hash TEXT,
+                    post_context_hash TEXT,
+                    file_digest TEXT,
+                    tags TEXT,
+                    priority TEXT,
+                    created_at TEXT NOT NULL,
+                    updated_at TEXT NOT NULL,
+                    workspace_id TEXT NOT NULL,
+                    FOREIGN KEY(workspace_id) REFERENCES workspaces(id)
+                        ON DELETE RESTRICT
+                        ON UPDATE CASCADE
+                );
+                "#,
+            ),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_annotations_file_path ON annotations(file_path);"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_annotations_created_at ON annotations(created_at);"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_annotations_file_span_created ON annotations(file_path, start_line, end_line, created_at);"),
+            // workspace-scoped indices
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_annotations_ws_id ON annotations(workspace_id);"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_annotations_ws_id_file ON annotations(workspace_id, file_path);"),
+            // workspace indices
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_workspaces_updated_at ON workspaces(updated_at);"),
+        ],
+    },
+    Migration {
+        version: 2,
+        name: "annotation_diff_and_status",
+        steps: &[
+            Step::AddColumn { table: "annotations", column: "diff_side", decl: "TEXT" },
+            Step::AddColumn { table: "annotations", column: "base_ref", decl: "TEXT" },
+            Step::AddColumn { table: "annotations", column: "status", decl: "TEXT NOT NULL DEFAULT 'active'" },
+            Step::AddColumn { table: "annotations", column: "resolved_at", decl: "TEXT" },
+            Step::AddColumn { table: "annotations", column: "resolved_by", decl: "TEXT" },
+            Step::AddColumn { table: "annotations", column: "resolution_note", decl: "TEXT" },
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_annotations_ws_id_status ON annotations(workspace_id, status);"),
+        ],
+    },
+    Migration {
+        version: 3,
+        name: "annotation_comments",
+        steps: &[
+            // Threaded replies; removed together with their annotation
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS annotation_comments (
+                    id TEXT PRIMARY KEY,
+                    annotation_id TEXT NOT NULL,
+                    author TEXT NOT NULL,
+                    body TEXT NOT NULL,
+                    created_at TEXT NOT NULL,
+                    updated_at TEXT NOT NULL,
+                    FOREIGN KEY(annotation_id) REFERENCES annotations(id)
+                        ON DELETE CASCADE
+                        ON UPDATE CASCADE
+                );
+                "#,
+            ),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_annotation_comments_ann_created ON annotation_comments(annotation_id, created_at);"),
+        ],
+    },
+    Migration {
+        version: 4,
+        name: "annotation_revisions",
+        steps: &[
+            // Revision log: full snapshot after each change (edits, relocations, imports, ...)
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS annotation_revisions (
+                    id TEXT PRIMARY KEY,
+                    annotation_id TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    snapshot TEXT NOT NULL,
+                    created_at TEXT NOT NULL,
+                    FOREIGN KEY(annotation_id) REFERENCES annotations(id)
+                        ON DELETE CASCADE
+                        ON UPDATE CASCADE
+                );
+                "#,
+            ),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_annotation_revisions_ann_created ON annotation_revisions(annotation_id, created_at);"),
+        ],
+    },
+    Migration {
+        version: 5,
+        name: "annotation_list_indexes",
+        steps: &[
+            // list filters / keyset sorts
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_annotations_ws_created ON annotations(workspace_id, created_at, id);"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_annotations_ws_updated ON annotations(workspace_id, updated_at, id);"),
+            // Matches the priority sort key exactly, so ORDER BY walks the index (unset priority sorts last)
+            Step::Sql(
+                "CREATE INDEX IF NOT EXISTS idx_annotations_ws_priority_key ON annotations(workspace_id, COALESCE(priority, 'P9'), created_at, id);",
+            ),
+            // Tags as rows so tag filters use an index instead of scanning JSON; kept in sync by triggers
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS annotation_tags (
+                    tag TEXT NOT NULL,
+                    annotation_id TEXT NOT NULL,
+                    PRIMARY KEY(tag, annotation_id),
+                    FOREIGN KEY(annotation_id) REFERENCES annotations(id)
+                        ON DELETE CASCADE
+                        ON UPDATE CASCADE
+                ) WITHOUT ROWID;
+                "#,
+            ),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_annotation_tags_ann ON annotation_tags(annotation_id);"),
+            Step::Sql(
+                r#"
+                CREATE TRIGGER IF NOT EXISTS annotation_tags_ai AFTER INSERT ON annotations BEGIN
+                    INSERT OR IGNORE INTO annotation_tags(tag, annotation_id)
+                    SELECT value, new.id FROM json_each(CASE WHEN json_valid(new.tags) THEN new.tags ELSE '[]' END)
+                    WHERE type = 'text';
+                END;
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TRIGGER IF NOT EXISTS annotation_tags_au AFTER UPDATE OF tags ON annotations BEGIN
+                    DELETE FROM annotation_tags WHERE annotation_id = new.id;
+                    INSERT OR IGNORE INTO annotation_tags(tag, annotation_id)
+                    SELECT value, new.id FROM json_each(CASE WHEN json_valid(new.tags) THEN new.tags ELSE '[]' END)
+                    WHERE type = 'text';
+                END;
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                INSERT OR IGNORE INTO annotation_tags(tag, annotation_id)
+                SELECT j.value, a.id FROM annotations a, json_each(CASE WHEN json_valid(a.tags) THEN a.tags ELSE '[]' END) j
+                WHERE j.type = 'text';
+                "#,
+            ),
+        ],
+    },
+    Migration {
+        version: 6,
+        name: "annotations_fts",
+        steps: &[
+            // FTS5 index over comment / selected_text / tags, kept in sync by triggers. It stores its own
+            // content under rowids from `annotation_fts_ids`: the implicit rowid of `annotations` (TEXT
+            // primary key) may be renumbered by VACUUM, an INTEGER PRIMARY KEY is not.
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS annotation_fts_ids (
+                    id INTEGER PRIMARY KEY,
+                    annotation_id TEXT NOT NULL UNIQUE
+                );
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE VIRTUAL TABLE IF NOT EXISTS annotations_fts USING fts5(
+                    comment, selected_text, tags,
+                    tokenize='unicode61 remove_diacritics 2'
+                );
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TRIGGER IF NOT EXISTS annotations_fts_ai AFTER INSERT ON annotations BEGIN
+                    INSERT INTO annotation_fts_ids(annotation_id) VALUES (new.id);
+                    INSERT INTO annotations_fts(rowid, comment, selected_text, tags)
+                    VALUES ((SELECT id FROM annotation_fts_ids WHERE annotation_id = new.id), new.comment, new.selected_text, new.tags);
+                END;
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TRIGGER IF NOT EXISTS annotations_fts_ad AFTER DELETE ON annotations BEGIN
+                    DELETE FROM annotations_fts WHERE rowid = (SELECT id FROM annotation_fts_ids WHERE annotation_id = old.id);
+                    DELETE FROM annotation_fts_ids WHERE annotation_id = old.id;
+                END;
+                "#,
+            ),
+            Step::Sql(
+                r#"
+                CREATE TRIGGER IF NOT EXISTS annotations_fts_au AFTER UPDATE OF id, comment, selected_text, tags ON annotations BEGIN
+                    UPDATE annotation_fts_ids SET annotation_id = new.id WHERE annotation_id = old.id;
+                    UPDATE annotations_fts SET comment = new.comment, selected_text = new.selected_text, tags = new.tags
+                    WHERE rowid = (SELECT id FROM annotation_fts_ids WHERE annotation_id = new.id);
+                END;
+                "#,
+            ),
+            // Index rows that predate the FTS table
+            Step::Sql("INSERT OR IGNORE INTO annotation_fts_ids(annotation_id) SELECT id FROM annotations;"),
+            Step::Sql(
+                r#"
+                INSERT INTO annotations_fts(rowid, comment, selected_text, tags)
+                SELECT m.id, a.comment, a.selected_text, a.tags FROM annotation_fts_ids m JOIN annotations a ON a.id = m.annotation_id;
+                "#,
+            ),
+        ],
+    },
+    Migration {
+        version: 7,
+        name: "workspace_identity",
+        steps: &[
+            // Stable identity (git remote + root commit, or `.ailoom/workspace-id`) surviving repo moves
+            Step::AddColumn { table: "workspaces", column: "identity", decl: "TEXT" },
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_workspaces_identity ON workspaces(identity);"),
+        ],
+    },
+    Migration {
+        version: 8,
+        name: "stitch_templates",
+        steps: &[
+            // User-defined stitch templates, named per workspace
+            Step::Sql(
+                r#"
+                CREATE TABLE IF NOT EXISTS stitch_templates (
+                    workspace_id TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    description TEXT,
+                    body TEXT NOT NULL,
+                    created_at TEXT NOT NULL,
+                    updated_at TEXT NOT NULL,
+                    PRIMARY KEY(workspace_id, name),
+                    FOREIGN KEY(workspace_id) REFERENCES workspaces(id)
+                        ON DELETE CASCADE
+                );
+                "#,
+            ),
+        ],
+    },
+];
+
+/// Bring the database up to `SCHEMA_VERSION`.
+pub(crate) async fn run(pool: &sqlx::SqlitePool) -> Result<(), StoreError> {
+    let mut conn = pool.acquire().await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER PRIMARY KEY, name TEXT NOT NULL, applied_at TEXT NOT NULL);",
+    )
+    .execute(&mut *conn)
+    .await?;
+    if current_version(&mut conn).await? >= SCHEMA_VERSION {
+        return check_not_newer(&mut conn).await;
+    }
+    // IMMEDIATE takes the write lock up front so concurrent servers upgrade one at a time
+    sqlx::query("BEGIN IMMEDIATE;").execute(&mut *conn).await?;
+    match apply_pending(&mut conn).await {
+        Ok(()) => {
+            sqlx::query("COMMIT;").execute(&mut *conn).await?;
+            Ok(())
+        }
+        Err(e) => {
+            let _ = sqlx::query("ROLLBACK;").execute(&mut *conn).await;
+            Err(e)
+        }
+    }
+}
+
+async fn apply_pending(conn: &mut SqliteConnection) -> Result<(), StoreError> {
+    // Re-read under the lock: another process may have upgraded meanwhile
+    let current = current_version(conn).await?;
+    for m in MIGRATIONS.iter().filter(|m| m.version > current) {
+        for step in m.steps {
+            match step {
+                Step::Sql(sql) => {
+                    sqlx::query(sql).execute(&mut *conn).await?;
+                }
+                Step::AddColumn { table, column, decl } => {
+                    let rows = sqlx::query(&format!("PRAGMA table_info({table})")).fetch_all(&mut *conn).await?;
+                    if !rows.iter().any(|r| r.get::<String, _>("name") == *column) {
+                        sqlx::query(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"))
+                            .execute(&mut *conn)
+                            .await?;
+                    }
+                }
+            }
+        }
+        sqlx::query(
+            "INSERT INTO schema_version (version, name, applied_at) VALUES (?1, ?2, strftime('%Y-%m-%dT%H:%M:%SZ','now'))",
+        )
+        .bind(m.version)
+        .bind(m.name)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+async fn check_not_newer(conn: &mut SqliteConnection) -> Result<(), StoreError> {
+    let found = current_version(conn).await?;
+    if found > SCHEMA_VERSION {
+        return Err(StoreError::SchemaTooNew { found, supported: SCHEMA_VERSION });
+    }
+    Ok(())
+}
+
+pub(crate) async fn current_version(conn: &mut SqliteConnection) -> Result<i64, StoreError> {
+    let v: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM schema_version")
+        .fetch_one(&mut *conn)
+        .await?;
+    Ok(v.unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Store;
+    use sqlx::sqlite::SqliteConnectOptions;
+    use sqlx::Connection;
+
+    fn temp_db(tag: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ailoom_store_{}_{}.db", uuid::Uuid::new_v4(), tag))
+    }
+
+    // Schema as shipped before versioned migrations (no schema_version table)
+    async fn create_legacy_db(path: &std::path::Path) {
+        let opts = SqliteConnectOptions::new().filename(path).create_if_missing(true);
+        let mut conn = SqliteConnection::connect_with(&opts).await.unwrap();
+        for step in MIGRATIONS[0].steps {
+            if let Step::Sql(sql) = step {
+                sqlx::query(sql).execute(&mut conn).await.unwrap();
+            }
+        }
+        sqlx::query(
+            "INSERT INTO workspaces (id, key, root_path, created_at, updated_at) VALUES ('ws1', '/legacy', '/legacy', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z')",
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"INSERT INTO annotations (id, file_path, start_line, end_line, selected_text, comment, tags, priority, created_at, updated_at, workspace_id)
+               VALUES ('a1', 'src/lib.rs', 3, 4, 'fn main()', 'legacy comment about parsing', '["perf"]', 'P1', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z', 'ws1')"#,
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        conn.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn upgrades_legacy_database_and_keeps_rows() {
+        let db = temp_db("legacy");
+        create_legacy_db(&db).await;
+
+        let store = Store::connect_path(&db, "/legacy", None).await.unwrap();
+        assert_eq!(store.schema_version().await.unwrap(), SCHEMA_VERSION);
+
+        let anns = store.list_annotations().await.unwrap();
+        assert_eq!(anns.len(), 1);
+        let a = &anns[0];
+        assert_eq!(a.id, "a1");
+        assert_eq!(a.status, ailoom_core::AnnotationStatus::Active);
+        assert_eq!(a.tags.as_deref(), Some(&["perf".to_string()][..]));
+
+        // Rows that predate the FTS table are searchable after the upgrade
+        let hits = store.search_annotations("pars", &[], &[], 10).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        // ... and their tags are backfilled into the join table
+        let f = crate::AnnotationFilter { tags: vec!["perf".into()], ..Default::default() };
+        assert_eq!(store.query_annotations(&f).await.unwrap().items.len(), 1);
+
+        // Tables added by later steps are usable
+        assert!(store.list_revisions("a1").await.unwrap().is_empty());
+        assert!(store.list_comments("a1").await.unwrap().is_empty());
+        let _ = std::fs::remove_file(&db);
+    }
+
+    #[tokio::test]
+    async fn reconnect_is_a_no_op() {
+        let db = temp_db("reconnect");
+        let store = Store::connect_path(&db, "/ws", None).await.unwrap();
+        drop(store);
+        let store = Store::connect_path(&db, "/ws", None).await.unwrap();
+        let applied: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM schema_version")
+            .fetch_one(&store.pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, MIGRATIONS.len() as i64);
+        let _ = std::fs::remove_file(&db);
+    }
+
+    #[tokio::test]
+    async fn refuses_database_from_newer_build() {
+        let db = temp_db("newer");
+        let store = Store::connect_path(&db, "/ws", None).await.unwrap();
+        sqlx::query("INSERT INTO schema_version (version, name, applied_at) VALUES (?1, 'future', '2099-01-01T00:00:00Z')")
+            .bind(SCHEMA_VERSION + 1)
+            .execute(&store.pool)
+            .await
+            .unwrap();
+        drop(store);
+        match Store::connect_path(&db, "/ws", None).await {
+            Err(StoreError::SchemaTooNew { found, supported }) => {
+                assert_eq!(found, SCHEMA_VERSION + 1);
+                assert_eq!(supported, SCHEMA_VERSION);
+            }
+            other => panic!("expected SchemaTooNew, got {:?}", other.map(|_| ())),
+        }
+        let _ = std::fs::remove_file(&db);
+    }
+}
@@ -3,7 +3,8 @@ import { http, toHttpError } from '@/lib/request'
 
 export async function listAnnotations(): Promise<Annotation[]> {
   try {
-    const res = await http.get('/api/annotations')
+    // orphaned 的位置已不可信，编辑器与列表只展示仍锚定的批注
+    const res = await http.get('/api/annotations', { params: { status: 'active,resolved' } })
     return res.data as Annotation[]
   } catch (e: any) {
     throw toHttpError(e, 'Failed to list annotations')
@@ -61,14 +62,14 @@ export async function verifyAnnotations(params: {
   filePath: string
   window?: number
   fullLimitBytes?: number
-  removeBroken?: boolean
+  markOrphaned?: boolean
 }): Promise<{
   checked: number
   updated: number
-  deleted: number
+  orphaned: number
   skipped: number
   updatedIds: string[]
-  deletedIds: string[]
+  orphanedIds: string[]
   skippedIds: string[]
 }> {
   try {
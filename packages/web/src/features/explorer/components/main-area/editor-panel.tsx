@@ -192,7 +192,7 @@ export default function EditorPanel() {
     lastVerifiedAtRef.current.set(selectedPath, Date.now())
     void (async () => {
       try {
-        await verifyAnnotations({ filePath: selectedPath, window: 40, fullLimitBytes: 5 * 1024 * 1024, removeBroken: true })
+        await verifyAnnotations({ filePath: selectedPath, window: 40, fullLimitBytes: 5 * 1024 * 1024, markOrphaned: true })
         await qc.invalidateQueries({ queryKey: ['annotations'] })
       } catch {
         // 静默失败，不影响查看体验